@@ -1,5 +1,8 @@
 use basws_client::prelude::*;
-use ncog_shared::{ncog_protocol_version, AuthenticatedUser, NcogRequest, NcogResponse};
+use ncog_shared::{
+    ncog_protocol_version, AuthenticatedUser, ConnectionRefusal, DeviceAuthorization, LoginFailure,
+    NcogRequest, NcogResponse,
+};
 
 pub type NcogClient<T> = Client<Ncog<T>>;
 
@@ -11,6 +14,8 @@ pub enum Error {
     WebBrowser(#[from] std::io::Error),
     #[error("error, message: {0:?}")]
     Generic(Option<String>),
+    #[error("login failed: {0:?}")]
+    LoginFailed(LoginFailure),
 }
 
 #[async_trait]
@@ -110,12 +115,31 @@ where
                 self.set_auth_state(AuthState::Authenticated(user), client)
                     .await
             }
+            NcogResponse::LoginFailed(failure) => {
+                self.logic
+                    .handle_error(Error::LoginFailed(failure), client)
+                    .await
+            }
+            NcogResponse::ConnectionRefused(refusal) => {
+                self.set_auth_state(AuthState::Refused(refusal), client)
+                    .await
+            }
             NcogResponse::AuthenticateAtUrl { url } => {
-                if let Err(err) = webbrowser::open(&url) {
-                    self.logic.handle_error(Error::from(err), client).await
-                } else {
-                    Ok(())
+                if webbrowser::open(&url).is_err() {
+                    // Dedicated servers, consoles and SSH sessions have no
+                    // browser to open, so let the user log in elsewhere.
+                    client
+                        .request(NcogRequest::RequestDeviceAuthorization)
+                        .await?;
                 }
+                Ok(())
+            }
+            NcogResponse::DeviceAuthorization(authorization) => {
+                self.set_auth_state(
+                    AuthState::AwaitingDeviceAuthorization(authorization),
+                    client,
+                )
+                .await
             }
             unhandled => {
                 self.logic
@@ -169,8 +193,14 @@ where
 pub enum AuthState {
     LoggedOut,
     Connected,
+    /// The user needs to enter the code at the verification uri to finish
+    /// logging in.
+    AwaitingDeviceAuthorization(DeviceAuthorization),
     Authenticated(AuthenticatedUser),
-    Error { message: Option<String> },
+    Refused(ConnectionRefusal),
+    Error {
+        message: Option<String>,
+    },
 }
 
 impl Default for AuthState {
@@ -181,6 +211,12 @@ impl Default for AuthState {
 
 impl AuthState {
     pub fn is_connected(&self) -> bool {
-        matches!(self, AuthState::Connected) || matches!(self, AuthState::Authenticated(_))
+        matches!(
+            self,
+            AuthState::Connected
+                | AuthState::AwaitingDeviceAuthorization(_)
+                | AuthState::Authenticated(_)
+                | AuthState::Refused(_)
+        )
     }
 }
@@ -4,7 +4,25 @@ mod migration_0003_prevent_timelord_changes;
 mod migration_0004_twitch;
 mod migration_0005_basws;
 mod migration_0006_collations;
+mod migration_0007_role_inclusions;
+mod migration_0008_string_resource_ids;
+mod migration_0009_iam_audit_log;
+mod migration_0010_account_suspensions;
+mod migration_0011_external_identities;
+mod migration_0012_oauth_states;
+mod migration_0013_identity_links;
+mod migration_0014_account_merges;
+mod migration_0015_oauth_token_lifecycle;
+mod migration_0016_encryption_key_ids;
+mod migration_0017_jwt_signing_keys;
+mod migration_0018_openid_connect;
+mod migration_0019_application_registry;
+mod migration_0020_device_authorizations;
+mod migration_0021_identity_verification_tokens;
+mod migration_0022_oauth_token_identities;
+mod migration_0023_pending_signing_keys;
 use crate::connection::pg;
+use sqlx::PgPool;
 use sqlx_simple_migrator::{Migration, MigrationError};
 
 const JONS_ACCOUNT_ID: i64 = 1;
@@ -20,11 +38,34 @@ pub fn migrations() -> Vec<Migration> {
         migration_0004_twitch::migration(),
         migration_0005_basws::migration(),
         migration_0006_collations::migration(),
+        migration_0007_role_inclusions::migration(),
+        migration_0008_string_resource_ids::migration(),
+        migration_0009_iam_audit_log::migration(),
+        migration_0010_account_suspensions::migration(),
+        migration_0011_external_identities::migration(),
+        migration_0012_oauth_states::migration(),
+        migration_0013_identity_links::migration(),
+        migration_0014_account_merges::migration(),
+        migration_0015_oauth_token_lifecycle::migration(),
+        migration_0016_encryption_key_ids::migration(),
+        migration_0017_jwt_signing_keys::migration(),
+        migration_0018_openid_connect::migration(),
+        migration_0019_application_registry::migration(),
+        migration_0020_device_authorizations::migration(),
+        migration_0021_identity_verification_tokens::migration(),
+        migration_0022_oauth_token_identities::migration(),
+        migration_0023_pending_signing_keys::migration(),
     ]
 }
 
 pub async fn run_all() -> Result<(), MigrationError> {
     let pool = pg();
 
-    Migration::run_all(&pool, migrations()).await
+    run_all_on(&pool).await
+}
+
+/// Runs the migrations on `pool` instead of the shared pool, for callers that
+/// manage their own connections.
+pub async fn run_all_on(pool: &PgPool) -> Result<(), MigrationError> {
+    Migration::run_all(pool, migrations()).await
 }
@@ -0,0 +1,16 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0007")
+        .with_up(
+            r#"
+        CREATE TABLE role_inclusions (
+            role_id BIGINT NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+            included_role_id BIGINT NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+            PRIMARY KEY (role_id, included_role_id),
+            CHECK (role_id <> included_role_id)
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS role_inclusions")
+}
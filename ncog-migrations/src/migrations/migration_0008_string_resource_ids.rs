@@ -0,0 +1,11 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0008")
+        .with_up(
+            "ALTER TABLE role_permission_statements ALTER COLUMN resource_id TYPE TEXT USING resource_id::TEXT",
+        )
+        .with_down(
+            "ALTER TABLE role_permission_statements ALTER COLUMN resource_id TYPE BIGINT USING resource_id::BIGINT",
+        )
+}
@@ -0,0 +1,38 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0009")
+        .with_up(
+            r#"
+        CREATE TABLE iam_audit_log (
+            id BIGSERIAL PRIMARY KEY,
+            account_id BIGINT NULL,
+            installation_id UUID NULL,
+            request_kind TEXT NOT NULL,
+            before JSONB NULL,
+            after JSONB NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS iam_audit_log")
+        .with_up("CREATE INDEX iam_audit_log_account_id ON iam_audit_log(account_id)")
+        .with_up("CREATE INDEX iam_audit_log_created_at ON iam_audit_log(created_at)")
+        .with_up(
+            r#"
+        CREATE FUNCTION iam_audit_log_append_only() RETURNS TRIGGER AS $$
+        BEGIN
+            RAISE EXCEPTION 'iam_audit_log is append-only';
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+        )
+        .with_down("DROP FUNCTION IF EXISTS iam_audit_log_append_only")
+        .with_up(
+            r#"
+        CREATE TRIGGER iam_audit_log_append_only BEFORE UPDATE OR DELETE ON iam_audit_log
+            FOR EACH ROW EXECUTE PROCEDURE iam_audit_log_append_only()
+        "#,
+        )
+        .with_down("DROP TRIGGER IF EXISTS iam_audit_log_append_only ON iam_audit_log")
+}
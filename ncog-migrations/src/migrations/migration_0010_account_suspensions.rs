@@ -0,0 +1,11 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0010")
+        .with_up("ALTER TABLE accounts ADD COLUMN suspended_at TIMESTAMPTZ NULL")
+        .with_down("ALTER TABLE accounts DROP COLUMN IF EXISTS suspended_at")
+        .with_up("ALTER TABLE accounts ADD COLUMN suspended_until TIMESTAMPTZ NULL")
+        .with_down("ALTER TABLE accounts DROP COLUMN IF EXISTS suspended_until")
+        .with_up("ALTER TABLE accounts ADD COLUMN suspension_reason TEXT NULL")
+        .with_down("ALTER TABLE accounts DROP COLUMN IF EXISTS suspension_reason")
+}
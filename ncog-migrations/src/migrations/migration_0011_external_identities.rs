@@ -0,0 +1,42 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0011")
+        .with_up(
+            r#"
+        CREATE TABLE external_identities (
+            provider TEXT NOT NULL,
+            external_id TEXT NOT NULL,
+            account_id BIGINT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+            login TEXT NULL,
+            display_name TEXT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (provider, external_id)
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS external_identities")
+        .with_up("CREATE INDEX external_identities_account_id ON external_identities(account_id)")
+        .with_up(
+            r#"
+        INSERT INTO external_identities (provider, external_id, account_id, login, display_name)
+            SELECT 'twitch', id, account_id, username, username FROM twitch_profiles
+        "#,
+        )
+        .with_down(
+            r#"
+        INSERT INTO twitch_profiles (id, account_id, username)
+            SELECT external_id, account_id, COALESCE(display_name, login, '') FROM external_identities WHERE provider = 'twitch'
+        "#,
+        )
+        .with_up("DROP TABLE twitch_profiles")
+        .with_down(
+            r#"
+        CREATE TABLE twitch_profiles (
+            id TEXT PRIMARY KEY,
+            account_id BIGINT NOT NULL REFERENCES accounts(id),
+            username TEXT NOT NULL
+        )
+        "#,
+        )
+}
@@ -0,0 +1,19 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0012")
+        .with_up(
+            r#"
+        CREATE TABLE oauth_states (
+            state TEXT PRIMARY KEY,
+            installation_id UUID NOT NULL REFERENCES installations(id) ON DELETE CASCADE,
+            provider TEXT NOT NULL,
+            nonce TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            expires_at TIMESTAMPTZ NOT NULL
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS oauth_states")
+        .with_up("CREATE INDEX oauth_states_expires_at ON oauth_states(expires_at)")
+}
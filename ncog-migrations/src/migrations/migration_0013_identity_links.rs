@@ -0,0 +1,9 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0013")
+        .with_up(
+            "ALTER TABLE oauth_states ADD COLUMN link_account_id BIGINT NULL REFERENCES accounts(id) ON DELETE CASCADE",
+        )
+        .with_down("ALTER TABLE oauth_states DROP COLUMN IF EXISTS link_account_id")
+}
@@ -0,0 +1,9 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0014")
+        .with_up(
+            "ALTER TABLE accounts ADD COLUMN merged_into_id BIGINT NULL REFERENCES accounts(id)",
+        )
+        .with_down("ALTER TABLE accounts DROP COLUMN IF EXISTS merged_into_id")
+}
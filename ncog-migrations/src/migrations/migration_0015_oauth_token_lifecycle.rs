@@ -0,0 +1,9 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0015")
+        .with_up("ALTER TABLE oauth_tokens ADD COLUMN invalidated_at TIMESTAMPTZ NULL")
+        .with_down("ALTER TABLE oauth_tokens DROP COLUMN IF EXISTS invalidated_at")
+        .with_up("CREATE INDEX oauth_tokens_expires ON oauth_tokens(expires) WHERE invalidated_at IS NULL")
+        .with_down("DROP INDEX IF EXISTS oauth_tokens_expires")
+}
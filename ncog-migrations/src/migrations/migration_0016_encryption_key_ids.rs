@@ -0,0 +1,11 @@
+use sqlx_simple_migrator::Migration;
+
+// A NULL key id marks a value stored in plaintext before encryption was
+// introduced. `ncog-server reencrypt` encrypts those rows.
+pub fn migration() -> Migration {
+    Migration::new("0016")
+        .with_up("ALTER TABLE oauth_tokens ADD COLUMN encryption_key_id TEXT NULL")
+        .with_down("ALTER TABLE oauth_tokens DROP COLUMN IF EXISTS encryption_key_id")
+        .with_up("ALTER TABLE installations ADD COLUMN encryption_key_id TEXT NULL")
+        .with_down("ALTER TABLE installations DROP COLUMN IF EXISTS encryption_key_id")
+}
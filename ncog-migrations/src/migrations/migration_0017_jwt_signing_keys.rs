@@ -0,0 +1,19 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0017")
+        .with_up(
+            r#"
+        CREATE TABLE jwt_signing_keys (
+            id TEXT PRIMARY KEY,
+            private_key BYTEA NOT NULL,
+            encryption_key_id TEXT NOT NULL,
+            rsa_n TEXT NOT NULL,
+            rsa_e TEXT NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            retired_at TIMESTAMPTZ NULL
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS jwt_signing_keys")
+}
@@ -0,0 +1,70 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0018")
+        .with_up(
+            r#"
+        CREATE TABLE applications (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            client_secret_hash BYTEA NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS applications")
+        .with_up(
+            r#"
+        CREATE TABLE application_redirect_uris (
+            application_id TEXT NOT NULL REFERENCES applications(id) ON DELETE CASCADE,
+            redirect_uri TEXT NOT NULL,
+            PRIMARY KEY (application_id, redirect_uri)
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS application_redirect_uris")
+        .with_up(
+            r#"
+        CREATE TABLE oidc_authorization_requests (
+            id TEXT PRIMARY KEY,
+            application_id TEXT NOT NULL REFERENCES applications(id) ON DELETE CASCADE,
+            redirect_uri TEXT NOT NULL,
+            scope TEXT NOT NULL,
+            state TEXT NULL,
+            nonce TEXT NULL,
+            code_challenge TEXT NOT NULL,
+            installation_id UUID NULL REFERENCES installations(id) ON DELETE SET NULL,
+            expires_at TIMESTAMPTZ NOT NULL
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS oidc_authorization_requests")
+        .with_up(
+            r#"
+        CREATE TABLE oidc_authorization_codes (
+            code_hash BYTEA PRIMARY KEY,
+            application_id TEXT NOT NULL REFERENCES applications(id) ON DELETE CASCADE,
+            account_id BIGINT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+            redirect_uri TEXT NOT NULL,
+            scope TEXT NOT NULL,
+            nonce TEXT NULL,
+            code_challenge TEXT NOT NULL,
+            auth_time TIMESTAMPTZ NOT NULL DEFAULT now(),
+            expires_at TIMESTAMPTZ NOT NULL
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS oidc_authorization_codes")
+        .with_up(
+            r#"
+        CREATE TABLE oidc_access_tokens (
+            token_hash BYTEA PRIMARY KEY,
+            application_id TEXT NOT NULL REFERENCES applications(id) ON DELETE CASCADE,
+            account_id BIGINT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+            scope TEXT NOT NULL,
+            expires_at TIMESTAMPTZ NOT NULL
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS oidc_access_tokens")
+}
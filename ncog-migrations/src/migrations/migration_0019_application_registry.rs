@@ -0,0 +1,18 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0019")
+        .with_up(
+            "ALTER TABLE applications ADD COLUMN owner_account_id BIGINT NULL REFERENCES accounts(id) ON DELETE SET NULL",
+        )
+        .with_down("ALTER TABLE applications DROP COLUMN IF EXISTS owner_account_id")
+        .with_up(
+            r#"
+        CREATE TABLE application_audiences (
+            audience TEXT PRIMARY KEY,
+            application_id TEXT NOT NULL REFERENCES applications(id) ON DELETE CASCADE
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS application_audiences")
+}
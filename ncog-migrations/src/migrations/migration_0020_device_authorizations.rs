@@ -0,0 +1,16 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0020")
+        .with_up(
+            r#"
+        CREATE TABLE device_authorizations (
+            user_code TEXT PRIMARY KEY,
+            installation_id UUID NOT NULL UNIQUE REFERENCES installations(id) ON DELETE CASCADE,
+            expires_at TIMESTAMPTZ NOT NULL,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS device_authorizations")
+}
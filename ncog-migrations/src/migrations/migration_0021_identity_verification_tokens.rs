@@ -0,0 +1,25 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0021")
+        .with_up(
+            r#"
+        CREATE TABLE identity_verification_tokens (
+            jwt_id TEXT PRIMARY KEY,
+            audience TEXT NOT NULL,
+            nonce BYTEA NOT NULL,
+            account_id BIGINT NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+            installation_id UUID NULL REFERENCES installations(id) ON DELETE SET NULL,
+            issued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            expires_at TIMESTAMPTZ NOT NULL,
+            revoked_at TIMESTAMPTZ NULL,
+            UNIQUE (audience, nonce)
+        )
+        "#,
+        )
+        .with_down("DROP TABLE IF EXISTS identity_verification_tokens")
+        .with_up(
+            "CREATE INDEX identity_verification_tokens_account_id ON identity_verification_tokens(account_id, issued_at)",
+        )
+        .with_down("DROP INDEX IF EXISTS identity_verification_tokens_account_id")
+}
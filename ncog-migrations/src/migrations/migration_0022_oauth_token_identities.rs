@@ -0,0 +1,34 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0022")
+        .with_up("ALTER TABLE oauth_tokens ADD COLUMN external_id TEXT NULL")
+        .with_down("ALTER TABLE oauth_tokens DROP COLUMN IF EXISTS external_id")
+        // Existing tokens were written by whichever identity logged in last,
+        // which is most likely the most recently linked one.
+        .with_up(
+            r#"
+        UPDATE oauth_tokens SET external_id = (
+            SELECT external_id FROM external_identities
+                WHERE external_identities.account_id = oauth_tokens.account_id
+                    AND external_identities.provider = oauth_tokens.service
+                ORDER BY created_at DESC
+                LIMIT 1
+        )
+        "#,
+        )
+        .with_up("DELETE FROM oauth_tokens WHERE external_id IS NULL")
+        .with_up("ALTER TABLE oauth_tokens ALTER COLUMN external_id SET NOT NULL")
+        .with_up("ALTER TABLE oauth_tokens DROP CONSTRAINT oauth_tokens_pkey")
+        .with_up("ALTER TABLE oauth_tokens ADD PRIMARY KEY (service, account_id, external_id)")
+        .with_down("ALTER TABLE oauth_tokens ADD PRIMARY KEY (service, account_id)")
+        .with_down(
+            r#"
+        DELETE FROM oauth_tokens WHERE ctid NOT IN (
+            SELECT DISTINCT ON (service, account_id) ctid FROM oauth_tokens
+                ORDER BY service, account_id, expires DESC NULLS LAST
+        )
+        "#,
+        )
+        .with_down("ALTER TABLE oauth_tokens DROP CONSTRAINT oauth_tokens_pkey")
+}
@@ -0,0 +1,10 @@
+use sqlx_simple_migrator::Migration;
+
+pub fn migration() -> Migration {
+    Migration::new("0023")
+        // Existing keys are already published, so they can sign right away.
+        .with_up(
+            "ALTER TABLE jwt_signing_keys ADD COLUMN activates_at TIMESTAMPTZ NOT NULL DEFAULT now()",
+        )
+        .with_down("ALTER TABLE jwt_signing_keys DROP COLUMN IF EXISTS activates_at")
+}
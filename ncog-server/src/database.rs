@@ -1,16 +1,24 @@
 use basws_server::prelude::InstallationConfig;
 use ncog_shared::{
-    iam::{PermissionStatement, Role, RoleSummary, User},
+    iam::{
+        self, ApplicationSort, ApplicationSummary, AuditLogEntry, AuditLogQuery, ListPage,
+        ListQuery, PermissionStatement, Role, RoleSort, RoleSummary, User, UserSort,
+    },
     permissions::{PermissionSet, Statement},
-    Installation, UserProfile,
+    AccountSuspension, Installation, LinkedIdentity, RevokedIdentityToken, UserProfile,
 };
 use uuid::Uuid;
 
+use crate::encryption::{self, EncryptionError};
 use ncog_migrations::{pg, sqlx};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use sqlx::executor::RefExecutor;
-use sqlx::{postgres::Postgres, prelude::*};
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    prelude::*,
+};
+use std::{fmt::Display, str::FromStr};
 
 pub async fn get_profile_by_installation_id<'e, E>(
     executor: E,
@@ -54,20 +62,26 @@ where
 
 pub async fn lookup_or_create_installation(
     installation_id: Option<Uuid>,
-) -> Result<Installation, sqlx::Error>
-{
+) -> Result<Installation, sqlx::Error> {
     if let Some(installation_id) = installation_id {
-        match sqlx::query_as!(
-            Installation,
-            "SELECT id, account_id, nonce, private_key FROM installations WHERE id = $1",
+        match sqlx::query!(
+            "SELECT id, account_id, nonce, private_key, encryption_key_id FROM installations WHERE id = $1",
             installation_id
         )
         .fetch_one(&pg())
-        .await {
-            Ok(installation) => if installation.private_key.is_some() {
-                return Ok(installation);
-            },
-            Err(sqlx::Error::RowNotFound) => {},
+        .await
+        {
+            Ok(row) => {
+                if let Some(private_key) = row.private_key {
+                    return Ok(Installation {
+                        id: row.id,
+                        account_id: row.account_id,
+                        nonce: row.nonce,
+                        private_key: Some(decrypt_bytes(&row.encryption_key_id, private_key)?),
+                    });
+                }
+            }
+            Err(sqlx::Error::RowNotFound) => {}
             Err(err) => return Err(err),
         }
     }
@@ -75,21 +89,28 @@ pub async fn lookup_or_create_installation(
     create_installation(&pg()).await
 }
 
-async fn create_installation<'e, E>(
-    executor: E,
-) -> Result<Installation, sqlx::Error>
+async fn create_installation<'e, E>(executor: E) -> Result<Installation, sqlx::Error>
 where
     E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
     println!("Creating installation");
     let default_config = InstallationConfig::default();
-    sqlx::query_as!(
-        Installation,
-        "INSERT INTO installations (id, private_key) VALUES ($1, $2) RETURNING id, account_id, nonce, private_key",
-        default_config.id, Vec::from(default_config.private_key)
+    let private_key = encryption::seal(&default_config.private_key)?;
+    sqlx::query!(
+        "INSERT INTO installations (id, private_key, encryption_key_id) VALUES ($1, $2, $3) RETURNING id",
+        default_config.id,
+        private_key,
+        encryption::current_key_id()
     )
     .fetch_one(executor)
-    .await
+    .await?;
+
+    Ok(Installation {
+        id: default_config.id,
+        account_id: None,
+        nonce: None,
+        private_key: Some(Vec::from(default_config.private_key)),
+    })
 }
 
 pub async fn set_installation_nonce<'e, E>(
@@ -128,236 +149,2638 @@ where
     Ok(())
 }
 
-pub async fn load_permissions_for<'e, E>(
+pub async fn get_account_id_by_external_identity<'e, E>(
     executor: E,
-    account_id: i64,
-) -> Result<PermissionSet, sqlx::Error>
+    provider: &str,
+    external_id: &str,
+) -> Result<Option<i64>, sqlx::Error>
 where
     E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
-    let results  = sqlx::query_as!(Statement, r#"SELECT roles.id as role_id, service, resource_type, resource_id, action, allow FROM role_permission_statements 
-            LEFT OUTER JOIN roles ON role_permission_statements.role_id = roles.id
-            LEFT OUTER JOIN account_roles ON account_roles.role_id = roles.id
-            WHERE 
-                (role_permission_statements.role_id IS NULL OR account_roles.account_id = $1)
-        "#, account_id).fetch_all(executor).await?;
-
-    Ok(results.into())
+    match sqlx::query!(
+        "SELECT account_id FROM external_identities WHERE provider = $1 AND external_id = $2",
+        provider,
+        external_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(row) => Ok(Some(row.account_id)),
+        Err(sqlx::Error::RowNotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
 }
 
-pub async fn iam_list_users<'e, E>(executor: E) -> Result<Vec<User>, sqlx::Error>
+pub async fn create_account<'e, E>(
+    executor: E,
+    login: &str,
+    display_name: &str,
+) -> Result<i64, sqlx::Error>
 where
     E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
-    let mut users: Vec<User> = Vec::new();
+    let id = sqlx::query!(
+        "INSERT INTO accounts (login, display_name) VALUES ($1, $2) RETURNING id",
+        login,
+        display_name
+    )
+    .fetch_one(executor)
+    .await?
+    .id;
+    Ok(id)
+}
 
-    // TODO https://github.com/launchbadge/sqlx/issues/367 Once this is shipping, we can switch this to strongly typed query again
-    let mut user_rows = sqlx::query(r#"SELECT accounts.id, screenname, created_at, roles.id as role_id, roles.name as role_name FROM accounts 
-            LEFT OUTER JOIN account_roles ON account_roles.account_id = accounts.id
-            LEFT OUTER JOIN roles ON roles.id = account_roles.role_id ORDER BY accounts.id"#).fetch(executor);
-    while let Some(row) = user_rows.next().await? {
-        let id = row.get::<Option<i64>, _>(0);
-        if users.is_empty() || users[users.len() - 1].id != id {
-            users.push(User {
-                id,
-                screenname: row.get::<Option<String>, _>(1),
-                created_at: row.get::<DateTime<Utc>, _>(2),
-                roles: Vec::new(),
-            });
-        }
+/// Accounts take their names from the first identity they log in with.
+pub async fn set_account_names_if_unset<E>(
+    executor: E,
+    account_id: i64,
+    login: &str,
+    display_name: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE accounts SET login = $2, display_name = $3 WHERE id = $1 AND login IS NULL",
+        account_id,
+        login,
+        display_name
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
 
-        if let Some(role_id) = row.get::<Option<i64>, _>(3) {
-            let role_name = row.get::<String, _>(4);
-            let users_count = users.len();
-            users
-                .get_mut(users_count - 1)
-                .unwrap()
-                .roles
-                .push(RoleSummary {
-                    id: Some(role_id),
-                    name: role_name,
-                });
-        }
-    }
+pub async fn upsert_external_identity<E>(
+    executor: E,
+    provider: &str,
+    external_id: &str,
+    account_id: i64,
+    login: &str,
+    display_name: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"INSERT INTO external_identities (provider, external_id, account_id, login, display_name) VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (provider, external_id) DO UPDATE SET account_id = $3, login = $4, display_name = $5"#,
+        provider,
+        external_id,
+        account_id,
+        login,
+        display_name
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
 
-    Ok(users)
+pub async fn list_external_identities<'e, E>(
+    executor: E,
+    account_id: i64,
+) -> Result<Vec<LinkedIdentity>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    sqlx::query_as!(
+        LinkedIdentity,
+        "SELECT provider, external_id, login, display_name, created_at AS linked_at FROM external_identities WHERE account_id = $1 ORDER BY created_at, provider",
+        account_id
+    )
+    .fetch_all(executor)
+    .await
 }
 
-pub async fn iam_get_user<'e, E>(executor: E, account_id: i64) -> Result<Option<User>, sqlx::Error>
+/// Serializes changes to an account's identities, which prevents concurrent
+/// unlinks from removing every identity.
+pub async fn lock_account<'e, E>(executor: E, account_id: i64) -> Result<(), sqlx::Error>
 where
     E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
-    let mut user = None;
+    sqlx::query!(
+        "SELECT id FROM accounts WHERE id = $1 FOR UPDATE",
+        account_id
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(())
+}
 
-    // TODO https://github.com/launchbadge/sqlx/issues/367 Once this is shipping, we can switch this to strongly typed query again
-    let mut user_rows = sqlx::query(r#"SELECT accounts.id, screenname, created_at, roles.id as role_id, roles.name as role_name FROM accounts 
-            LEFT OUTER JOIN account_roles ON account_roles.account_id = accounts.id
-            LEFT OUTER JOIN roles ON roles.id = account_roles.role_id WHERE accounts.id = $1 ORDER BY accounts.id"#).bind(&account_id).fetch(executor);
-    while let Some(row) = user_rows.next().await? {
-        user = Some(match user {
-            Some(user) => user,
-            None => User {
-                id: row.get::<Option<i64>, _>(0),
-                screenname: row.get::<Option<String>, _>(1),
-                created_at: row.get::<DateTime<Utc>, _>(2),
-                roles: Vec::new(),
-            },
-        });
+/// Removes the identity from the account. Returns false if the identity isn't
+/// linked to the account.
+pub async fn delete_external_identity<E>(
+    executor: E,
+    account_id: i64,
+    provider: &str,
+    external_id: &str,
+) -> Result<bool, sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let deleted = sqlx::query!(
+        "DELETE FROM external_identities WHERE account_id = $1 AND provider = $2 AND external_id = $3",
+        account_id,
+        provider,
+        external_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(deleted > 0)
+}
 
-        if let Some(role_id) = row.get::<Option<i64>, _>(3) {
-            let role_name = row.get::<String, _>(4);
-            user.as_mut().unwrap().roles.push(RoleSummary {
-                id: Some(role_id),
-                name: role_name,
-            });
-        }
+/// An OAuth token issued to ncog on behalf of one of an account's external
+/// identities.
+#[derive(Debug, Clone)]
+pub struct StoredOAuthToken {
+    pub account_id: i64,
+    pub service: String,
+    pub external_id: String,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires: Option<NaiveDateTime>,
+    /// The encrypted access token as stored, used to detect concurrent updates.
+    stored_access_token: String,
+}
+
+/// An `oauth_tokens` row as stored, before its tokens are decrypted.
+struct OAuthTokenRow {
+    account_id: i64,
+    service: String,
+    external_id: String,
+    access_token: String,
+    refresh_token: Option<String>,
+    expires: Option<NaiveDateTime>,
+    encryption_key_id: Option<String>,
+}
+
+fn stored_oauth_token(row: OAuthTokenRow) -> Result<StoredOAuthToken, EncryptionError> {
+    let key_id = row.encryption_key_id;
+    Ok(StoredOAuthToken {
+        account_id: row.account_id,
+        service: row.service,
+        external_id: row.external_id,
+        access_token: decrypt_text(&key_id, &row.access_token)?,
+        refresh_token: row
+            .refresh_token
+            .map(|token| decrypt_text(&key_id, &token))
+            .transpose()?,
+        expires: row.expires,
+        stored_access_token: row.access_token,
+    })
+}
+
+/// Decrypts a text column, passing through values stored before encryption.
+fn decrypt_text(key_id: &Option<String>, value: &str) -> Result<String, EncryptionError> {
+    match key_id {
+        Some(key_id) => encryption::open_str(key_id, value),
+        None => Ok(value.to_owned()),
     }
+}
 
-    Ok(user)
+fn decrypt_bytes(key_id: &Option<String>, value: Vec<u8>) -> Result<Vec<u8>, EncryptionError> {
+    match key_id {
+        Some(key_id) => encryption::open(key_id, &value),
+        None => Ok(value),
+    }
 }
 
-pub async fn iam_list_roles<'e, E>(executor: E) -> Result<Vec<RoleSummary>, sqlx::Error>
+/// Deletes the token issued for an identity that was unlinked from the
+/// account, returning it so that it can be revoked.
+pub async fn take_identity_oauth_token<'e, E>(
+    executor: E,
+    account_id: i64,
+    provider: &str,
+    external_id: &str,
+) -> Result<Option<StoredOAuthToken>, sqlx::Error>
 where
     E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
-    sqlx::query_as!(RoleSummary, "SELECT id, name FROM roles")
-        .fetch_all(executor)
-        .await
+    let rows = sqlx::query_as!(
+        OAuthTokenRow,
+        r#"DELETE FROM oauth_tokens WHERE account_id = $1 AND service = $2 AND external_id = $3
+            RETURNING account_id, service, external_id, access_token, refresh_token, expires, encryption_key_id"#,
+        account_id,
+        provider,
+        external_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(stored_oauth_token)
+        .next()
+        .transpose()?)
 }
 
-pub async fn iam_get_role<'e, E>(executor: E, role_id: i64) -> Result<Option<Role>, sqlx::Error>
+/// Deletes the source account's tokens for identities the target account
+/// already has a token for, returning them so that they can be revoked.
+pub async fn take_conflicting_oauth_tokens<'e, E>(
+    executor: E,
+    source_account_id: i64,
+    target_account_id: i64,
+) -> Result<Vec<StoredOAuthToken>, sqlx::Error>
 where
-    E: Copy + 'e + Send + RefExecutor<'e, Database = Postgres>,
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
-    let summary = match sqlx::query_as!(
-        RoleSummary,
-        "SELECT id, name FROM roles WHERE id = $1",
-        role_id
-    )
-    .fetch_one(executor)
-    .await
-    {
-        Ok(role) => role,
-        Err(err) => match err {
-            sqlx::Error::RowNotFound => return Ok(None),
-            _ => return Err(err),
-        },
-    };
-
-    let permission_statements = sqlx::query_as!(
-        PermissionStatement,
-        r#"SELECT *
-        FROM role_permission_statements
-        WHERE role_id = $1
-        ORDER BY id"#,
-        role_id
+    let rows = sqlx::query_as!(
+        OAuthTokenRow,
+        r#"DELETE FROM oauth_tokens WHERE account_id = $1
+            AND (service, external_id) IN (SELECT service, external_id FROM oauth_tokens WHERE account_id = $2)
+            RETURNING account_id, service, external_id, access_token, refresh_token, expires, encryption_key_id"#,
+        source_account_id,
+        target_account_id
     )
     .fetch_all(executor)
     .await?;
-
-    Ok(Some(Role {
-        id: summary.id,
-        name: summary.name,
-        permission_statements,
-    }))
+    Ok(rows
+        .into_iter()
+        .map(stored_oauth_token)
+        .collect::<Result<_, _>>()?)
 }
 
-pub async fn iam_update_role<'e, E>(executor: E, role: &RoleSummary) -> Result<i64, sqlx::Error>
+/// Lists the valid tokens that can be refreshed and expire before
+/// `expires_before`.
+pub async fn list_expiring_oauth_tokens<'e, E>(
+    executor: E,
+    expires_before: NaiveDateTime,
+) -> Result<Vec<StoredOAuthToken>, sqlx::Error>
 where
     E: 'e + Send + RefExecutor<'e, Database = Postgres>,
 {
-    let id = sqlx::query!(
-        r#"INSERT INTO roles (id, name) VALUES (COALESCE($1, (SELECT nextval('roles_id_seq'))), $2) 
-            ON CONFLICT (id) DO UPDATE SET name = $2
-            RETURNING id"#,
-        role.id,
-        &role.name
+    let rows = sqlx::query_as!(
+        OAuthTokenRow,
+        r#"SELECT account_id, service, external_id, access_token, refresh_token, expires, encryption_key_id FROM oauth_tokens
+            WHERE invalidated_at IS NULL AND refresh_token IS NOT NULL AND expires < $1
+            ORDER BY expires"#,
+        expires_before
     )
-    .fetch_one(executor)
-    .await?
-    .id;
-
-    Ok(id)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(stored_oauth_token)
+        .collect::<Result<_, _>>()?)
 }
 
-
-pub async fn iam_delete_role<E>(
+/// Replaces a token after it was refreshed. Nothing is updated if the token
+/// was replaced or removed while it was being refreshed.
+pub async fn update_refreshed_oauth_token<E>(
     executor: E,
-    id: i64
+    previous: &StoredOAuthToken,
+    access_token: &str,
+    refresh_token: Option<&String>,
+    expires: Option<NaiveDateTime>,
 ) -> Result<(), sqlx::Error>
 where
     E: Send + Executor<Database = Postgres>,
 {
+    let access_token = encryption::seal_str(access_token)?;
+    let refresh_token = refresh_token
+        .map(|token| encryption::seal_str(token))
+        .transpose()?;
     sqlx::query!(
-        "DELETE FROM roles WHERE id=$1", id)
-        .execute(executor).await?;
-
+        r#"UPDATE oauth_tokens SET access_token = $4, refresh_token = $5, expires = $6, encryption_key_id = $7
+            WHERE account_id = $1 AND service = $2 AND access_token = $3 AND external_id = $8"#,
+        previous.account_id,
+        &previous.service,
+        &previous.stored_access_token,
+        access_token,
+        refresh_token,
+        expires,
+        encryption::current_key_id(),
+        &previous.external_id
+    )
+    .execute(executor)
+    .await?;
     Ok(())
 }
 
-pub async fn iam_get_permission_statement<'e, E>(
+pub async fn invalidate_oauth_token<E>(
     executor: E,
-    permission_statement_id: i64,
-) -> Result<PermissionStatement, sqlx::Error>
+    token: &StoredOAuthToken,
+) -> Result<(), sqlx::Error>
 where
-    E: Copy + 'e + Send + RefExecutor<'e, Database = Postgres>,
+    E: Send + Executor<Database = Postgres>,
 {
-    sqlx::query_as!(
-        PermissionStatement,
-        r#"SELECT *
-        FROM role_permission_statements
-        WHERE id = $1"#,
-        permission_statement_id
+    sqlx::query!(
+        "UPDATE oauth_tokens SET invalidated_at = now() WHERE account_id = $1 AND service = $2 AND external_id = $3 AND access_token = $4",
+        token.account_id,
+        &token.service,
+        &token.external_id,
+        &token.stored_access_token
     )
-    .fetch_one(executor)
-    .await
+    .execute(executor)
+    .await?;
+    Ok(())
 }
 
-pub async fn iam_update_permission_statement<'e, E>(
-    executor: E,
-    statement: &PermissionStatement,
-) -> Result<i64, sqlx::Error>
+/// Marks expired tokens that can't be refreshed as invalid, returning how
+/// many were invalidated. `expires` is stored in UTC.
+pub async fn invalidate_expired_oauth_tokens<E>(executor: E) -> Result<u64, sqlx::Error>
 where
-    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+    E: Send + Executor<Database = Postgres>,
 {
-    let id = sqlx::query(
-        r#"INSERT INTO role_permission_statements (
-                id, 
-                role_id, 
-                service, 
-                resource_type, 
-                resource_id, 
-                action, 
-                allow, 
-                comment
-            ) VALUES (COALESCE($1, (SELECT nextval('role_permission_statements_id_seq'))), $2, $3, $4, $5, $6, $7, $8) 
-            ON CONFLICT (id) DO UPDATE SET role_id = $2, service = $3, resource_type = $4, resource_id = $5, action = $6, allow = $7, comment = $8
-            RETURNING id"#)
-        .bind(statement.id)
-        .bind(statement.role_id)
-        .bind(&statement.service)
-        .bind(&statement.resource_type)
-        .bind(statement.resource_id)
-        .bind(&statement.action)
-        .bind(statement.allow)
-        .bind(&statement.comment)
-    
-    .fetch(executor).next().await?.expect("Error saving permission statement").get::<i64,_>(0);
-
-    Ok(id)
+    sqlx::query!(
+        r#"UPDATE oauth_tokens SET invalidated_at = now()
+            WHERE invalidated_at IS NULL AND refresh_token IS NULL AND expires < (now() AT TIME ZONE 'UTC')"#
+    )
+    .execute(executor)
+    .await
 }
 
-
-pub async fn iam_delete_permission_statement<E>(
+pub async fn upsert_oauth_token<E>(
     executor: E,
-    id: i64
+    account_id: i64,
+    service: &str,
+    external_id: &str,
+    access_token: &str,
+    refresh_token: &Option<String>,
+    expires: Option<NaiveDateTime>,
 ) -> Result<(), sqlx::Error>
 where
     E: Send + Executor<Database = Postgres>,
 {
+    let access_token = encryption::seal_str(access_token)?;
+    let refresh_token = refresh_token
+        .as_deref()
+        .map(encryption::seal_str)
+        .transpose()?;
     sqlx::query!(
-        "DELETE FROM role_permission_statements WHERE id=$1", id)
-        .execute(executor).await?;
+        r#"INSERT INTO oauth_tokens (account_id, service, external_id, access_token, refresh_token, expires, encryption_key_id) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (service, account_id, external_id) DO UPDATE SET access_token = $4, refresh_token = $5, expires = $6, encryption_key_id = $7, invalidated_at = NULL"#,
+        account_id,
+        service,
+        external_id,
+        access_token,
+        refresh_token,
+        expires,
+        encryption::current_key_id()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Locks the tokens that aren't encrypted with the current master key.
+pub async fn lock_oauth_tokens_needing_reencryption<'e, E>(
+    executor: E,
+) -> Result<Vec<StoredOAuthToken>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query_as!(
+        OAuthTokenRow,
+        r#"SELECT account_id, service, external_id, access_token, refresh_token, expires, encryption_key_id FROM oauth_tokens
+            WHERE encryption_key_id IS DISTINCT FROM $1 FOR UPDATE"#,
+        encryption::current_key_id()
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(stored_oauth_token)
+        .collect::<Result<_, _>>()?)
+}
 
+/// Stores the token encrypted with the current master key.
+pub async fn reseal_oauth_token<E>(executor: E, token: &StoredOAuthToken) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let access_token = encryption::seal_str(&token.access_token)?;
+    let refresh_token = token
+        .refresh_token
+        .as_deref()
+        .map(encryption::seal_str)
+        .transpose()?;
+    sqlx::query!(
+        "UPDATE oauth_tokens SET access_token = $3, refresh_token = $4, encryption_key_id = $5 WHERE account_id = $1 AND service = $2 AND external_id = $6",
+        token.account_id,
+        &token.service,
+        access_token,
+        refresh_token,
+        encryption::current_key_id(),
+        &token.external_id
+    )
+    .execute(executor)
+    .await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Locks the installations whose private keys aren't encrypted with the
+/// current master key, returning their decrypted private keys.
+pub async fn lock_installation_keys_needing_reencryption<'e, E>(
+    executor: E,
+) -> Result<Vec<(Uuid, Vec<u8>)>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"SELECT id, private_key, encryption_key_id FROM installations
+            WHERE private_key IS NOT NULL AND encryption_key_id IS DISTINCT FROM $1 FOR UPDATE"#,
+        encryption::current_key_id()
+    )
+    .fetch_all(executor)
+    .await?;
+    let mut installations = Vec::with_capacity(rows.len());
+    for row in rows {
+        if let Some(private_key) = row.private_key {
+            installations.push((row.id, decrypt_bytes(&row.encryption_key_id, private_key)?));
+        }
+    }
+    Ok(installations)
+}
+
+/// Stores the installation's private key encrypted with the current master
+/// key.
+pub async fn reseal_installation_private_key<E>(
+    executor: E,
+    installation_id: Uuid,
+    private_key: &[u8],
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let private_key = encryption::seal(private_key)?;
+    sqlx::query!(
+        "UPDATE installations SET private_key = $2, encryption_key_id = $3 WHERE id = $1",
+        installation_id,
+        private_key,
+        encryption::current_key_id()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn create_oauth_state<E>(
+    executor: E,
+    state: &str,
+    installation_id: Uuid,
+    provider: &str,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+    link_account_id: Option<i64>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO oauth_states (state, installation_id, provider, nonce, expires_at, link_account_id) VALUES ($1, $2, $3, $4, $5, $6)",
+        state,
+        installation_id,
+        provider,
+        nonce,
+        expires_at,
+        link_account_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_expired_oauth_states<E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("DELETE FROM oauth_states WHERE expires_at < now()")
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// A login started with `create_oauth_state`.
+pub struct OAuthState {
+    pub installation_id: Uuid,
+    pub nonce: String,
+    /// Set when the login links another identity to an existing account.
+    pub link_account_id: Option<i64>,
+}
+
+/// Deletes the state so that it can only be used once, returning what it was
+/// created with if it hasn't expired.
+pub async fn take_oauth_state<'e, E>(
+    executor: E,
+    state: &str,
+    provider: &str,
+) -> Result<Option<OAuthState>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    match sqlx::query!(
+        "DELETE FROM oauth_states WHERE state = $1 AND provider = $2 RETURNING installation_id, nonce, link_account_id, expires_at > now() AS unexpired",
+        state,
+        provider
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(row) => {
+            if row.unexpired.unwrap_or(false) {
+                Ok(Some(OAuthState {
+                    installation_id: row.installation_id,
+                    nonce: row.nonce,
+                    link_account_id: row.link_account_id,
+                }))
+            } else {
+                Ok(None)
+            }
+        }
+        Err(sqlx::Error::RowNotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// A key used to sign identity tokens. Keys are published until they are
+/// deleted, but only sign tokens from when they activate until they are
+/// retired.
+pub struct StoredSigningKey {
+    pub id: String,
+    /// The PEM encoded RSA private key.
+    pub private_key: Vec<u8>,
+    pub rsa_n: String,
+    pub rsa_e: String,
+    pub created_at: DateTime<Utc>,
+    pub activates_at: DateTime<Utc>,
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+/// Prevents concurrent transactions from rotating signing keys, so that only
+/// one replacement is generated.
+pub async fn lock_jwt_signing_keys<E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("LOCK TABLE jwt_signing_keys IN SHARE ROW EXCLUSIVE MODE")
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Lists the keys that aren't retired or were retired after `retired_after`,
+/// including keys that haven't activated yet, newest first.
+pub async fn list_jwt_signing_keys<'e, E>(
+    executor: E,
+    retired_after: DateTime<Utc>,
+) -> Result<Vec<StoredSigningKey>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"SELECT id, private_key, encryption_key_id, rsa_n, rsa_e, created_at, retired_at, activates_at FROM jwt_signing_keys
+            WHERE retired_at IS NULL OR retired_at > $1
+            ORDER BY activates_at DESC, created_at DESC"#,
+        retired_after
+    )
+    .fetch_all(executor)
+    .await?;
+    let mut keys = Vec::with_capacity(rows.len());
+    for row in rows {
+        keys.push(StoredSigningKey {
+            private_key: encryption::open(&row.encryption_key_id, &row.private_key)?,
+            id: row.id,
+            rsa_n: row.rsa_n,
+            rsa_e: row.rsa_e,
+            created_at: row.created_at,
+            activates_at: row.activates_at,
+            retired_at: row.retired_at,
+        });
+    }
+    Ok(keys)
+}
+
+pub async fn insert_jwt_signing_key<E>(
+    executor: E,
+    id: &str,
+    private_key: &[u8],
+    rsa_n: &str,
+    rsa_e: &str,
+    activates_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let private_key = encryption::seal(private_key)?;
+    sqlx::query!(
+        "INSERT INTO jwt_signing_keys (id, private_key, encryption_key_id, rsa_n, rsa_e, activates_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        id,
+        private_key,
+        encryption::current_key_id(),
+        rsa_n,
+        rsa_e,
+        activates_at
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Retires every key except `except_id` at `retired_at`, unless they are
+/// already retired by then.
+pub async fn retire_other_jwt_signing_keys<E>(
+    executor: E,
+    except_id: &str,
+    retired_at: DateTime<Utc>,
+) -> Result<u64, sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE jwt_signing_keys SET retired_at = $2 WHERE (retired_at IS NULL OR retired_at > $2) AND id <> $1",
+        except_id,
+        retired_at
+    )
+    .execute(executor)
+    .await
+}
+
+/// Retires the key immediately, even if it was scheduled to retire later.
+/// Returns false if the key doesn't exist or was already retired.
+pub async fn retire_jwt_signing_key<E>(executor: E, id: &str) -> Result<bool, sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let retired = sqlx::query!(
+        "UPDATE jwt_signing_keys SET retired_at = now() WHERE (retired_at IS NULL OR retired_at > now()) AND id = $1",
+        id
+    )
+    .execute(executor)
+    .await?;
+    Ok(retired > 0)
+}
+
+/// Deletes the keys retired before `retired_before`, returning how many were
+/// deleted.
+pub async fn delete_retired_jwt_signing_keys<E>(
+    executor: E,
+    retired_before: DateTime<Utc>,
+) -> Result<u64, sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM jwt_signing_keys WHERE retired_at < $1",
+        retired_before
+    )
+    .execute(executor)
+    .await
+}
+
+/// Locks the signing keys that aren't encrypted with the current master key,
+/// returning their ids and decrypted private keys.
+pub async fn lock_jwt_signing_keys_needing_reencryption<'e, E>(
+    executor: E,
+) -> Result<Vec<(String, Vec<u8>)>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        "SELECT id, private_key, encryption_key_id FROM jwt_signing_keys WHERE encryption_key_id <> $1 FOR UPDATE",
+        encryption::current_key_id()
+    )
+    .fetch_all(executor)
+    .await?;
+    let mut keys = Vec::with_capacity(rows.len());
+    for row in rows {
+        keys.push((
+            row.id,
+            encryption::open(&row.encryption_key_id, &row.private_key)?,
+        ));
+    }
+    Ok(keys)
+}
+
+/// Stores the signing key's private key encrypted with the current master key.
+pub async fn reseal_jwt_signing_key<E>(
+    executor: E,
+    id: &str,
+    private_key: &[u8],
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let private_key = encryption::seal(private_key)?;
+    sqlx::query!(
+        "UPDATE jwt_signing_keys SET private_key = $2, encryption_key_id = $3 WHERE id = $1",
+        id,
+        private_key,
+        encryption::current_key_id()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// A third-party application that can log users in with OpenID Connect.
+pub struct Application {
+    /// The application's `client_id`.
+    pub id: String,
+    pub name: String,
+    /// `None` for public clients, which can only authenticate with PKCE.
+    pub client_secret_hash: Option<Vec<u8>>,
+    pub owner_account_id: Option<i64>,
+}
+
+impl From<Application> for ApplicationSummary {
+    fn from(application: Application) -> Self {
+        Self {
+            id: Some(application.id),
+            name: application.name,
+            owner_account_id: application.owner_account_id,
+        }
+    }
+}
+
+pub async fn get_application<'e, E>(
+    executor: E,
+    application_id: &str,
+) -> Result<Option<Application>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query_as!(
+        Application,
+        "SELECT id, name, client_secret_hash, owner_account_id FROM applications WHERE id = $1",
+        application_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().next())
+}
+
+/// The application that identity verification tokens for `audience` are
+/// issued to.
+pub async fn get_application_by_audience<'e, E>(
+    executor: E,
+    audience: &str,
+) -> Result<Option<Application>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query_as!(
+        Application,
+        r#"SELECT applications.id, name, client_secret_hash, owner_account_id FROM applications
+            INNER JOIN application_audiences ON application_audiences.application_id = applications.id
+            WHERE application_audiences.audience = $1"#,
+        audience
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().next())
+}
+
+pub async fn create_application<E>(
+    executor: E,
+    application_id: &str,
+    name: &str,
+    client_secret_hash: Option<&[u8]>,
+    owner_account_id: Option<i64>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO applications (id, name, client_secret_hash, owner_account_id) VALUES ($1, $2, $3, $4)",
+        application_id,
+        name,
+        client_secret_hash,
+        owner_account_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn add_application_redirect_uri<E>(
+    executor: E,
+    application_id: &str,
+    redirect_uri: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO application_redirect_uris (application_id, redirect_uri) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        application_id,
+        redirect_uri
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Redirect uris must exactly match one registered for the application.
+pub async fn application_allows_redirect_uri<'e, E>(
+    executor: E,
+    application_id: &str,
+    redirect_uri: &str,
+) -> Result<bool, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        "SELECT EXISTS (SELECT 1 FROM application_redirect_uris WHERE application_id = $1 AND redirect_uri = $2) AS allowed",
+        application_id,
+        redirect_uri
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok(row.allowed.unwrap_or(false))
+}
+
+/// An OpenID Connect authentication request awaiting the user's consent.
+pub struct OidcAuthorizationRequest {
+    pub id: String,
+    pub application_id: String,
+    pub redirect_uri: String,
+    /// The space separated scopes being granted.
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    /// The S256 PKCE code challenge.
+    pub code_challenge: String,
+}
+
+pub async fn create_oidc_authorization_request<E>(
+    executor: E,
+    request: &OidcAuthorizationRequest,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"INSERT INTO oidc_authorization_requests (id, application_id, redirect_uri, scope, state, nonce, code_challenge, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        &request.id,
+        &request.application_id,
+        &request.redirect_uri,
+        &request.scope,
+        request.state.as_deref(),
+        request.nonce.as_deref(),
+        &request.code_challenge,
+        expires_at
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns the request if it hasn't expired, remembering the installation
+/// viewing it so that logging in can return to it.
+pub async fn view_oidc_authorization_request<'e, E>(
+    executor: E,
+    request_id: &str,
+    installation_id: Uuid,
+) -> Result<Option<OidcAuthorizationRequest>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query_as!(
+        OidcAuthorizationRequest,
+        r#"UPDATE oidc_authorization_requests SET installation_id = $2 WHERE id = $1 AND expires_at > now()
+            RETURNING id, application_id, redirect_uri, scope, state, nonce, code_challenge"#,
+        request_id,
+        installation_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().next())
+}
+
+/// Deletes the request so that it can only be answered once, returning it if
+/// it hasn't expired.
+pub async fn take_oidc_authorization_request<'e, E>(
+    executor: E,
+    request_id: &str,
+) -> Result<Option<OidcAuthorizationRequest>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query_as!(
+        OidcAuthorizationRequest,
+        r#"DELETE FROM oidc_authorization_requests WHERE id = $1 AND expires_at > now()
+            RETURNING id, application_id, redirect_uri, scope, state, nonce, code_challenge"#,
+        request_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().next())
+}
+
+/// The newest request the installation was viewing before logging in.
+pub async fn pending_oidc_authorization_request_id<'e, E>(
+    executor: E,
+    installation_id: Uuid,
+) -> Result<Option<String>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"SELECT id FROM oidc_authorization_requests WHERE installation_id = $1 AND expires_at > now()
+            ORDER BY expires_at DESC LIMIT 1"#,
+        installation_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.id).next())
+}
+
+/// An authorization code issued after the user consented to a request.
+pub struct OidcAuthorizationCode {
+    pub application_id: String,
+    pub account_id: i64,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub nonce: Option<String>,
+    pub code_challenge: String,
+    pub auth_time: DateTime<Utc>,
+}
+
+pub async fn create_oidc_authorization_code<E>(
+    executor: E,
+    code_hash: &[u8],
+    request: &OidcAuthorizationRequest,
+    account_id: i64,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"INSERT INTO oidc_authorization_codes (code_hash, application_id, account_id, redirect_uri, scope, nonce, code_challenge, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+        code_hash,
+        &request.application_id,
+        account_id,
+        &request.redirect_uri,
+        &request.scope,
+        request.nonce.as_deref(),
+        &request.code_challenge,
+        expires_at
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the code so that it can only be exchanged once, returning it if it
+/// hasn't expired.
+pub async fn take_oidc_authorization_code<'e, E>(
+    executor: E,
+    code_hash: &[u8],
+) -> Result<Option<OidcAuthorizationCode>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query_as!(
+        OidcAuthorizationCode,
+        r#"DELETE FROM oidc_authorization_codes WHERE code_hash = $1 AND expires_at > now()
+            RETURNING application_id, account_id, redirect_uri, scope, nonce, code_challenge, auth_time"#,
+        code_hash
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().next())
+}
+
+pub async fn create_oidc_access_token<E>(
+    executor: E,
+    token_hash: &[u8],
+    application_id: &str,
+    account_id: i64,
+    scope: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"INSERT INTO oidc_access_tokens (token_hash, application_id, account_id, scope, expires_at)
+            VALUES ($1, $2, $3, $4, $5)"#,
+        token_hash,
+        application_id,
+        account_id,
+        scope,
+        expires_at
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Returns the account id and scope of the access token if it hasn't expired.
+pub async fn get_oidc_access_token<'e, E>(
+    executor: E,
+    token_hash: &[u8],
+) -> Result<Option<(i64, String)>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        "SELECT account_id, scope FROM oidc_access_tokens WHERE token_hash = $1 AND expires_at > now()",
+        token_hash
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.account_id, row.scope))
+        .next())
+}
+
+pub async fn delete_expired_oidc_grants<E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"WITH deleted_requests AS (
+                DELETE FROM oidc_authorization_requests WHERE expires_at < now()
+            ), deleted_codes AS (
+                DELETE FROM oidc_authorization_codes WHERE expires_at < now()
+            )
+            DELETE FROM oidc_access_tokens WHERE expires_at < now()"#
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the account's access tokens and the codes it hasn't exchanged yet.
+pub async fn delete_oidc_grants_for_account<E>(
+    executor: E,
+    account_id: i64,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"WITH deleted_codes AS (
+                DELETE FROM oidc_authorization_codes WHERE account_id = $1
+            )
+            DELETE FROM oidc_access_tokens WHERE account_id = $1"#,
+        account_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Stores the installation's device authorization, replacing any code it
+/// requested before.
+pub async fn create_device_authorization<E>(
+    executor: E,
+    user_code: &str,
+    installation_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"INSERT INTO device_authorizations (user_code, installation_id, expires_at) VALUES ($1, $2, $3)
+            ON CONFLICT (installation_id) DO UPDATE SET user_code = $1, expires_at = $3, created_at = now()"#,
+        user_code,
+        installation_id,
+        expires_at
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Deletes the device authorization so that its code can only be entered
+/// once, returning the installation waiting on it if it hasn't expired.
+pub async fn take_device_authorization<'e, E>(
+    executor: E,
+    user_code: &str,
+) -> Result<Option<Uuid>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"DELETE FROM device_authorizations WHERE user_code = $1 AND expires_at > now()
+            RETURNING installation_id"#,
+        user_code
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.installation_id).next())
+}
+
+pub async fn delete_expired_device_authorizations<E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("DELETE FROM device_authorizations WHERE expires_at < now()")
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Records an identity verification token. Returns false without recording
+/// it if a token was already issued for the `audience` and `nonce`.
+pub async fn create_identity_verification_token<'e, E>(
+    executor: E,
+    jwt_id: &str,
+    audience: &str,
+    nonce: &[u8],
+    account_id: i64,
+    installation_id: Option<Uuid>,
+    expires_at: DateTime<Utc>,
+) -> Result<bool, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"INSERT INTO identity_verification_tokens (jwt_id, audience, nonce, account_id, installation_id, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (audience, nonce) DO NOTHING
+            RETURNING jwt_id"#,
+        jwt_id,
+        audience,
+        nonce,
+        account_id,
+        installation_id,
+        expires_at
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(!rows.is_empty())
+}
+
+/// How many tokens were issued to the account, and to the installation, since
+/// `since`.
+pub async fn count_identity_verification_tokens_since<'e, E>(
+    executor: E,
+    account_id: i64,
+    installation_id: Option<Uuid>,
+    since: DateTime<Utc>,
+) -> Result<(i64, i64), sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"SELECT COUNT(*) AS account_tokens, COUNT(*) FILTER (WHERE installation_id = $2) AS installation_tokens
+            FROM identity_verification_tokens WHERE account_id = $1 AND issued_at > $3"#,
+        account_id,
+        installation_id,
+        since
+    )
+    .fetch_one(executor)
+    .await?;
+    Ok((
+        row.account_tokens.unwrap_or_default(),
+        row.installation_tokens.unwrap_or_default(),
+    ))
+}
+
+/// Revokes every unexpired token issued to the account.
+pub async fn revoke_identity_verification_tokens_for_account<E>(
+    executor: E,
+    account_id: i64,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE identity_verification_tokens SET revoked_at = now() WHERE account_id = $1 AND revoked_at IS NULL AND expires_at > now()",
+        account_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Whether the token was issued, hasn't expired, and hasn't been revoked.
+pub async fn identity_verification_token_is_active<'e, E>(
+    executor: E,
+    jwt_id: &str,
+) -> Result<bool, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        "SELECT jwt_id FROM identity_verification_tokens WHERE jwt_id = $1 AND revoked_at IS NULL AND expires_at > now()",
+        jwt_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(!rows.is_empty())
+}
+
+pub async fn revoke_identity_verification_token<E>(
+    executor: E,
+    jwt_id: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE identity_verification_tokens SET revoked_at = now() WHERE jwt_id = $1 AND revoked_at IS NULL",
+        jwt_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The revoked tokens that haven't expired yet.
+pub async fn list_revoked_identity_verification_tokens<'e, E>(
+    executor: E,
+) -> Result<Vec<RevokedIdentityToken>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    sqlx::query_as!(
+        RevokedIdentityToken,
+        r#"SELECT jwt_id, expires_at FROM identity_verification_tokens
+            WHERE revoked_at IS NOT NULL AND expires_at > now() ORDER BY expires_at"#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Expired tokens no longer need replay protection or revoking.
+pub async fn delete_expired_identity_verification_tokens<E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("DELETE FROM identity_verification_tokens WHERE expires_at < now()")
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+pub async fn load_permissions_for<'e, E>(
+    executor: E,
+    account_id: i64,
+) -> Result<PermissionSet, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    // Roles can include other roles, so the effective roles are every role
+    // reachable from the roles directly assigned to the account. UNION
+    // discards duplicates, which also stops the recursion on any cycles.
+    let results  = sqlx::query_as!(Statement, r#"WITH RECURSIVE effective_roles(role_id) AS (
+                SELECT role_id FROM account_roles WHERE account_id = $1
+                UNION
+                SELECT role_inclusions.included_role_id FROM role_inclusions
+                    INNER JOIN effective_roles ON effective_roles.role_id = role_inclusions.role_id
+            )
+            SELECT id, role_id, service, resource_type, resource_id, action, allow FROM role_permission_statements 
+            WHERE 
+                (role_id IS NULL OR role_id IN (SELECT role_id FROM effective_roles))
+        "#, account_id).fetch_all(executor).await?;
+
+    Ok(results.into())
+}
+
+/// A position within a sorted list: the id and sort value of the last entry
+/// on the previous page. Ids must not contain `:`.
+pub struct ListCursor<I = i64> {
+    pub id: I,
+    pub sort_value: String,
+}
+
+impl<I: FromStr> ListCursor<I> {
+    pub fn parse(cursor: &str) -> Option<Self> {
+        let mut parts = cursor.splitn(2, ':');
+        let id = parts.next()?.parse().ok()?;
+        let sort_value = parts.next()?.to_string();
+        Some(Self { id, sort_value })
+    }
+}
+
+impl<I: Display> ToString for ListCursor<I> {
+    fn to_string(&self) -> String {
+        format!("{}:{}", self.id, self.sort_value)
+    }
+}
+
+/// Turns a search into an ILIKE pattern that matches it anywhere, escaping
+/// any wildcards the search contains.
+fn search_pattern(search: &Option<String>) -> Option<String> {
+    search.as_ref().and_then(|search| {
+        let search = search.trim();
+        if search.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "%{}%",
+                search
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            ))
+        }
+    })
+}
+
+fn sort_direction(descending: bool) -> (&'static str, &'static str) {
+    if descending {
+        ("DESC", "<")
+    } else {
+        ("ASC", ">")
+    }
+}
+
+pub async fn iam_list_users<'e, E>(
+    executor: E,
+    query: &ListQuery<UserSort>,
+    cursor: Option<ListCursor>,
+    limit: i64,
+) -> Result<ListPage<User>, sqlx::Error>
+where
+    E: Copy + 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let search = search_pattern(&query.search);
+    let (sort_expression, sort_type) = match query.sort {
+        UserSort::Id => ("accounts.id", "BIGINT"),
+        UserSort::Login => ("COALESCE(accounts.login, '')", "TEXT"),
+        UserSort::DisplayName => ("COALESCE(accounts.display_name, '')", "TEXT"),
+        UserSort::CreatedAt => ("accounts.created_at", "TIMESTAMPTZ"),
+    };
+    let (direction, comparison) = sort_direction(query.descending);
+
+    let total_count = sqlx::query!(
+        "SELECT COUNT(*) AS total_count FROM accounts WHERE ($1::TEXT IS NULL OR login ILIKE $1 OR display_name ILIKE $1)",
+        search.as_deref()
+    )
+    .fetch_one(executor)
+    .await?
+    .total_count
+    .unwrap_or_default();
+
+    // The page of accounts is selected first so that the limit isn't affected
+    // by the number of roles each account has.
+    let sql = format!(
+        r#"WITH page AS (
+                SELECT accounts.id, login, display_name, created_at, {sort}::TEXT AS sort_value, {sort} AS sort_key,
+                    suspended_at, suspended_until, suspension_reason, merged_into_id FROM accounts
+                WHERE ($1::TEXT IS NULL OR login ILIKE $1 OR display_name ILIKE $1)
+                    AND ($2::BIGINT IS NULL OR ({sort}, accounts.id) {comparison} ($3::{sort_type}, $2))
+                ORDER BY sort_key {direction}, accounts.id {direction}
+                LIMIT $4
+            )
+            SELECT page.id, login, display_name, created_at, sort_value, roles.id as role_id, roles.name as role_name,
+                suspended_at, suspended_until, suspension_reason, merged_into_id FROM page
+            LEFT OUTER JOIN account_roles ON account_roles.account_id = page.id
+            LEFT OUTER JOIN roles ON roles.id = account_roles.role_id
+            ORDER BY page.sort_key {direction}, page.id {direction}, roles.name"#,
+        sort = sort_expression,
+        sort_type = sort_type,
+        comparison = comparison,
+        direction = direction,
+    );
+
+    let mut users: Vec<User> = Vec::new();
+    let mut sort_values = Vec::new();
+    let mut user_rows = sqlx::query(&sql)
+        .bind(&search)
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(cursor.as_ref().map(|cursor| cursor.sort_value.clone()))
+        .bind(limit + 1)
+        .fetch(executor);
+    while let Some(row) = user_rows.next().await? {
+        let id = row.get::<Option<i64>, _>("id");
+        if users.is_empty() || users[users.len() - 1].id != id {
+            users.push(user(&row));
+            sort_values.push(row.get::<String, _>("sort_value"));
+        }
+
+        if let Some(role_id) = row.get::<Option<i64>, _>("role_id") {
+            let role_name = row.get::<String, _>("role_name");
+            users.last_mut().unwrap().roles.push(RoleSummary {
+                id: Some(role_id),
+                name: role_name,
+            });
+        }
+    }
+
+    // One more row than requested is fetched to know whether there's another page
+    let next_cursor = if users.len() as i64 > limit {
+        users.pop();
+        users.last().map(|user| {
+            ListCursor {
+                id: user.id.unwrap(),
+                sort_value: sort_values[users.len() - 1].clone(),
+            }
+            .to_string()
+        })
+    } else {
+        None
+    };
+
+    Ok(ListPage {
+        entities: users,
+        total_count,
+        next_cursor,
+    })
+}
+
+pub async fn iam_get_user<'e, E>(executor: E, account_id: i64) -> Result<Option<User>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let mut user = None;
+
+    // TODO https://github.com/launchbadge/sqlx/issues/367 Once this is shipping, we can switch this to strongly typed query again
+    let mut user_rows = sqlx::query(r#"SELECT accounts.id, login, display_name, created_at, roles.id as role_id, roles.name as role_name,
+            suspended_at, suspended_until, suspension_reason, merged_into_id FROM accounts 
+            LEFT OUTER JOIN account_roles ON account_roles.account_id = accounts.id
+            LEFT OUTER JOIN roles ON roles.id = account_roles.role_id WHERE accounts.id = $1 ORDER BY accounts.id"#).bind(&account_id).fetch(executor);
+    while let Some(row) = user_rows.next().await? {
+        user = Some(match user {
+            Some(user) => user,
+            None => self::user(&row),
+        });
+
+        if let Some(role_id) = row.get::<Option<i64>, _>("role_id") {
+            let role_name = row.get::<String, _>("role_name");
+            user.as_mut().unwrap().roles.push(RoleSummary {
+                id: Some(role_id),
+                name: role_name,
+            });
+        }
+    }
+
+    Ok(user)
+}
+
+/// The user in a row of `iam_list_users` or `iam_get_user`, without roles.
+fn user(row: &PgRow) -> User {
+    User {
+        id: row.get::<Option<i64>, _>("id"),
+        login: row.get::<Option<String>, _>("login"),
+        display_name: row.get::<Option<String>, _>("display_name"),
+        created_at: row.get::<DateTime<Utc>, _>("created_at"),
+        roles: Vec::new(),
+        suspension: active_suspension(
+            row.get::<Option<DateTime<Utc>>, _>("suspended_at"),
+            row.get::<Option<DateTime<Utc>>, _>("suspended_until"),
+            row.get::<Option<String>, _>("suspension_reason"),
+        ),
+        merged_into_id: row.get::<Option<i64>, _>("merged_into_id"),
+    }
+}
+
+fn active_suspension(
+    suspended_at: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    reason: Option<String>,
+) -> Option<AccountSuspension> {
+    suspended_at
+        .map(|suspended_at| AccountSuspension {
+            reason,
+            suspended_at,
+            until,
+        })
+        .filter(AccountSuspension::is_active)
+}
+
+pub async fn get_account_suspension<'e, E>(
+    executor: E,
+    account_id: i64,
+) -> Result<Option<AccountSuspension>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    match sqlx::query!(
+        "SELECT suspended_at, suspended_until, suspension_reason FROM accounts WHERE id = $1",
+        account_id
+    )
+    .fetch_one(executor)
+    .await
+    {
+        Ok(row) => Ok(active_suspension(
+            row.suspended_at,
+            row.suspended_until,
+            row.suspension_reason,
+        )),
+        Err(sqlx::Error::RowNotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn iam_suspend_account<E>(
+    executor: E,
+    account_id: i64,
+    reason: &Option<String>,
+    until: Option<DateTime<Utc>>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE accounts SET suspended_at = now(), suspended_until = $2, suspension_reason = $3 WHERE id = $1",
+        account_id,
+        until,
+        reason.as_deref()
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn iam_unsuspend_account<E>(executor: E, account_id: i64) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE accounts SET suspended_at = NULL, suspended_until = NULL, suspension_reason = NULL WHERE id = $1",
+        account_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Moves the source account's roles, identities, tokens and installations to
+/// the target account, then marks the source as merged into the target. Where
+/// both accounts have a token for the same identity, the target's is kept.
+/// Grants issued to applications for the source are deleted, and its identity
+/// verification tokens are revoked. Returns the ids of the installations that
+/// were moved.
+pub async fn iam_merge_accounts<'e, E>(
+    executor: E,
+    source_account_id: i64,
+    target_account_id: i64,
+) -> Result<Vec<Uuid>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"WITH copied_roles AS (
+                INSERT INTO account_roles (account_id, role_id) SELECT $2, role_id FROM account_roles WHERE account_id = $1
+                    ON CONFLICT DO NOTHING
+            ), deleted_roles AS (
+                DELETE FROM account_roles WHERE account_id = $1
+            ), moved_identities AS (
+                UPDATE external_identities SET account_id = $2 WHERE account_id = $1
+            ), copied_tokens AS (
+                INSERT INTO oauth_tokens (account_id, service, external_id, access_token, refresh_token, expires, encryption_key_id, invalidated_at)
+                    SELECT $2, service, external_id, access_token, refresh_token, expires, encryption_key_id, invalidated_at FROM oauth_tokens WHERE account_id = $1
+                    ON CONFLICT (service, account_id, external_id) DO NOTHING
+            ), deleted_tokens AS (
+                DELETE FROM oauth_tokens WHERE account_id = $1
+            ), deleted_states AS (
+                DELETE FROM oauth_states WHERE link_account_id = $1
+            ), retired_accounts AS (
+                UPDATE accounts SET merged_into_id = $2 WHERE id = $1 OR merged_into_id = $1
+            ), deleted_oidc_codes AS (
+                DELETE FROM oidc_authorization_codes WHERE account_id = $1
+            ), deleted_oidc_tokens AS (
+                DELETE FROM oidc_access_tokens WHERE account_id = $1
+            ), revoked_identity_tokens AS (
+                UPDATE identity_verification_tokens SET revoked_at = now()
+                    WHERE account_id = $1 AND revoked_at IS NULL AND expires_at > now()
+            ), moved_installations AS (
+                UPDATE installations SET account_id = $2 WHERE account_id = $1 RETURNING id
+            )
+            SELECT id FROM moved_installations"#,
+        source_account_id,
+        target_account_id
+    )
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+pub async fn iam_add_account_role<E>(
+    executor: E,
+    account_id: i64,
+    role_id: i64,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO account_roles (account_id, role_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        account_id,
+        role_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn iam_remove_account_role<E>(
+    executor: E,
+    account_id: i64,
+    role_id: i64,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM account_roles WHERE account_id = $1 AND role_id = $2",
+        account_id,
+        role_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn iam_list_roles<'e, E>(
+    executor: E,
+    query: &ListQuery<RoleSort>,
+    cursor: Option<ListCursor>,
+    limit: i64,
+) -> Result<ListPage<RoleSummary>, sqlx::Error>
+where
+    E: Copy + 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let search = search_pattern(&query.search);
+    let (sort_expression, sort_type) = match query.sort {
+        RoleSort::Id => ("id", "BIGINT"),
+        RoleSort::Name => ("name", "TEXT"),
+    };
+    let (direction, comparison) = sort_direction(query.descending);
+
+    let total_count = sqlx::query!(
+        "SELECT COUNT(*) AS total_count FROM roles WHERE ($1::TEXT IS NULL OR name ILIKE $1)",
+        search.as_deref()
+    )
+    .fetch_one(executor)
+    .await?
+    .total_count
+    .unwrap_or_default();
+
+    let sql = format!(
+        r#"SELECT id, name, {sort}::TEXT AS sort_value FROM roles
+            WHERE ($1::TEXT IS NULL OR name ILIKE $1)
+                AND ($2::BIGINT IS NULL OR ({sort}, id) {comparison} ($3::{sort_type}, $2))
+            ORDER BY {sort} {direction}, id {direction}
+            LIMIT $4"#,
+        sort = sort_expression,
+        sort_type = sort_type,
+        comparison = comparison,
+        direction = direction,
+    );
+
+    let mut roles = Vec::new();
+    let mut sort_values = Vec::new();
+    let mut rows = sqlx::query(&sql)
+        .bind(&search)
+        .bind(cursor.as_ref().map(|cursor| cursor.id))
+        .bind(cursor.as_ref().map(|cursor| cursor.sort_value.clone()))
+        .bind(limit + 1)
+        .fetch(executor);
+    while let Some(row) = rows.next().await? {
+        roles.push(RoleSummary {
+            id: row.get::<Option<i64>, _>("id"),
+            name: row.get::<String, _>("name"),
+        });
+        sort_values.push(row.get::<String, _>("sort_value"));
+    }
+
+    // One more row than requested is fetched to know whether there's another page
+    let next_cursor = if roles.len() as i64 > limit {
+        roles.pop();
+        roles.last().map(|role| {
+            ListCursor {
+                id: role.id.unwrap(),
+                sort_value: sort_values[roles.len() - 1].clone(),
+            }
+            .to_string()
+        })
+    } else {
+        None
+    };
+
+    Ok(ListPage {
+        entities: roles,
+        total_count,
+        next_cursor,
+    })
+}
+
+/// Loads the role with its statements and included roles. Takes a mutable
+/// reference so that every query can run within the same transaction.
+pub async fn iam_get_role<E>(executor: &mut E, role_id: i64) -> Result<Option<Role>, sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let summary = match sqlx::query_as!(
+        RoleSummary,
+        "SELECT id, name FROM roles WHERE id = $1",
+        role_id
+    )
+    .fetch_one(&mut *executor)
+    .await
+    {
+        Ok(role) => role,
+        Err(err) => match err {
+            sqlx::Error::RowNotFound => return Ok(None),
+            _ => return Err(err),
+        },
+    };
+
+    let permission_statements = sqlx::query_as!(
+        PermissionStatement,
+        r#"SELECT *
+        FROM role_permission_statements
+        WHERE role_id = $1
+        ORDER BY id"#,
+        role_id
+    )
+    .fetch_all(&mut *executor)
+    .await?;
+
+    let included_roles = sqlx::query_as!(
+        RoleSummary,
+        r#"SELECT roles.id, roles.name
+        FROM role_inclusions
+        INNER JOIN roles ON roles.id = role_inclusions.included_role_id
+        WHERE role_inclusions.role_id = $1
+        ORDER BY roles.name"#,
+        role_id
+    )
+    .fetch_all(&mut *executor)
+    .await?;
+
+    Ok(Some(Role {
+        id: summary.id,
+        name: summary.name,
+        permission_statements,
+        included_roles,
+    }))
+}
+
+pub async fn iam_update_role<'e, E>(executor: E, role: &RoleSummary) -> Result<i64, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let id = sqlx::query!(
+        r#"INSERT INTO roles (id, name) VALUES (COALESCE($1, (SELECT nextval('roles_id_seq'))), $2) 
+            ON CONFLICT (id) DO UPDATE SET name = $2
+            RETURNING id"#,
+        role.id,
+        &role.name
+    )
+    .fetch_one(executor)
+    .await?
+    .id;
+
+    Ok(id)
+}
+
+
+pub async fn iam_delete_role<E>(
+    executor: E,
+    id: i64
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM roles WHERE id=$1", id)
+        .execute(executor).await?;
+
+    Ok(())
+}
+
+/// Prevents concurrent transactions from changing role inclusions, so that
+/// cycle detection cannot race with another save.
+pub async fn iam_lock_role_inclusions<E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("LOCK TABLE role_inclusions IN SHARE ROW EXCLUSIVE MODE")
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn iam_clear_role_inclusions<E>(executor: E, role_id: i64) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("DELETE FROM role_inclusions WHERE role_id = $1", role_id)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn iam_add_role_inclusion<E>(
+    executor: E,
+    role_id: i64,
+    included_role_id: i64,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO role_inclusions (role_id, included_role_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        role_id,
+        included_role_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns true if `role_id` grants the statements of `included_role_id`,
+/// either directly or through other included roles.
+pub async fn iam_role_includes<'e, E>(
+    executor: E,
+    role_id: i64,
+    included_role_id: i64,
+) -> Result<bool, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let row = sqlx::query!(
+        r#"WITH RECURSIVE included_roles(role_id) AS (
+                SELECT included_role_id FROM role_inclusions WHERE role_id = $1
+                UNION
+                SELECT role_inclusions.included_role_id FROM role_inclusions
+                    INNER JOIN included_roles ON included_roles.role_id = role_inclusions.role_id
+            )
+            SELECT EXISTS(SELECT 1 FROM included_roles WHERE role_id = $2) AS included"#,
+        role_id,
+        included_role_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(row.included.unwrap_or(false))
+}
+
+/// Returns the ids of every account that has `role_id` assigned, either
+/// directly or through a role that includes it.
+pub async fn iam_accounts_with_effective_role<'e, E>(
+    executor: E,
+    role_id: i64,
+) -> Result<Vec<i64>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        r#"WITH RECURSIVE including_roles(role_id) AS (
+                SELECT $1::BIGINT
+                UNION
+                SELECT role_inclusions.role_id FROM role_inclusions
+                    INNER JOIN including_roles ON including_roles.role_id = role_inclusions.included_role_id
+            )
+            SELECT DISTINCT account_id FROM account_roles
+            WHERE role_id IN (SELECT role_id FROM including_roles)"#,
+        role_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.account_id).collect())
+}
+
+/// Returns the ids of the roles directly included by `role_id`.
+pub async fn iam_included_role_ids<'e, E>(
+    executor: E,
+    role_id: i64,
+) -> Result<Vec<i64>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let rows = sqlx::query!(
+        "SELECT included_role_id FROM role_inclusions WHERE role_id = $1",
+        role_id
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| row.included_role_id).collect())
+}
+
+/// Returns the statements granted by `role_id`, including the statements of
+/// every role it includes.
+pub async fn iam_effective_role_statements<'e, E>(
+    executor: E,
+    role_id: i64,
+) -> Result<Vec<Statement>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    sqlx::query_as!(Statement, r#"WITH RECURSIVE effective_roles(role_id) AS (
+                SELECT $1::BIGINT
+                UNION
+                SELECT role_inclusions.included_role_id FROM role_inclusions
+                    INNER JOIN effective_roles ON effective_roles.role_id = role_inclusions.role_id
+            )
+            SELECT id, role_id, service, resource_type, resource_id, action, allow FROM role_permission_statements
+            WHERE role_id IN (SELECT role_id FROM effective_roles)
+        "#, role_id).fetch_all(executor).await
+}
+
+pub async fn iam_get_permission_statement<'e, E>(
+    executor: E,
+    permission_statement_id: i64,
+) -> Result<PermissionStatement, sqlx::Error>
+where
+    E: Copy + 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    sqlx::query_as!(
+        PermissionStatement,
+        r#"SELECT *
+        FROM role_permission_statements
+        WHERE id = $1"#,
+        permission_statement_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn iam_update_permission_statement<'e, E>(
+    executor: E,
+    statement: &PermissionStatement,
+) -> Result<i64, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let id = sqlx::query!(
+        r#"INSERT INTO role_permission_statements (
+                id, 
+                role_id, 
+                service, 
+                resource_type, 
+                resource_id, 
+                action, 
+                allow, 
+                comment
+            ) VALUES (COALESCE($1, (SELECT nextval('role_permission_statements_id_seq'))), $2, $3, $4, $5, $6, $7, $8) 
+            ON CONFLICT (id) DO UPDATE SET role_id = $2, service = $3, resource_type = $4, resource_id = $5, action = $6, allow = $7, comment = $8
+            RETURNING id"#,
+        statement.id,
+        statement.role_id,
+        statement.service.as_deref(),
+        statement.resource_type.as_deref(),
+        statement.resource_id.as_deref(),
+        statement.action.as_deref(),
+        statement.allow,
+        statement.comment.as_deref()
+    )
+    .fetch_one(executor)
+    .await?
+    .id;
+
+    Ok(id)
+}
+
+
+pub async fn iam_delete_permission_statement<E>(
+    executor: E,
+    id: i64
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM role_permission_statements WHERE id=$1", id)
+        .execute(executor).await?;
+
+    Ok(())
+}
+
+pub async fn iam_list_applications<'e, E>(
+    executor: E,
+    query: &ListQuery<ApplicationSort>,
+    cursor: Option<ListCursor<String>>,
+    limit: i64,
+) -> Result<ListPage<ApplicationSummary>, sqlx::Error>
+where
+    E: Copy + 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    let search = search_pattern(&query.search);
+    let (sort_expression, sort_type) = match query.sort {
+        ApplicationSort::Name => ("name", "TEXT"),
+    };
+    let (direction, comparison) = sort_direction(query.descending);
+
+    let total_count = sqlx::query!(
+        "SELECT COUNT(*) AS total_count FROM applications WHERE ($1::TEXT IS NULL OR name ILIKE $1)",
+        search.as_deref()
+    )
+    .fetch_one(executor)
+    .await?
+    .total_count
+    .unwrap_or_default();
+
+    let sql = format!(
+        r#"SELECT id, name, owner_account_id, {sort}::TEXT AS sort_value FROM applications
+            WHERE ($1::TEXT IS NULL OR name ILIKE $1)
+                AND ($2::TEXT IS NULL OR ({sort}, id) {comparison} ($3::{sort_type}, $2))
+            ORDER BY {sort} {direction}, id {direction}
+            LIMIT $4"#,
+        sort = sort_expression,
+        sort_type = sort_type,
+        comparison = comparison,
+        direction = direction,
+    );
+
+    let mut applications = Vec::new();
+    let mut sort_values = Vec::new();
+    let mut rows = sqlx::query(&sql)
+        .bind(&search)
+        .bind(cursor.as_ref().map(|cursor| cursor.id.clone()))
+        .bind(cursor.as_ref().map(|cursor| cursor.sort_value.clone()))
+        .bind(limit + 1)
+        .fetch(executor);
+    while let Some(row) = rows.next().await? {
+        applications.push(ApplicationSummary {
+            id: row.get("id"),
+            name: row.get("name"),
+            owner_account_id: row.get("owner_account_id"),
+        });
+        sort_values.push(row.get::<String, _>("sort_value"));
+    }
+
+    let next_cursor = if applications.len() as i64 > limit {
+        applications.pop();
+        applications.last().map(|application| {
+            ListCursor {
+                id: application.id.clone().unwrap(),
+                sort_value: sort_values[applications.len() - 1].clone(),
+            }
+            .to_string()
+        })
+    } else {
+        None
+    };
+
+    Ok(ListPage {
+        entities: applications,
+        total_count,
+        next_cursor,
+    })
+}
+
+/// Takes a mutable reference so that every query can run within the same
+/// transaction.
+pub async fn iam_get_application<E>(
+    executor: &mut E,
+    application_id: &str,
+) -> Result<Option<iam::Application>, sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    let row = match sqlx::query!(
+        r#"SELECT id, name, owner_account_id, client_secret_hash IS NOT NULL AS has_client_secret, created_at
+            FROM applications WHERE id = $1"#,
+        application_id
+    )
+    .fetch_one(&mut *executor)
+    .await
+    {
+        Ok(row) => row,
+        Err(sqlx::Error::RowNotFound) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let audiences = sqlx::query!(
+        "SELECT audience FROM application_audiences WHERE application_id = $1 ORDER BY audience",
+        application_id
+    )
+    .fetch_all(&mut *executor)
+    .await?
+    .into_iter()
+    .map(|row| row.audience)
+    .collect();
+
+    let redirect_uris = sqlx::query!(
+        "SELECT redirect_uri FROM application_redirect_uris WHERE application_id = $1 ORDER BY redirect_uri",
+        application_id
+    )
+    .fetch_all(&mut *executor)
+    .await?
+    .into_iter()
+    .map(|row| row.redirect_uri)
+    .collect();
+
+    Ok(Some(iam::Application {
+        summary: ApplicationSummary {
+            id: Some(row.id),
+            name: row.name,
+            owner_account_id: row.owner_account_id,
+        },
+        has_client_secret: row.has_client_secret.unwrap_or(false),
+        audiences,
+        redirect_uris,
+        created_at: row.created_at,
+    }))
+}
+
+pub async fn iam_update_application<E>(
+    executor: E,
+    application: &ApplicationSummary,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE applications SET name = $2, owner_account_id = $3 WHERE id = $1",
+        application.id.as_deref(),
+        &application.name,
+        application.owner_account_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn iam_delete_application<E>(executor: E, application_id: &str) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!("DELETE FROM applications WHERE id = $1", application_id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+pub async fn iam_set_application_client_secret_hash<E>(
+    executor: E,
+    application_id: &str,
+    client_secret_hash: &[u8],
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "UPDATE applications SET client_secret_hash = $2 WHERE id = $1",
+        application_id,
+        client_secret_hash
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn iam_clear_application_audiences<E>(
+    executor: E,
+    application_id: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM application_audiences WHERE application_id = $1",
+        application_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn iam_add_application_audience<E>(
+    executor: E,
+    application_id: &str,
+    audience: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "INSERT INTO application_audiences (audience, application_id) VALUES ($1, $2)",
+        audience,
+        application_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn iam_clear_application_redirect_uris<E>(
+    executor: E,
+    application_id: &str,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        "DELETE FROM application_redirect_uris WHERE application_id = $1",
+        application_id
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+pub async fn iam_insert_audit_log_entry<E>(
+    executor: E,
+    account_id: Option<i64>,
+    installation_id: Option<Uuid>,
+    request_kind: &str,
+    before: Option<String>,
+    after: Option<String>,
+) -> Result<(), sqlx::Error>
+where
+    E: Send + Executor<Database = Postgres>,
+{
+    sqlx::query!(
+        r#"INSERT INTO iam_audit_log (account_id, installation_id, request_kind, before, after)
+            VALUES ($1, $2, $3, $4::TEXT::JSONB, $5::TEXT::JSONB)"#,
+        account_id,
+        installation_id,
+        request_kind,
+        before,
+        after
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn iam_query_audit_log<'e, E>(
+    executor: E,
+    query: &AuditLogQuery,
+    limit: i64,
+) -> Result<Vec<AuditLogEntry>, sqlx::Error>
+where
+    E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+{
+    sqlx::query_as!(
+        AuditLogEntry,
+        r#"SELECT id, account_id, installation_id, request_kind, before::TEXT AS before, after::TEXT AS after, created_at
+            FROM iam_audit_log
+            WHERE ($1::BIGINT IS NULL OR account_id = $1)
+                AND ($2::UUID IS NULL OR installation_id = $2)
+                AND ($3::TEXT IS NULL OR request_kind = $3)
+                AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+                AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+                AND ($6::BIGINT IS NULL OR id < $6)
+            ORDER BY id DESC
+            LIMIT $7"#,
+        query.account_id,
+        query.installation_id,
+        query.request_kind.as_deref(),
+        query.since,
+        query.until,
+        query.before_id,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_database;
+
+    async fn test_account<'e, E>(executor: E) -> i64
+    where
+        E: 'e + Send + RefExecutor<'e, Database = Postgres>,
+    {
+        let login = format!("test-{}", Uuid::new_v4());
+        create_account(executor, &login, &login).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn oauth_tokens_are_kept_per_identity() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let account_id = test_account(&mut tx).await;
+        let first_id = Uuid::new_v4().to_string();
+        let second_id = Uuid::new_v4().to_string();
+        for external_id in &[&first_id, &second_id] {
+            upsert_oauth_token(
+                &mut tx,
+                account_id,
+                "twitch",
+                external_id,
+                &format!("access-{}", external_id),
+                &None,
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let unlinked = take_identity_oauth_token(&mut tx, account_id, "twitch", &first_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(unlinked.access_token, format!("access-{}", first_id));
+        let remaining = take_identity_oauth_token(&mut tx, account_id, "twitch", &second_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(remaining.access_token, format!("access-{}", second_id));
+    }
+
+    #[tokio::test]
+    async fn merge_accounts() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let source = test_account(&mut tx).await;
+        let target = test_account(&mut tx).await;
+        let external_id = Uuid::new_v4().to_string();
+        upsert_external_identity(&mut tx, "twitch", &external_id, source, "source", "Source")
+            .await
+            .unwrap();
+        let installation = create_installation(&mut tx).await.unwrap();
+        set_installation_account_id(&mut tx, installation.id, Some(source))
+            .await
+            .unwrap();
+        let jwt_id = Uuid::new_v4().to_string();
+        assert!(create_identity_verification_token(
+            &mut tx,
+            &jwt_id,
+            "merge-test",
+            jwt_id.as_bytes(),
+            source,
+            Some(installation.id),
+            Utc::now() + chrono::Duration::minutes(5),
+        )
+        .await
+        .unwrap());
+
+        let moved = iam_merge_accounts(&mut tx, source, target).await.unwrap();
+
+        assert_eq!(moved, vec![installation.id]);
+        let source_user = iam_get_user(&mut tx, source).await.unwrap().unwrap();
+        assert_eq!(source_user.merged_into_id, Some(target));
+        assert_eq!(
+            get_account_id_by_external_identity(&mut tx, "twitch", &external_id)
+                .await
+                .unwrap(),
+            Some(target)
+        );
+        assert!(!identity_verification_token_is_active(&mut tx, &jwt_id)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn merge_keeps_invalidated_tokens_invalid() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let source = test_account(&mut tx).await;
+        let target = test_account(&mut tx).await;
+        let external_id = Uuid::new_v4().to_string();
+        let expires = (Utc::now() - chrono::Duration::minutes(5)).naive_utc();
+        upsert_oauth_token(
+            &mut tx,
+            source,
+            "twitch",
+            &external_id,
+            "access",
+            &Some("refresh".to_owned()),
+            Some(expires),
+        )
+        .await
+        .unwrap();
+        let token = list_expiring_oauth_tokens(&mut tx, Utc::now().naive_utc())
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|token| token.external_id == external_id)
+            .unwrap();
+        invalidate_oauth_token(&mut tx, &token).await.unwrap();
+
+        iam_merge_accounts(&mut tx, source, target).await.unwrap();
+
+        assert!(list_expiring_oauth_tokens(&mut tx, Utc::now().naive_utc())
+            .await
+            .unwrap()
+            .iter()
+            .all(|token| token.external_id != external_id));
+    }
+
+    #[tokio::test]
+    async fn oauth_states_are_single_use() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let installation = create_installation(&mut tx).await.unwrap();
+        let state = Uuid::new_v4().to_string();
+        create_oauth_state(
+            &mut tx,
+            &state,
+            installation.id,
+            "twitch",
+            "nonce",
+            Utc::now() + chrono::Duration::minutes(10),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // The state is only valid for the provider it was created for.
+        assert!(take_oauth_state(&mut tx, &state, "other")
+            .await
+            .unwrap()
+            .is_none());
+        let taken = take_oauth_state(&mut tx, &state, "twitch")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(taken.installation_id, installation.id);
+        assert_eq!(taken.nonce, "nonce");
+        assert!(take_oauth_state(&mut tx, &state, "twitch")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn expired_oauth_states_are_rejected() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let installation = create_installation(&mut tx).await.unwrap();
+        let state = Uuid::new_v4().to_string();
+        create_oauth_state(
+            &mut tx,
+            &state,
+            installation.id,
+            "twitch",
+            "nonce",
+            Utc::now() - chrono::Duration::seconds(1),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert!(take_oauth_state(&mut tx, &state, "twitch")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn identity_verification_nonces_are_single_use() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let account_id = test_account(&mut tx).await;
+        let audience = Uuid::new_v4().to_string();
+        let expires_at = Utc::now() + chrono::Duration::minutes(5);
+
+        for (audience, created) in &[
+            (audience.as_str(), true),
+            (audience.as_str(), false),
+            ("other-audience", true),
+        ] {
+            assert_eq!(
+                create_identity_verification_token(
+                    &mut tx,
+                    &Uuid::new_v4().to_string(),
+                    audience,
+                    b"nonce",
+                    account_id,
+                    None,
+                    expires_at,
+                )
+                .await
+                .unwrap(),
+                *created,
+                "{}",
+                audience
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn identity_verification_tokens_are_counted_per_installation() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let account_id = test_account(&mut tx).await;
+        let installation = create_installation(&mut tx).await.unwrap();
+        let other_installation = create_installation(&mut tx).await.unwrap();
+        let since = Utc::now() - chrono::Duration::minutes(1);
+        for _ in 0..3 {
+            let jwt_id = Uuid::new_v4().to_string();
+            assert!(create_identity_verification_token(
+                &mut tx,
+                &jwt_id,
+                "counting",
+                jwt_id.as_bytes(),
+                account_id,
+                Some(installation.id),
+                Utc::now() + chrono::Duration::minutes(5),
+            )
+            .await
+            .unwrap());
+        }
+
+        assert_eq!(
+            count_identity_verification_tokens_since(
+                &mut tx,
+                account_id,
+                Some(installation.id),
+                since
+            )
+            .await
+            .unwrap(),
+            (3, 3)
+        );
+        assert_eq!(
+            count_identity_verification_tokens_since(
+                &mut tx,
+                account_id,
+                Some(other_installation.id),
+                since
+            )
+            .await
+            .unwrap(),
+            (3, 0)
+        );
+        assert_eq!(
+            count_identity_verification_tokens_since(&mut tx, account_id, None, since)
+                .await
+                .unwrap()
+                .0,
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn revoked_identity_verification_tokens_are_inactive() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let account_id = test_account(&mut tx).await;
+        let jwt_id = Uuid::new_v4().to_string();
+        assert!(create_identity_verification_token(
+            &mut tx,
+            &jwt_id,
+            "revocation",
+            jwt_id.as_bytes(),
+            account_id,
+            None,
+            Utc::now() + chrono::Duration::minutes(5),
+        )
+        .await
+        .unwrap());
+        assert!(identity_verification_token_is_active(&mut tx, &jwt_id)
+            .await
+            .unwrap());
+
+        revoke_identity_verification_token(&mut tx, &jwt_id)
+            .await
+            .unwrap();
+
+        assert!(!identity_verification_token_is_active(&mut tx, &jwt_id)
+            .await
+            .unwrap());
+        assert!(list_revoked_identity_verification_tokens(&mut tx)
+            .await
+            .unwrap()
+            .iter()
+            .any(|token| token.jwt_id == jwt_id));
+    }
+
+    #[tokio::test]
+    async fn deleting_oidc_grants_removes_codes_and_tokens() {
+        let pool = test_database::pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        let account_id = test_account(&mut tx).await;
+        let application_id = Uuid::new_v4().to_string();
+        create_application(&mut tx, &application_id, "grants", None, None)
+            .await
+            .unwrap();
+        let request = OidcAuthorizationRequest {
+            id: Uuid::new_v4().to_string(),
+            application_id: application_id.clone(),
+            redirect_uri: "https://example.com/callback".to_string(),
+            scope: "openid".to_string(),
+            state: None,
+            nonce: None,
+            code_challenge: "challenge".to_string(),
+        };
+        let expires_at = Utc::now() + chrono::Duration::minutes(5);
+        create_oidc_authorization_code(&mut tx, b"code", &request, account_id, expires_at)
+            .await
+            .unwrap();
+        create_oidc_access_token(
+            &mut tx,
+            b"token",
+            &application_id,
+            account_id,
+            "openid",
+            expires_at,
+        )
+        .await
+        .unwrap();
+
+        delete_oidc_grants_for_account(&mut tx, account_id)
+            .await
+            .unwrap();
+
+        assert!(take_oidc_authorization_code(&mut tx, b"code")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(get_oidc_access_token(&mut tx, b"token")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
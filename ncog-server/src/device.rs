@@ -0,0 +1,105 @@
+//! The device authorization grant (RFC 8628) for game clients that can't open
+//! a browser, such as dedicated servers, consoles, or clients running over
+//! SSH. The client shows a short code, which the user enters on the website
+//! after logging in. The installation is then logged in the same way as after
+//! an OAuth login.
+
+use crate::{database, webserver_base_url};
+use chrono::{Duration, Utc};
+use ncog_migrations::pg;
+use ncog_shared::DeviceAuthorization;
+use rand::{thread_rng, Rng};
+use uuid::Uuid;
+
+/// How long the user has to enter the code.
+const DEVICE_AUTHORIZATION_LIFETIME_MINUTES: i64 = 10;
+
+/// Consonants only, as recommended by RFC 8628, so that codes can't spell
+/// words and are easy to type on any keyboard.
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+
+fn generate_user_code() -> String {
+    let mut rng = thread_rng();
+    let code = (0..USER_CODE_LENGTH)
+        .map(|_| USER_CODE_ALPHABET[rng.gen_range(0, USER_CODE_ALPHABET.len())] as char)
+        .collect::<String>();
+    format_user_code(&code)
+}
+
+/// Splits the code in half with a dash, as it is shown to users.
+fn format_user_code(code: &str) -> String {
+    let (first, second) = code.split_at(code.len() / 2);
+    format!("{}-{}", first, second)
+}
+
+/// Normalizes a code the user typed, which may be lowercase or be missing the
+/// dash.
+pub fn normalize_user_code(user_code: &str) -> String {
+    let code = user_code
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_uppercase())
+        .collect::<String>();
+    format_user_code(&code)
+}
+
+/// Starts a device authorization for the installation, replacing any code it
+/// requested before.
+pub async fn start(installation_id: Uuid) -> anyhow::Result<DeviceAuthorization> {
+    let user_code = generate_user_code();
+    let expires_at = Utc::now() + Duration::minutes(DEVICE_AUTHORIZATION_LIFETIME_MINUTES);
+    let mut tx = pg().begin().await?;
+    database::delete_expired_device_authorizations(&mut tx).await?;
+    database::create_device_authorization(&mut tx, &user_code, installation_id, expires_at).await?;
+    tx.commit().await?;
+
+    Ok(DeviceAuthorization {
+        verification_uri: webserver_base_url()
+            .path_and_query("/device")
+            .build()?
+            .to_string(),
+        verification_uri_complete: webserver_base_url()
+            .path_and_query(format!("/device/{}", user_code).as_str())
+            .build()?
+            .to_string(),
+        user_code,
+        expires_at,
+    })
+}
+
+/// Logs the installation waiting on `user_code` into the account. Returns
+/// false if the code is unknown or has expired.
+pub async fn authorize(user_code: &str, account_id: i64) -> anyhow::Result<bool> {
+    let user_code = normalize_user_code(user_code);
+    let mut tx = pg().begin().await?;
+    let installation_id = match database::take_device_authorization(&mut tx, &user_code).await? {
+        Some(installation_id) => installation_id,
+        None => return Ok(false),
+    };
+    database::set_installation_account_id(&mut tx, installation_id, Some(account_id)).await?;
+    tx.commit().await?;
+
+    crate::pubsub::notify("installation_login", installation_id.to_string()).await?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_codes_are_normalized() {
+        let user_code = generate_user_code();
+        assert_eq!(user_code.len(), USER_CODE_LENGTH + 1);
+        assert_eq!(normalize_user_code(&user_code), user_code);
+    }
+
+    #[test]
+    fn normalize_user_code_accepts_typed_codes() {
+        assert_eq!(normalize_user_code("bcdf-ghjk"), "BCDF-GHJK");
+        assert_eq!(normalize_user_code("BCDFGHJK"), "BCDF-GHJK");
+        assert_eq!(normalize_user_code(" bcdf ghjk\n"), "BCDF-GHJK");
+    }
+}
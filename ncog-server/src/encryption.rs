@@ -0,0 +1,288 @@
+//! Envelope encryption for secrets stored in Postgres.
+//!
+//! Each value is encrypted with its own random data key, which is then
+//! encrypted ("wrapped") with a master key from the configuration. Rows record
+//! the id of the master key that wrapped their data keys, which allows master
+//! keys to be rotated with `reencrypt_all`.
+
+use crate::database;
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, NewAead},
+    Aes256Gcm,
+};
+use ncog_migrations::pg;
+use rand::{thread_rng, RngCore};
+use std::collections::HashMap;
+
+const ENVELOPE_VERSION: u8 = 1;
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+const WRAPPED_KEY_LENGTH: usize = KEY_LENGTH + TAG_LENGTH;
+const HEADER_LENGTH: usize = 1 + NONCE_LENGTH + WRAPPED_KEY_LENGTH + NONCE_LENGTH;
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncryptionError {
+    #[error("master key {0} is not configured")]
+    UnknownKey(String),
+    #[error("encrypted value is malformed")]
+    Malformed,
+    #[error("error encrypting value")]
+    Encryption,
+    #[error("encrypted value could not be decrypted")]
+    Decryption,
+}
+
+impl From<EncryptionError> for sqlx::Error {
+    fn from(err: EncryptionError) -> Self {
+        sqlx::Error::Decode(Box::new(err))
+    }
+}
+
+pub struct MasterKeys {
+    current_id: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+lazy_static::lazy_static! {
+    static ref MASTER_KEYS: MasterKeys = MasterKeys::from_env()
+        .unwrap_or_else(|err| panic!("Error loading encryption keys: {}", err));
+}
+
+impl MasterKeys {
+    /// Reads `ENCRYPTION_KEYS`, a comma separated list of `id=key` pairs where
+    /// each key is 32 base64 encoded bytes. New values are encrypted with the
+    /// key named by `ENCRYPTION_KEY_ID`, which defaults to the first key.
+    fn from_env() -> anyhow::Result<Self> {
+        let keys = std::env::var("ENCRYPTION_KEYS")
+            .map_err(|_| anyhow::anyhow!("ENCRYPTION_KEYS is not set"))?;
+        Self::parse(&keys, std::env::var("ENCRYPTION_KEY_ID").ok())
+    }
+
+    pub fn parse(keys: &str, current_id: Option<String>) -> anyhow::Result<Self> {
+        let mut first_id = None;
+        let mut parsed = HashMap::new();
+        for entry in keys
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let mut parts = entry.splitn(2, '=');
+            let id = parts.next().unwrap_or_default().trim();
+            let key = base64::decode(parts.next().unwrap_or_default().trim())?;
+            if id.is_empty() || key.len() != KEY_LENGTH {
+                anyhow::bail!("encryption key {:?} must be {} bytes", id, KEY_LENGTH);
+            }
+            first_id.get_or_insert_with(|| id.to_owned());
+            parsed.insert(
+                id.to_owned(),
+                Aes256Gcm::new(GenericArray::from_slice(&key)),
+            );
+        }
+
+        let current_id = current_id
+            .or(first_id)
+            .ok_or_else(|| anyhow::anyhow!("no encryption keys are configured"))?;
+        if !parsed.contains_key(&current_id) {
+            anyhow::bail!("encryption key {} is not configured", current_id);
+        }
+        Ok(Self {
+            current_id,
+            keys: parsed,
+        })
+    }
+
+    pub fn current_id(&self) -> &str {
+        &self.current_id
+    }
+
+    /// Encrypts `plaintext` with a new data key wrapped by the current master
+    /// key.
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let master_key = &self.keys[&self.current_id];
+        let mut data_key = [0; KEY_LENGTH];
+        thread_rng().fill_bytes(&mut data_key);
+        let key_nonce = random_nonce();
+        let data_nonce = random_nonce();
+
+        let wrapped_key = master_key
+            .encrypt(GenericArray::from_slice(&key_nonce), &data_key[..])
+            .map_err(|_| EncryptionError::Encryption)?;
+        let ciphertext = Aes256Gcm::new(GenericArray::from_slice(&data_key))
+            .encrypt(GenericArray::from_slice(&data_nonce), plaintext)
+            .map_err(|_| EncryptionError::Encryption)?;
+
+        let mut envelope = Vec::with_capacity(HEADER_LENGTH + ciphertext.len());
+        envelope.push(ENVELOPE_VERSION);
+        envelope.extend_from_slice(&key_nonce);
+        envelope.extend_from_slice(&wrapped_key);
+        envelope.extend_from_slice(&data_nonce);
+        envelope.extend_from_slice(&ciphertext);
+        Ok(envelope)
+    }
+
+    /// Decrypts an envelope created by `seal` using the master key `key_id`.
+    pub fn open(&self, key_id: &str, envelope: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let master_key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| EncryptionError::UnknownKey(key_id.to_owned()))?;
+        if envelope.len() < HEADER_LENGTH || envelope[0] != ENVELOPE_VERSION {
+            return Err(EncryptionError::Malformed);
+        }
+
+        let (key_nonce, rest) = envelope[1..].split_at(NONCE_LENGTH);
+        let (wrapped_key, rest) = rest.split_at(WRAPPED_KEY_LENGTH);
+        let (data_nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        let data_key = master_key
+            .decrypt(GenericArray::from_slice(key_nonce), wrapped_key)
+            .map_err(|_| EncryptionError::Decryption)?;
+        Aes256Gcm::new(GenericArray::from_slice(&data_key))
+            .decrypt(GenericArray::from_slice(data_nonce), ciphertext)
+            .map_err(|_| EncryptionError::Decryption)
+    }
+}
+
+fn random_nonce() -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0; NONCE_LENGTH];
+    thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// Loads the master keys, panicking if they are missing or invalid.
+pub fn initialize() {
+    lazy_static::initialize(&MASTER_KEYS);
+}
+
+/// The id of the master key new values are encrypted with.
+pub fn current_key_id() -> &'static str {
+    MASTER_KEYS.current_id()
+}
+
+pub fn seal(plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    MASTER_KEYS.seal(plaintext)
+}
+
+pub fn open(key_id: &str, envelope: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+    MASTER_KEYS.open(key_id, envelope)
+}
+
+/// `seal` for text columns, which stores the envelope base64 encoded.
+pub fn seal_str(plaintext: &str) -> Result<String, EncryptionError> {
+    Ok(base64::encode(seal(plaintext.as_bytes())?))
+}
+
+pub fn open_str(key_id: &str, sealed: &str) -> Result<String, EncryptionError> {
+    let envelope = base64::decode(sealed).map_err(|_| EncryptionError::Malformed)?;
+    String::from_utf8(open(key_id, &envelope)?).map_err(|_| EncryptionError::Malformed)
+}
+
+/// Re-encrypts every stored secret that isn't encrypted with the current
+/// master key, including values stored before encryption was introduced.
+/// Retired master keys must remain configured until this completes.
+pub async fn reencrypt_all() -> anyhow::Result<()> {
+    let mut tx = pg().begin().await?;
+
+    let tokens = database::lock_oauth_tokens_needing_reencryption(&mut tx).await?;
+    for token in &tokens {
+        database::reseal_oauth_token(&mut tx, token).await?;
+    }
+
+    let installations = database::lock_installation_keys_needing_reencryption(&mut tx).await?;
+    for (installation_id, private_key) in &installations {
+        database::reseal_installation_private_key(&mut tx, *installation_id, private_key).await?;
+    }
+
+    let signing_keys = database::lock_jwt_signing_keys_needing_reencryption(&mut tx).await?;
+    for (key_id, private_key) in &signing_keys {
+        database::reseal_jwt_signing_key(&mut tx, key_id, private_key).await?;
+    }
+
+    tx.commit().await?;
+
+    info!(
+        "Re-encrypted {} oauth tokens, {} installation keys and {} jwt signing keys with {}",
+        tokens.len(),
+        installations.len(),
+        signing_keys.len(),
+        current_key_id()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_keys(current_id: Option<&str>) -> MasterKeys {
+        MasterKeys::parse(
+            &format!(
+                "old={}, new={}",
+                base64::encode([1; KEY_LENGTH]),
+                base64::encode([2; KEY_LENGTH])
+            ),
+            current_id.map(str::to_owned),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let keys = test_keys(None);
+        assert_eq!(keys.current_id(), "old");
+        let envelope = keys.seal(b"secret").unwrap();
+        assert_eq!(keys.open("old", &envelope).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn unique_envelopes() {
+        let keys = test_keys(None);
+        assert_ne!(keys.seal(b"secret").unwrap(), keys.seal(b"secret").unwrap());
+    }
+
+    #[test]
+    fn rotation() {
+        let old_keys = test_keys(None);
+        let envelope = old_keys.seal(b"secret").unwrap();
+
+        let new_keys = test_keys(Some("new"));
+        assert_eq!(new_keys.open("old", &envelope).unwrap(), b"secret");
+        let resealed = new_keys.seal(b"secret").unwrap();
+        assert!(matches!(
+            new_keys.open("old", &resealed),
+            Err(EncryptionError::Decryption)
+        ));
+        assert_eq!(new_keys.open("new", &resealed).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn tampering() {
+        let keys = test_keys(None);
+        let mut envelope = keys.seal(b"secret").unwrap();
+        let last = envelope.len() - 1;
+        envelope[last] ^= 1;
+        assert!(matches!(
+            keys.open("old", &envelope),
+            Err(EncryptionError::Decryption)
+        ));
+        assert!(matches!(
+            keys.open("old", &envelope[..10]),
+            Err(EncryptionError::Malformed)
+        ));
+        assert!(matches!(
+            keys.open("missing", &envelope),
+            Err(EncryptionError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_configuration() {
+        assert!(MasterKeys::parse("", None).is_err());
+        assert!(MasterKeys::parse(&format!("short={}", base64::encode([1; 16])), None).is_err());
+        assert!(MasterKeys::parse(
+            &format!("old={}", base64::encode([1; KEY_LENGTH])),
+            Some("missing".to_owned())
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,341 @@
+//! Identity verification tokens, which game clients hand to game servers to
+//! prove who they are. See authentication.md for the flow.
+//!
+//! Every token is recorded until it expires. This refuses a second token for
+//! the same `(audience, nonce)`, limits how quickly tokens are issued, and
+//! lets tokens be revoked by their `jti`. Relying parties can introspect
+//! tokens to learn whether they're still active.
+
+use crate::{database, signing_keys, well_known};
+use chrono::{Duration, Utc};
+use ncog_migrations::pg;
+use ncog_shared::{
+    connect_claim, permissions::PermissionSet, AuthenticatedUser, IdentityTokenIntrospection,
+    IdentityVerificationClaims, UserProfile,
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use uuid::Uuid;
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+const TOKEN_LIFETIME_MINUTES: i64 = 5;
+const RATE_LIMIT_WINDOW_SECONDS: i64 = 60;
+const MAX_TOKENS_PER_ACCOUNT: i64 = 30;
+const MAX_TOKENS_PER_INSTALLATION: i64 = 10;
+/// Revocations must reach relying parties quickly, so the list is only cached
+/// briefly.
+const REVOKED_MAX_AGE_SECONDS: u64 = 30;
+
+/// Why a token wasn't issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssuanceRefusal {
+    /// A token was already issued for the audience and nonce.
+    NonceReused,
+    RateLimited,
+}
+
+impl IssuanceRefusal {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::NonceReused => "a token was already issued for this nonce",
+            Self::RateLimited => "too many identity verification tokens were requested",
+        }
+    }
+}
+
+/// Signs a token identifying `user` to `audience`. The token only carries the
+/// user's permissions that apply to `services`, so relying parties don't learn
+/// about grants elsewhere.
+pub async fn issue(
+    user: &AuthenticatedUser,
+    installation_id: Option<Uuid>,
+    audience: String,
+    nonce: [u8; 32],
+    services: Vec<String>,
+) -> anyhow::Result<Result<String, IssuanceRefusal>> {
+    let account_id = user.profile.id;
+    let issuance_time = Utc::now();
+    let expiration_time = issuance_time + Duration::minutes(TOKEN_LIFETIME_MINUTES);
+    let jwt_id = Uuid::new_v4().to_string();
+
+    let mut tx = pg().begin().await?;
+    // Concurrent requests for the account would otherwise all pass the rate
+    // limit before any of them are recorded.
+    database::lock_account(&mut tx, account_id).await?;
+    database::delete_expired_identity_verification_tokens(&mut tx).await?;
+    let (account_tokens, installation_tokens) = database::count_identity_verification_tokens_since(
+        &mut tx,
+        account_id,
+        installation_id,
+        issuance_time - Duration::seconds(RATE_LIMIT_WINDOW_SECONDS),
+    )
+    .await?;
+    if is_rate_limited(account_tokens, installation_tokens) {
+        return Ok(Err(IssuanceRefusal::RateLimited));
+    }
+    if !database::create_identity_verification_token(
+        &mut tx,
+        &jwt_id,
+        &audience,
+        &nonce,
+        account_id,
+        installation_id,
+        expiration_time,
+    )
+    .await?
+    {
+        return Ok(Err(IssuanceRefusal::NonceReused));
+    }
+
+    let claims = IdentityVerificationClaims {
+        issuer: well_known::issuer(),
+        subject: account_id.to_string(),
+        audience,
+        nonce,
+        issuance_time: issuance_time.timestamp() as u64,
+        expiration_time: expiration_time.timestamp() as u64,
+        jwt_id,
+        ncog_profile: user.profile.clone(),
+        ncog_permissions: user.permissions.limited_to_services(&services).into(),
+        ncog_services: services,
+    };
+    let token = signing_keys::sign(&claims).await?;
+    tx.commit().await?;
+
+    Ok(Ok(token))
+}
+
+/// Whether another token can't be issued, given how many were issued to the
+/// account and to the installation within `RATE_LIMIT_WINDOW_SECONDS`.
+fn is_rate_limited(account_tokens: i64, installation_tokens: i64) -> bool {
+    account_tokens >= MAX_TOKENS_PER_ACCOUNT || installation_tokens >= MAX_TOKENS_PER_INSTALLATION
+}
+
+/// Checks whether the token can still be trusted. Tokens stop being active
+/// when they're revoked, or when their account is suspended or loses the
+/// `connect_claim`, even if they haven't expired.
+pub async fn introspect(token: &str) -> anyhow::Result<IdentityTokenIntrospection> {
+    let claims = match signing_keys::verify::<IdentityVerificationClaims>(token).await? {
+        Some(claims) => claims,
+        None => return Ok(IdentityTokenIntrospection::inactive()),
+    };
+    if !database::identity_verification_token_is_active(&pg(), &claims.jwt_id).await? {
+        return Ok(IdentityTokenIntrospection::inactive());
+    }
+    let account_id = claims.subject.parse::<i64>()?;
+    let profile = match database::get_profile_by_account_id(&pg(), account_id).await? {
+        Some(profile) => profile,
+        None => return Ok(IdentityTokenIntrospection::inactive()),
+    };
+    let permissions = database::load_permissions_for(&pg(), account_id).await?;
+    let suspended = database::get_account_suspension(&pg(), account_id)
+        .await?
+        .is_some();
+
+    Ok(account_introspection(
+        claims,
+        profile,
+        &permissions,
+        suspended,
+    ))
+}
+
+/// Introspects a token that hasn't been revoked from its account's current
+/// state.
+fn account_introspection(
+    claims: IdentityVerificationClaims,
+    profile: UserProfile,
+    permissions: &PermissionSet,
+    suspended: bool,
+) -> IdentityTokenIntrospection {
+    if suspended || !permissions.allowed(&connect_claim()) {
+        return IdentityTokenIntrospection::inactive();
+    }
+
+    IdentityTokenIntrospection {
+        active: true,
+        jwt_id: Some(claims.jwt_id),
+        subject: Some(claims.subject),
+        audience: Some(claims.audience),
+        expiration_time: Some(claims.expiration_time),
+        ncog_profile: Some(profile),
+        ncog_permissions: Some(
+            permissions
+                .limited_to_services(&claims.ncog_services)
+                .into(),
+        ),
+    }
+}
+
+/// Revokes the token. Holding a token is enough to revoke it, which lets
+/// anyone who finds a leaked token make it useless.
+pub async fn revoke(token: &str) -> anyhow::Result<()> {
+    if let Some(claims) = signing_keys::verify::<IdentityVerificationClaims>(token).await? {
+        database::revoke_identity_verification_token(&pg(), &claims.jwt_id).await?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    token: String,
+}
+
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let revoked_list = warp::get()
+        .and(warp::path!("identity-tokens" / "revoked"))
+        .and_then(revoked);
+    let introspection = warp::post()
+        .and(warp::path!("identity-tokens" / "introspect"))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::form())
+        .and_then(introspection);
+    let revocation = warp::post()
+        .and(warp::path!("identity-tokens" / "revoke"))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::form())
+        .and_then(revocation);
+    revoked_list.or(introspection).or(revocation)
+}
+
+/// Callers aren't authenticated, since holding the token is enough to learn
+/// what it says. See authentication.md.
+async fn introspection(request: TokenRequest) -> Result<warp::reply::Response, Infallible> {
+    match introspect(&request.token).await {
+        Ok(introspection) => Ok(warp::reply::with_header(
+            warp::reply::json(&introspection),
+            "Cache-Control",
+            "no-store",
+        )
+        .into_response()),
+        Err(err) => {
+            error!("Error introspecting identity token: {:?}", err);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// As in OAuth 2.0 Token Revocation (RFC 7009), invalid tokens are not an
+/// error, since there is nothing left to revoke.
+async fn revocation(request: TokenRequest) -> Result<warp::reply::Response, Infallible> {
+    match revoke(&request.token).await {
+        Ok(()) => Ok(StatusCode::OK.into_response()),
+        Err(err) => {
+            error!("Error revoking identity token: {:?}", err);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn revoked() -> Result<warp::reply::Response, Infallible> {
+    match database::list_revoked_identity_verification_tokens(&pg()).await {
+        Ok(tokens) => Ok(warp::reply::with_header(
+            warp::reply::json(&tokens),
+            "Cache-Control",
+            format!("public, max-age={}", REVOKED_MAX_AGE_SECONDS),
+        )
+        .into_response()),
+        Err(err) => {
+            error!("Error loading revoked identity tokens: {:?}", err);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ncog_shared::permissions::{Claim, Statement};
+
+    fn profile() -> UserProfile {
+        UserProfile {
+            id: 1,
+            login: Some("ecton".to_owned()),
+            display_name: Some("Ecton".to_owned()),
+        }
+    }
+
+    fn claims() -> IdentityVerificationClaims {
+        IdentityVerificationClaims {
+            issuer: "https://ncog.example".to_owned(),
+            subject: "1".to_owned(),
+            audience: "game".to_owned(),
+            expiration_time: 2,
+            issuance_time: 1,
+            jwt_id: "jti".to_owned(),
+            nonce: [0; 32],
+            ncog_profile: profile(),
+            ncog_services: vec!["game".to_owned()],
+            ncog_permissions: PermissionSet::default().into(),
+        }
+    }
+
+    fn statement(service: &str, action: &str, allow: bool) -> Statement {
+        Statement {
+            id: None,
+            role_id: None,
+            service: Some(service.to_owned()),
+            resource_type: None,
+            resource_id: None,
+            action: Some(action.to_owned()),
+            allow,
+        }
+    }
+
+    fn connecting_permissions() -> PermissionSet {
+        PermissionSet::from(vec![
+            statement("ncog", "connect", true),
+            statement("game", "play", true),
+            statement("other-game", "play", true),
+        ])
+    }
+
+    #[test]
+    fn active_introspection_limits_permissions() {
+        let introspection =
+            account_introspection(claims(), profile(), &connecting_permissions(), false);
+        assert!(introspection.active);
+        assert_eq!(introspection.jwt_id.as_deref(), Some("jti"));
+        assert_eq!(introspection.ncog_profile, Some(profile()));
+        let permissions = PermissionSet::from(introspection.ncog_permissions.unwrap());
+        assert!(permissions.allowed(&Claim::new("game", None, None, "play")));
+        assert!(!permissions.allowed(&Claim::new("other-game", None, None, "play")));
+    }
+
+    #[test]
+    fn suspended_accounts_are_inactive() {
+        let introspection =
+            account_introspection(claims(), profile(), &connecting_permissions(), true);
+        assert!(!introspection.active);
+        assert!(introspection.ncog_profile.is_none());
+        assert!(introspection.ncog_permissions.is_none());
+    }
+
+    #[test]
+    fn accounts_without_connect_claim_are_inactive() {
+        let permissions = PermissionSet::from(vec![
+            statement("game", "play", true),
+            statement("ncog", "connect", false),
+        ]);
+        let introspection = account_introspection(claims(), profile(), &permissions, false);
+        assert!(!introspection.active);
+        assert!(introspection.ncog_permissions.is_none());
+    }
+
+    #[test]
+    fn installation_rate_limit() {
+        assert!(!is_rate_limited(0, MAX_TOKENS_PER_INSTALLATION - 1));
+        assert!(is_rate_limited(
+            MAX_TOKENS_PER_INSTALLATION,
+            MAX_TOKENS_PER_INSTALLATION
+        ));
+        // Other installations of the account can still be issued tokens.
+        assert!(!is_rate_limited(MAX_TOKENS_PER_INSTALLATION, 0));
+    }
+
+    #[test]
+    fn account_rate_limit() {
+        assert!(!is_rate_limited(MAX_TOKENS_PER_ACCOUNT - 1, 0));
+        assert!(is_rate_limited(MAX_TOKENS_PER_ACCOUNT, 0));
+    }
+}
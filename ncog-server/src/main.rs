@@ -7,8 +7,16 @@ use tracing_subscriber::prelude::*;
 use warp::{Filter, Reply};
 
 pub mod database;
+mod device;
+mod encryption;
+mod identity_tokens;
+mod oauth;
+mod oidc;
 mod pubsub;
-mod twitch;
+mod signing_keys;
+#[cfg(test)]
+mod test_database;
+mod well_known;
 // mod randomnames;
 mod websockets;
 
@@ -48,6 +56,7 @@ async fn main() {
     dotenv::dotenv().expect("Error initializing environment");
     initialize_logging();
     info!("server starting up");
+    encryption::initialize();
 
     let base_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_owned());
     let base_dir = Path::new(&base_dir);
@@ -57,6 +66,29 @@ async fn main() {
         .expect("Error running migrations");
 
     info!("Done running migrations");
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(String::as_str) {
+        Some("reencrypt") => {
+            encryption::reencrypt_all()
+                .await
+                .expect("Error re-encrypting stored secrets");
+            return;
+        }
+        Some("applications") => {
+            oidc::command(&args[1..])
+                .await
+                .expect("Error managing applications");
+            return;
+        }
+        Some("jwt-keys") => {
+            signing_keys::command(&args[1..])
+                .await
+                .expect("Error managing jwt signing keys");
+            return;
+        }
+        _ => {}
+    }
+
     let websocket_server = websockets::initialize();
     let notify_server = websocket_server.clone();
 
@@ -65,6 +97,8 @@ async fn main() {
             .await
             .expect("Error on pubsub thread")
     });
+    tokio::spawn(oauth::refresh_loop());
+    tokio::spawn(signing_keys::rotation_loop());
 
     let healthcheck = warp::get()
         .and(warp::path("__healthcheck"))
@@ -99,10 +133,13 @@ async fn main() {
         }
     });
 
-    let auth = twitch::callback();
+    let auth = oauth::callback();
 
     let api = warp::path("v1").and(websocket_route.or(auth));
     let routes = healthcheck
+        .or(well_known::routes())
+        .or(identity_tokens::routes())
+        .or(oidc::routes())
         .or(api)
         .with(custom_logger)
         .with(warp::reply::with::header(
@@ -0,0 +1,502 @@
+use crate::{api_server_base_url, database, database::OAuthState, webserver_base_url};
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDateTime, Utc};
+use ncog_migrations::pg;
+use ncog_shared::LoginFailure;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
+use uuid::Uuid;
+use warp::{Filter, Rejection, Reply};
+
+mod configured;
+mod lifecycle;
+mod twitch;
+
+pub use configured::{ConfiguredProvider, ProviderConfig, ProviderEndpoints};
+pub use lifecycle::{refresh_loop, revoke};
+
+/// An identity provider accounts can log in with using the OAuth 2.0
+/// authorization code flow.
+#[async_trait]
+pub trait OAuthProvider: Send + Sync {
+    /// The name used in requests and callback urls, such as `twitch`.
+    fn name(&self) -> &str;
+
+    /// The url to send the user to, which redirects back to `callback_uri`
+    /// with a code and `state`. Providers issuing ID tokens include `nonce`
+    /// in them.
+    fn authorization_url(&self, state: &str, nonce: &str) -> String;
+
+    async fn exchange_code(&self, code: &str) -> anyhow::Result<OAuthTokens>;
+
+    /// Verifies the signature and claims of an OpenID Connect ID token issued
+    /// alongside the tokens, including that it contains `nonce`.
+    async fn validate_id_token(&self, id_token: &str, nonce: &str)
+        -> anyhow::Result<IdTokenClaims>;
+
+    async fn fetch_profile(&self, tokens: &OAuthTokens) -> anyhow::Result<ExternalProfile>;
+
+    /// Exchanges a refresh token for new tokens. Providers may not issue a
+    /// new refresh token, in which case the previous one remains valid.
+    async fn refresh_tokens(&self, refresh_token: &str) -> Result<OAuthTokens, TokenError>;
+
+    /// Revokes `token` at the provider. `token_type_hint` is either
+    /// `access_token` or `refresh_token`. Providers without a revocation
+    /// endpoint do nothing.
+    async fn revoke_token(&self, token: &str, token_type_hint: &str) -> Result<(), TokenError>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenError {
+    /// The provider no longer accepts the token.
+    #[error("the provider rejected the token")]
+    Rejected,
+    #[error("error communicating with the provider: {0}")]
+    Request(#[from] reqwest::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct OAuthTokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// The number of seconds the access token is valid for.
+    pub expires_in: Option<u64>,
+    pub id_token: Option<String>,
+}
+
+impl OAuthTokens {
+    /// When the access token expires, in UTC.
+    pub fn expires(&self) -> Option<NaiveDateTime> {
+        self.expires_in
+            .map(|expires_in| Utc::now().naive_utc() + Duration::seconds(expires_in as i64))
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IdTokenClaims {
+    #[serde(rename = "iss")]
+    pub issuer: Option<String>,
+    #[serde(rename = "sub")]
+    pub subject: Option<String>,
+    /// Either a single audience or a list of them in the token.
+    #[serde(rename = "aud", default, deserialize_with = "deserialize_audience")]
+    pub audience: Vec<String>,
+    #[serde(rename = "exp")]
+    pub expiration_time: Option<u64>,
+    #[serde(rename = "iat")]
+    pub issuance_time: Option<u64>,
+    pub nonce: Option<String>,
+}
+
+fn deserialize_audience<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(
+        match <Audience as serde::Deserialize>::deserialize(deserializer)? {
+            Audience::One(audience) => vec![audience],
+            Audience::Many(audiences) => audiences,
+        },
+    )
+}
+
+/// The user's identity as reported by a provider.
+#[derive(Debug, Clone)]
+pub struct ExternalProfile {
+    /// The provider's unique, stable id for the user.
+    pub id: String,
+    pub login: String,
+    pub display_name: Option<String>,
+}
+
+lazy_static::lazy_static! {
+    static ref PROVIDERS: HashMap<String, Arc<dyn OAuthProvider>> = load_providers();
+}
+
+/// Loads the providers named in the comma separated `OAUTH_PROVIDERS`
+/// environment variable, which defaults to `twitch`.
+fn load_providers() -> HashMap<String, Arc<dyn OAuthProvider>> {
+    let names = std::env::var("OAUTH_PROVIDERS").unwrap_or_else(|_| twitch::NAME.to_owned());
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let defaults = match name {
+                twitch::NAME => Some(twitch::defaults()),
+                _ => None,
+            };
+            let config = ProviderConfig::from_env(name, defaults)
+                .unwrap_or_else(|err| panic!("Error configuring oauth provider {}: {}", name, err));
+            let provider: Arc<dyn OAuthProvider> = Arc::new(ConfiguredProvider::new(config));
+            (name.to_owned(), provider)
+        })
+        .collect()
+}
+
+pub fn provider(name: &str) -> Option<Arc<dyn OAuthProvider>> {
+    PROVIDERS.get(name).cloned()
+}
+
+/// How long a login started with `authorization_url` can be completed for.
+const LOGIN_STATE_LIFETIME_MINUTES: i64 = 10;
+
+/// A reason a login callback failed that is reported to the user.
+#[derive(Debug, thiserror::Error)]
+pub enum LoginError {
+    #[error("unknown login provider")]
+    UnknownProvider,
+    #[error("this login link is invalid, expired, or was already used")]
+    InvalidState,
+    #[error("the login response did not match the login request")]
+    NonceMismatch,
+    #[error("the user denied access")]
+    Denied,
+    #[error("the provider returned {error}: {description:?}")]
+    Provider {
+        error: String,
+        description: Option<String>,
+    },
+    #[error("the provider rejected the authorization code")]
+    CodeExchange(#[source] anyhow::Error),
+    #[error("the identity is linked to another account")]
+    IdentityInUse,
+    #[error("the ID token and profile are for different users")]
+    SubjectMismatch,
+}
+
+impl LoginError {
+    fn failure(&self) -> LoginFailure {
+        match self {
+            Self::UnknownProvider => LoginFailure::UnknownProvider,
+            Self::InvalidState | Self::NonceMismatch => LoginFailure::InvalidState,
+            Self::Denied => LoginFailure::Denied,
+            Self::Provider { .. } | Self::CodeExchange(_) | Self::SubjectMismatch => {
+                LoginFailure::Provider
+            }
+            Self::IdentityInUse => LoginFailure::IdentityInUse,
+        }
+    }
+}
+
+pub fn random_token() -> String {
+    thread_rng().sample_iter(&Alphanumeric).take(32).collect()
+}
+
+/// Starts a login for the installation, returning the url to send the user
+/// to. The `state` and nonce in the url are single use and expire after
+/// `LOGIN_STATE_LIFETIME_MINUTES`. When `link_account_id` is set, the
+/// identity is linked to that account instead of being logged into.
+pub async fn authorization_url(
+    provider: &dyn OAuthProvider,
+    installation_id: Uuid,
+    link_account_id: Option<i64>,
+) -> anyhow::Result<String> {
+    let state = random_token();
+    let nonce = random_token();
+    let expires_at = Utc::now() + Duration::minutes(LOGIN_STATE_LIFETIME_MINUTES);
+
+    let mut tx = pg().begin().await?;
+    database::delete_expired_oauth_states(&mut tx).await?;
+    database::create_oauth_state(
+        &mut tx,
+        &state,
+        installation_id,
+        provider.name(),
+        &nonce,
+        expires_at,
+        link_account_id,
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(provider.authorization_url(&state, &nonce))
+}
+
+pub fn callback_uri(provider_name: &str) -> String {
+    api_server_base_url()
+        .path_and_query(format!("/v1/auth/callback/{}", provider_name).as_str())
+        .build()
+        .unwrap()
+        .to_string()
+}
+
+/// The query parameters of a provider's redirect. On success `code` and
+/// `state` are present, otherwise `error` describes what went wrong.
+#[derive(serde::Deserialize)]
+struct OAuthCallback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+pub fn callback() -> impl warp::Filter<Extract = (impl warp::Reply,), Error = Rejection> + Copy {
+    warp::path!("auth" / "callback" / String)
+        .and(warp::query())
+        .and_then(
+            |provider_name: String, callback: OAuthCallback| async move {
+                callback.respond(provider_name).await
+            },
+        )
+}
+
+impl OAuthCallback {
+    async fn respond(self, provider_name: String) -> Result<warp::reply::Response, Infallible> {
+        // The installation is only known once the state has been verified.
+        let mut installation_id = None;
+        let result = self.complete(&provider_name, &mut installation_id).await;
+
+        let path = match result {
+            Ok(path) => path,
+            Err(err) => {
+                let failure = match err.downcast_ref::<LoginError>() {
+                    Some(login_error) => login_error.failure(),
+                    None => LoginFailure::ServerError,
+                };
+                error!(
+                    provider = provider_name.as_str(),
+                    installation_id = ?installation_id,
+                    failure = failure.code(),
+                    error = ?err,
+                    "OAuth login failed"
+                );
+
+                if let Some(installation_id) = installation_id {
+                    if let Err(err) = crate::pubsub::notify(
+                        "installation_login_failed",
+                        format!("{} {}", installation_id, failure.code()),
+                    )
+                    .await
+                    {
+                        error!("Error sending login failure notice: {:?}", err);
+                    }
+                }
+
+                format!("/login/error/{}", failure.code())
+            }
+        };
+
+        Ok(warp::redirect::redirect(
+            webserver_base_url()
+                .path_and_query(path.as_str())
+                .build()
+                .unwrap(),
+        )
+        .into_response())
+    }
+
+    /// Returns the path to send the user to once the login completes, which
+    /// is the consent page of an application they were logging into if there
+    /// is one.
+    async fn complete(
+        self,
+        provider_name: &str,
+        installation_id: &mut Option<Uuid>,
+    ) -> anyhow::Result<String> {
+        let provider = provider(provider_name).ok_or(LoginError::UnknownProvider)?;
+        let state = self.state.ok_or(LoginError::InvalidState)?;
+        let state = database::take_oauth_state(&pg(), &state, provider.name())
+            .await?
+            .ok_or(LoginError::InvalidState)?;
+        *installation_id = Some(state.installation_id);
+
+        if let Some(error) = self.error {
+            if error == "access_denied" {
+                return Err(LoginError::Denied.into());
+            }
+            return Err(LoginError::Provider {
+                error,
+                description: self.error_description,
+            }
+            .into());
+        }
+
+        let code = self.code.ok_or(LoginError::InvalidState)?;
+        login(provider.as_ref(), &state, &code).await?;
+
+        if state.link_account_id.is_some() {
+            return Ok("/profile".to_owned());
+        }
+        Ok(
+            match database::pending_oidc_authorization_request_id(&pg(), state.installation_id)
+                .await?
+            {
+                Some(request_id) => format!("/authorize/{}", request_id),
+                None => "/".to_owned(),
+            },
+        )
+    }
+}
+
+/// Completes a login by exchanging `code` for tokens. The provider's profile
+/// is then linked to the account in `state`, or logged into, creating an
+/// account if the identity hasn't been seen before.
+pub async fn login(
+    provider: &dyn OAuthProvider,
+    state: &OAuthState,
+    code: &str,
+) -> anyhow::Result<()> {
+    let tokens = provider
+        .exchange_code(code)
+        .await
+        .map_err(LoginError::CodeExchange)?;
+    let id_token_claims = match &tokens.id_token {
+        Some(id_token) => Some(provider.validate_id_token(id_token, &state.nonce).await?),
+        None => None,
+    };
+    let profile = provider.fetch_profile(&tokens).await?;
+    // The profile is fetched separately from the validated ID token, so it
+    // has to be for the same user before the identity can be trusted.
+    if let Some(claims) = id_token_claims {
+        if claims.subject.as_deref() != Some(profile.id.as_str()) {
+            return Err(LoginError::SubjectMismatch.into());
+        }
+    }
+    let display_name = profile
+        .display_name
+        .clone()
+        .unwrap_or_else(|| profile.login.clone());
+    let mut tx = pg().begin().await?;
+
+    let identity_account_id =
+        database::get_account_id_by_external_identity(&mut tx, provider.name(), &profile.id)
+            .await?;
+    let account_id = if let Some(account_id) = state.link_account_id {
+        if identity_account_id.map_or(false, |existing| existing != account_id) {
+            return Err(LoginError::IdentityInUse.into());
+        }
+        account_id
+    } else {
+        // Log into the account the identity is linked to, falling back to the
+        // installation's account and then to creating a new one.
+        let account_id = match identity_account_id {
+            Some(account_id) => account_id,
+            None => match database::get_profile_by_installation_id(&mut tx, state.installation_id)
+                .await?
+            {
+                Some(account) => account.id,
+                None => database::create_account(&mut tx, &profile.login, &display_name).await?,
+            },
+        };
+        database::set_installation_account_id(&mut tx, state.installation_id, Some(account_id))
+            .await?;
+        account_id
+    };
+    database::set_account_names_if_unset(&mut tx, account_id, &profile.login, &display_name)
+        .await?;
+
+    database::upsert_external_identity(
+        &mut tx,
+        provider.name(),
+        &profile.id,
+        account_id,
+        &profile.login,
+        &display_name,
+    )
+    .await?;
+    database::upsert_oauth_token(
+        &mut tx,
+        account_id,
+        provider.name(),
+        &profile.id,
+        &tokens.access_token,
+        &tokens.refresh_token,
+        tokens.expires(),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    if state.link_account_id.is_none() {
+        crate::pubsub::notify("installation_login", state.installation_id.to_string()).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A provider whose ID token and profile are for different users.
+    struct MismatchedProvider;
+
+    #[async_trait]
+    impl OAuthProvider for MismatchedProvider {
+        fn name(&self) -> &str {
+            "mismatched"
+        }
+
+        fn authorization_url(&self, _state: &str, _nonce: &str) -> String {
+            unimplemented!()
+        }
+
+        async fn exchange_code(&self, _code: &str) -> anyhow::Result<OAuthTokens> {
+            Ok(OAuthTokens {
+                access_token: "access".to_owned(),
+                refresh_token: None,
+                expires_in: None,
+                id_token: Some("id-token".to_owned()),
+            })
+        }
+
+        async fn validate_id_token(
+            &self,
+            _id_token: &str,
+            nonce: &str,
+        ) -> anyhow::Result<IdTokenClaims> {
+            Ok(IdTokenClaims {
+                issuer: None,
+                subject: Some("external-user".to_owned()),
+                audience: Vec::new(),
+                expiration_time: None,
+                issuance_time: None,
+                nonce: Some(nonce.to_owned()),
+            })
+        }
+
+        async fn fetch_profile(&self, _tokens: &OAuthTokens) -> anyhow::Result<ExternalProfile> {
+            Ok(ExternalProfile {
+                id: "other-user".to_owned(),
+                login: "other".to_owned(),
+                display_name: None,
+            })
+        }
+
+        async fn refresh_tokens(&self, _refresh_token: &str) -> Result<OAuthTokens, TokenError> {
+            unimplemented!()
+        }
+
+        async fn revoke_token(
+            &self,
+            _token: &str,
+            _token_type_hint: &str,
+        ) -> Result<(), TokenError> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn login_rejects_profile_for_other_user() {
+        let state = OAuthState {
+            installation_id: Uuid::nil(),
+            nonce: "nonce".to_owned(),
+            link_account_id: None,
+        };
+
+        let err = login(&MismatchedProvider, &state, "code")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LoginError>(),
+            Some(LoginError::SubjectMismatch)
+        ));
+    }
+}
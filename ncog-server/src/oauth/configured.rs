@@ -0,0 +1,719 @@
+use super::{
+    callback_uri, ExternalProfile, IdTokenClaims, LoginError, OAuthProvider, OAuthTokens,
+    TokenError,
+};
+use async_trait::async_trait;
+use ncog_shared::{jsonwebtoken, jwk::JwtKeySet};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+use url::Url;
+
+/// Where a provider's OAuth endpoints live. Each can be overridden with an
+/// environment variable, such as `TWITCH_TOKEN_URL`, so that tests can point
+/// a provider at a local mock.
+#[derive(Debug, Clone, Default)]
+pub struct ProviderEndpoints {
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+    /// The OAuth 2.0 Token Revocation (RFC 7009) endpoint, if supported.
+    pub revoke_url: Option<String>,
+    /// The JSON Web Key Set used to verify ID tokens. Providers without one
+    /// don't issue ID tokens.
+    pub jwks_url: Option<String>,
+    /// The expected `iss` claim of ID tokens.
+    pub issuer: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProviderConfig {
+    pub name: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub endpoints: ProviderEndpoints,
+    pub scopes: Vec<String>,
+    /// JSON pointers locating the profile fields within the userinfo
+    /// response, such as `/data/0/id`.
+    pub user_id_pointer: String,
+    pub user_login_pointer: String,
+    pub user_display_name_pointer: Option<String>,
+    /// Some providers, like Twitch, require the client id on API requests in
+    /// addition to the access token.
+    pub send_client_id_header: bool,
+}
+
+impl ProviderConfig {
+    /// Reads the configuration for `name` from environment variables
+    /// prefixed with the uppercased name. Anything not in the environment is
+    /// taken from `defaults`.
+    pub fn from_env(name: &str, defaults: Option<ProviderConfig>) -> anyhow::Result<Self> {
+        let prefix = name.to_uppercase();
+        let defaults = defaults.unwrap_or_default();
+        let var = |suffix: &str| std::env::var(format!("{}_{}", prefix, suffix)).ok();
+        let required = |suffix: &str, default: String| {
+            var(suffix)
+                .or_else(|| Some(default).filter(|value| !value.is_empty()))
+                .ok_or_else(|| anyhow::anyhow!("{}_{} is not set", prefix, suffix))
+        };
+
+        Ok(Self {
+            name: name.to_owned(),
+            client_id: required("CLIENT_ID", defaults.client_id)?,
+            client_secret: required("CLIENT_SECRET", defaults.client_secret)?,
+            endpoints: ProviderEndpoints {
+                authorize_url: required("AUTHORIZE_URL", defaults.endpoints.authorize_url)?,
+                token_url: required("TOKEN_URL", defaults.endpoints.token_url)?,
+                userinfo_url: required("USERINFO_URL", defaults.endpoints.userinfo_url)?,
+                revoke_url: var("REVOKE_URL").or(defaults.endpoints.revoke_url),
+                jwks_url: var("JWKS_URL").or(defaults.endpoints.jwks_url),
+                issuer: var("ISSUER").or(defaults.endpoints.issuer),
+            },
+            scopes: match var("SCOPES") {
+                Some(scopes) => scopes.split_whitespace().map(str::to_owned).collect(),
+                None => defaults.scopes,
+            },
+            user_id_pointer: required("USER_ID_POINTER", defaults.user_id_pointer)?,
+            user_login_pointer: required("USER_LOGIN_POINTER", defaults.user_login_pointer)?,
+            user_display_name_pointer: var("USER_DISPLAY_NAME_POINTER")
+                .or(defaults.user_display_name_pointer),
+            send_client_id_header: var("SEND_CLIENT_ID_HEADER")
+                .map(|value| value == "true" || value == "1")
+                .unwrap_or(defaults.send_client_id_header),
+        })
+    }
+}
+
+/// An `OAuthProvider` driven entirely by a `ProviderConfig`, which covers
+/// providers following the OAuth 2.0 and OpenID Connect specifications.
+pub struct ConfiguredProvider {
+    config: ProviderConfig,
+    client: reqwest::Client,
+}
+
+impl ConfiguredProvider {
+    pub fn new(config: ProviderConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Posts `params` to the token endpoint along with the client
+    /// credentials. The provider rejecting the request as a bad request or
+    /// unauthorized is reported as `TokenError::Rejected`.
+    async fn request_tokens(&self, params: &[(&str, &str)]) -> Result<TokenResponse, TokenError> {
+        let mut form = vec![
+            ("client_id", self.config.client_id.as_str()),
+            ("client_secret", self.config.client_secret.as_str()),
+        ];
+        form.extend_from_slice(params);
+        let response = self
+            .client
+            .post(&self.config.endpoints.token_url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&form)
+            .send()
+            .await?;
+        if is_rejection(response.status()) {
+            return Err(TokenError::Rejected);
+        }
+
+        Ok(response.error_for_status()?.json().await?)
+    }
+}
+
+fn is_rejection(status: StatusCode) -> bool {
+    status == StatusCode::BAD_REQUEST || status == StatusCode::UNAUTHORIZED
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+    id_token: Option<String>,
+}
+
+impl From<TokenResponse> for OAuthTokens {
+    fn from(response: TokenResponse) -> Self {
+        Self {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_in: response.expires_in,
+            id_token: response.id_token,
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for ConfiguredProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn authorization_url(&self, state: &str, nonce: &str) -> String {
+        Url::parse_with_params(
+            &self.config.endpoints.authorize_url,
+            &[
+                ("client_id", self.config.client_id.clone()),
+                ("scope", self.config.scopes.join(" ")),
+                ("response_type", "code".to_owned()),
+                ("redirect_uri", callback_uri(self.name())),
+                ("state", state.to_owned()),
+                ("nonce", nonce.to_owned()),
+            ],
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    async fn exchange_code(&self, code: &str) -> anyhow::Result<OAuthTokens> {
+        let redirect_uri = callback_uri(self.name());
+        let response = self
+            .request_tokens(&[
+                ("code", code),
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", &redirect_uri),
+            ])
+            .await?;
+
+        if self.config.endpoints.jwks_url.is_some() && response.id_token.is_none() {
+            anyhow::bail!("{} did not return an id token", self.name());
+        }
+
+        Ok(response.into())
+    }
+
+    async fn refresh_tokens(&self, refresh_token: &str) -> Result<OAuthTokens, TokenError> {
+        let response = self
+            .request_tokens(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ])
+            .await?;
+        Ok(response.into())
+    }
+
+    async fn revoke_token(&self, token: &str, token_type_hint: &str) -> Result<(), TokenError> {
+        let revoke_url = match &self.config.endpoints.revoke_url {
+            Some(revoke_url) => revoke_url,
+            None => return Ok(()),
+        };
+        let response = self
+            .client
+            .post(revoke_url)
+            .form(&[
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+                ("token", token),
+                ("token_type_hint", token_type_hint),
+            ])
+            .send()
+            .await?;
+        if is_rejection(response.status()) {
+            return Err(TokenError::Rejected);
+        }
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    async fn validate_id_token(
+        &self,
+        id_token: &str,
+        nonce: &str,
+    ) -> anyhow::Result<IdTokenClaims> {
+        let jwks_url = self
+            .config
+            .endpoints
+            .jwks_url
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("{} does not issue id tokens", self.name()))?;
+        let jwt_keys: JwtKeySet = self
+            .client
+            .get(jwks_url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let key_id = jsonwebtoken::decode_header(id_token)?.kid;
+        let jwt_key = jwt_keys
+            .keys
+            .into_iter()
+            .find(|key| {
+                key.key_type == "RSA" && (key_id.is_none() || key_id.as_ref() == Some(&key.key_id))
+            })
+            .ok_or_else(|| anyhow::anyhow!("{} has no matching RSA key", self.name()))?;
+
+        // parse_token verifies the signature and expiration.
+        let token = jwt_key.parse_token::<IdTokenClaims>(id_token)?;
+        if token.claims.expiration_time.is_none()
+            || token.claims.issuer != self.config.endpoints.issuer
+            || !token.claims.audience.contains(&self.config.client_id)
+        {
+            anyhow::bail!("Invalid JWT Token");
+        }
+        if token.claims.nonce.as_deref() != Some(nonce) {
+            return Err(LoginError::NonceMismatch.into());
+        }
+
+        Ok(token.claims)
+    }
+
+    async fn fetch_profile(&self, tokens: &OAuthTokens) -> anyhow::Result<ExternalProfile> {
+        let mut request = self
+            .client
+            .get(&self.config.endpoints.userinfo_url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Bearer {}", tokens.access_token),
+            );
+        if self.config.send_client_id_header {
+            request = request.header("client-id", &self.config.client_id);
+        }
+        let userinfo: Value = request.send().await?.error_for_status()?.json().await?;
+
+        let field = |pointer: &str| {
+            userinfo.pointer(pointer).and_then(|value| match value {
+                Value::String(value) => Some(value.clone()),
+                Value::Number(value) => Some(value.to_string()),
+                _ => None,
+            })
+        };
+        let id = field(&self.config.user_id_pointer)
+            .ok_or_else(|| anyhow::anyhow!("{} profile is missing an id", self.name()))?;
+        let login = field(&self.config.user_login_pointer)
+            .ok_or_else(|| anyhow::anyhow!("{} profile is missing a login", self.name()))?;
+        let display_name = self
+            .config
+            .user_display_name_pointer
+            .as_ref()
+            .and_then(|pointer| field(pointer));
+
+        Ok(ExternalProfile {
+            id,
+            login,
+            display_name,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ncog_shared::jwk::JwtKey;
+    use serde_json::json;
+    use std::{
+        collections::HashMap,
+        net::SocketAddr,
+        sync::{Arc, Mutex},
+    };
+    use warp::Filter;
+
+    type Form = HashMap<String, String>;
+
+    fn provider_for(addr: SocketAddr, revoke: bool) -> ConfiguredProvider {
+        ConfiguredProvider::new(ProviderConfig {
+            name: "mock".to_owned(),
+            client_id: "client".to_owned(),
+            client_secret: "secret".to_owned(),
+            endpoints: ProviderEndpoints {
+                authorize_url: format!("http://{}/authorize", addr),
+                token_url: format!("http://{}/token", addr),
+                userinfo_url: format!("http://{}/userinfo", addr),
+                revoke_url: if revoke {
+                    Some(format!("http://{}/revoke", addr))
+                } else {
+                    None
+                },
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    /// Starts a provider that publishes `keys` and serves `userinfo` to the
+    /// access token `access`. Its token endpoint exchanges the code
+    /// `valid-code` for tokens including an ID token, `code-without-id-token`
+    /// for an access token alone, and the refresh token `valid-refresh` for new
+    /// tokens. Forms posted to the token and revocation endpoints, and userinfo
+    /// authorization headers, are recorded.
+    fn mock_provider(
+        keys: Vec<JwtKey>,
+        userinfo: Value,
+    ) -> (SocketAddr, Arc<Mutex<Vec<Form>>>, Arc<Mutex<Vec<String>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let token = warp::post()
+            .and(warp::path("token"))
+            .and(warp::body::form())
+            .map(move |form: Form| {
+                recorded.lock().unwrap().push(form.clone());
+                let field = |name: &str| form.get(name).map(String::as_str);
+                let valid_client = field("client_id") == Some("client")
+                    && field("client_secret") == Some("secret");
+                let tokens = match (field("grant_type"), field("code"), field("refresh_token")) {
+                    (Some("authorization_code"), Some("valid-code"), _) => Some(json!({
+                        "access_token": "access",
+                        "refresh_token": "refresh",
+                        "expires_in": 3600,
+                        "id_token": "id-token",
+                    })),
+                    (Some("authorization_code"), Some("code-without-id-token"), _) => {
+                        Some(json!({ "access_token": "access" }))
+                    }
+                    (Some("refresh_token"), _, Some("valid-refresh")) => Some(json!({
+                        "access_token": "new-access",
+                        "refresh_token": "new-refresh",
+                        "expires_in": 3600,
+                    })),
+                    _ => None,
+                };
+                match tokens {
+                    Some(tokens) if valid_client => {
+                        warp::reply::with_status(warp::reply::json(&tokens), StatusCode::OK)
+                    }
+                    _ => warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": "invalid_grant" })),
+                        StatusCode::BAD_REQUEST,
+                    ),
+                }
+            });
+        let revoke_requests = requests.clone();
+        let revoke = warp::post()
+            .and(warp::path("revoke"))
+            .and(warp::body::form())
+            .map(move |form: Form| {
+                revoke_requests.lock().unwrap().push(form);
+                warp::reply()
+            });
+        let authorizations = Arc::new(Mutex::new(Vec::new()));
+        let recorded_authorizations = authorizations.clone();
+        let userinfo = warp::get()
+            .and(warp::path("userinfo"))
+            .and(warp::header::<String>("authorization"))
+            .map(move |authorization: String| {
+                recorded_authorizations
+                    .lock()
+                    .unwrap()
+                    .push(authorization.clone());
+                if authorization == "Bearer access" {
+                    warp::reply::with_status(warp::reply::json(&userinfo), StatusCode::OK)
+                } else {
+                    warp::reply::with_status(
+                        warp::reply::json(&json!({ "error": "invalid_token" })),
+                        StatusCode::UNAUTHORIZED,
+                    )
+                }
+            });
+        let key_set = JwtKeySet { keys };
+        let jwks = warp::get()
+            .and(warp::path("jwks"))
+            .map(move || warp::reply::json(&key_set));
+
+        let (addr, server) =
+            warp::serve(token.or(revoke).or(userinfo).or(jwks)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        (addr, requests, authorizations)
+    }
+
+    #[tokio::test]
+    async fn refresh_returns_new_tokens() {
+        let (addr, requests, _) = mock_provider(Vec::new(), json!({}));
+        let provider = provider_for(addr, true);
+
+        let tokens = provider.refresh_tokens("valid-refresh").await.unwrap();
+        assert_eq!(tokens.access_token, "new-access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("new-refresh"));
+        assert_eq!(tokens.expires_in, Some(3600));
+        assert!(tokens.expires().is_some());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn refresh_rejected_token() {
+        let (addr, _, _) = mock_provider(Vec::new(), json!({}));
+        let provider = provider_for(addr, true);
+
+        assert!(matches!(
+            provider.refresh_tokens("revoked-refresh").await,
+            Err(TokenError::Rejected)
+        ));
+    }
+
+    #[tokio::test]
+    async fn refresh_unreachable_provider() {
+        let (addr, _, _) = mock_provider(Vec::new(), json!({}));
+        let mut provider = provider_for(addr, true);
+        provider.config.endpoints.token_url = "http://127.0.0.1:1/token".to_owned();
+
+        assert!(matches!(
+            provider.refresh_tokens("valid-refresh").await,
+            Err(TokenError::Request(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn revoke_posts_token() {
+        let (addr, requests, _) = mock_provider(Vec::new(), json!({}));
+        let provider = provider_for(addr, true);
+
+        provider
+            .revoke_token("old-access", "access_token")
+            .await
+            .unwrap();
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["token"], "old-access");
+        assert_eq!(requests[0]["token_type_hint"], "access_token");
+        assert_eq!(requests[0]["client_id"], "client");
+    }
+
+    #[tokio::test]
+    async fn revoke_without_endpoint() {
+        let (addr, requests, _) = mock_provider(Vec::new(), json!({}));
+        let provider = provider_for(addr, false);
+
+        provider
+            .revoke_token("old-access", "access_token")
+            .await
+            .unwrap();
+        assert!(requests.lock().unwrap().is_empty());
+    }
+
+    const ISSUER: &str = "https://issuer.example";
+
+    /// An RSA key that signs the mock provider's ID tokens.
+    struct TestKey {
+        encoding_key: jsonwebtoken::EncodingKey,
+        jwk: JwtKey,
+    }
+
+    fn test_key(key_id: &str) -> TestKey {
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        TestKey {
+            encoding_key: jsonwebtoken::EncodingKey::from_rsa_pem(
+                &rsa.private_key_to_pem().unwrap(),
+            )
+            .unwrap(),
+            jwk: JwtKey {
+                algorithm: "RS256".to_owned(),
+                key_id: key_id.to_owned(),
+                key_type: "RSA".to_owned(),
+                rsa_e: base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+                rsa_n: base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+                public_use: "sig".to_owned(),
+            },
+        }
+    }
+
+    fn sign_id_token(key: &TestKey, key_id: &str, claims: Value) -> String {
+        let header = jsonwebtoken::Header {
+            kid: Some(key_id.to_owned()),
+            ..jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256)
+        };
+        jsonwebtoken::encode(&header, &claims, &key.encoding_key).unwrap()
+    }
+
+    fn valid_claims() -> Value {
+        json!({
+            "iss": ISSUER,
+            "sub": "external-user",
+            "aud": "client",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "iat": chrono::Utc::now().timestamp(),
+            "nonce": "expected-nonce",
+        })
+    }
+
+    fn oidc_provider_for(addr: SocketAddr) -> ConfiguredProvider {
+        let mut provider = provider_for(addr, false);
+        provider.config.endpoints.jwks_url = Some(format!("http://{}/jwks", addr));
+        provider.config.endpoints.issuer = Some(ISSUER.to_owned());
+        provider.config.user_id_pointer = "/data/0/id".to_owned();
+        provider.config.user_login_pointer = "/data/0/login".to_owned();
+        provider.config.user_display_name_pointer = Some("/data/0/display_name".to_owned());
+        provider
+    }
+
+    #[tokio::test]
+    async fn exchange_code_returns_tokens() {
+        let (addr, requests, _) = mock_provider(Vec::new(), json!({}));
+        let provider = oidc_provider_for(addr);
+
+        let tokens = provider.exchange_code("valid-code").await.unwrap();
+        assert_eq!(tokens.access_token, "access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(tokens.expires_in, Some(3600));
+        assert_eq!(tokens.id_token.as_deref(), Some("id-token"));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["code"], "valid-code");
+        assert_eq!(requests[0]["redirect_uri"], callback_uri("mock"));
+    }
+
+    #[tokio::test]
+    async fn exchange_code_rejected() {
+        let (addr, _, _) = mock_provider(Vec::new(), json!({}));
+        let provider = oidc_provider_for(addr);
+
+        assert!(provider.exchange_code("invalid-code").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn exchange_code_requires_id_token() {
+        let (addr, _, _) = mock_provider(Vec::new(), json!({}));
+        let provider = oidc_provider_for(addr);
+        assert!(provider
+            .exchange_code("code-without-id-token")
+            .await
+            .is_err());
+
+        // Providers without a key set don't issue ID tokens.
+        let provider = provider_for(addr, false);
+        let tokens = provider
+            .exchange_code("code-without-id-token")
+            .await
+            .unwrap();
+        assert!(tokens.id_token.is_none());
+    }
+
+    fn access_tokens() -> OAuthTokens {
+        OAuthTokens {
+            access_token: "access".to_owned(),
+            refresh_token: None,
+            expires_in: None,
+            id_token: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_profile_follows_pointers() {
+        let (addr, _, authorizations) = mock_provider(
+            Vec::new(),
+            json!({ "data": [{ "id": 1234, "login": "ecton", "display_name": "Ecton" }] }),
+        );
+        let provider = oidc_provider_for(addr);
+
+        let profile = provider.fetch_profile(&access_tokens()).await.unwrap();
+        assert_eq!(profile.id, "1234");
+        assert_eq!(profile.login, "ecton");
+        assert_eq!(profile.display_name.as_deref(), Some("Ecton"));
+        assert_eq!(
+            authorizations.lock().unwrap().as_slice(),
+            &["Bearer access".to_owned()]
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_profile_missing_fields() {
+        let (addr, _, _) = mock_provider(
+            Vec::new(),
+            json!({ "data": [{ "id": "1234", "display_name": ["not", "a", "string"] }] }),
+        );
+        let mut provider = oidc_provider_for(addr);
+        assert!(provider.fetch_profile(&access_tokens()).await.is_err());
+
+        provider.config.user_login_pointer = "/data/0/id".to_owned();
+        let profile = provider.fetch_profile(&access_tokens()).await.unwrap();
+        assert_eq!(profile.login, "1234");
+        assert!(profile.display_name.is_none());
+    }
+
+    #[tokio::test]
+    async fn validate_id_token_accepts_valid_token() {
+        let key = test_key("current");
+        let (addr, _, _) = mock_provider(vec![key.jwk.clone()], json!({}));
+        let provider = oidc_provider_for(addr);
+
+        let id_token = sign_id_token(&key, "current", valid_claims());
+        let claims = provider
+            .validate_id_token(&id_token, "expected-nonce")
+            .await
+            .unwrap();
+        assert_eq!(claims.subject.as_deref(), Some("external-user"));
+
+        // Tokens may be issued for several audiences.
+        let mut claims = valid_claims();
+        claims["aud"] = json!(["other-client", "client"]);
+        let id_token = sign_id_token(&key, "current", claims);
+        let claims = provider
+            .validate_id_token(&id_token, "expected-nonce")
+            .await
+            .unwrap();
+        assert_eq!(claims.audience, vec!["other-client", "client"]);
+    }
+
+    #[tokio::test]
+    async fn validate_id_token_rejects_invalid_claims() {
+        let key = test_key("current");
+        let (addr, _, _) = mock_provider(vec![key.jwk.clone()], json!({}));
+        let provider = oidc_provider_for(addr);
+
+        let with = |field: &str, value: Value| {
+            let mut claims = valid_claims();
+            claims[field] = value;
+            claims
+        };
+        let mut without_expiration = valid_claims();
+        without_expiration.as_object_mut().unwrap().remove("exp");
+        for claims in vec![
+            with("iss", json!("https://other-issuer.example")),
+            with("aud", json!("other-client")),
+            with("aud", json!(["other-client", "another-client"])),
+            with("exp", json!(chrono::Utc::now().timestamp() - 300)),
+            without_expiration,
+        ] {
+            let id_token = sign_id_token(&key, "current", claims.clone());
+            assert!(
+                provider
+                    .validate_id_token(&id_token, "expected-nonce")
+                    .await
+                    .is_err(),
+                "accepted {}",
+                claims
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn validate_id_token_rejects_unknown_key() {
+        let key = test_key("current");
+        let other_key = test_key("other");
+        let (addr, _, _) = mock_provider(vec![key.jwk.clone()], json!({}));
+        let provider = oidc_provider_for(addr);
+
+        // Signed by a key the provider doesn't publish.
+        let id_token = sign_id_token(&other_key, "other", valid_claims());
+        assert!(provider
+            .validate_id_token(&id_token, "expected-nonce")
+            .await
+            .is_err());
+
+        // Claiming a published key id without being signed by it.
+        let id_token = sign_id_token(&other_key, "current", valid_claims());
+        assert!(provider
+            .validate_id_token(&id_token, "expected-nonce")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn validate_id_token_rejects_nonce_mismatch() {
+        let key = test_key("current");
+        let (addr, _, _) = mock_provider(vec![key.jwk.clone()], json!({}));
+        let provider = oidc_provider_for(addr);
+
+        let id_token = sign_id_token(&key, "current", valid_claims());
+        let err = provider
+            .validate_id_token(&id_token, "other-nonce")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LoginError>(),
+            Some(LoginError::NonceMismatch)
+        ));
+    }
+}
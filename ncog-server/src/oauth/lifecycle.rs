@@ -0,0 +1,102 @@
+use super::{provider, TokenError};
+use crate::database::{self, StoredOAuthToken};
+use chrono::{Duration, Utc};
+use ncog_migrations::pg;
+
+/// How often tokens are checked for refreshing.
+const REFRESH_INTERVAL_SECONDS: u64 = 60;
+/// Tokens are refreshed once they are within this many minutes of expiring.
+const REFRESH_WINDOW_MINUTES: i64 = 10;
+
+/// Refreshes tokens before they expire, marking any that can no longer be
+/// refreshed as invalid.
+pub async fn refresh_loop() {
+    let mut interval =
+        tokio::time::interval(std::time::Duration::from_secs(REFRESH_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        if let Err(err) = refresh_expiring_tokens().await {
+            error!("Error refreshing oauth tokens: {:?}", err);
+        }
+    }
+}
+
+async fn refresh_expiring_tokens() -> anyhow::Result<()> {
+    let invalidated = database::invalidate_expired_oauth_tokens(&pg()).await?;
+    if invalidated > 0 {
+        info!("Invalidated {} expired oauth tokens", invalidated);
+    }
+
+    let expires_before = Utc::now().naive_utc() + Duration::minutes(REFRESH_WINDOW_MINUTES);
+    for token in database::list_expiring_oauth_tokens(&pg(), expires_before).await? {
+        if let Err(err) = refresh(&token).await {
+            // Tokens that fail for reasons other than being rejected are
+            // retried on the next interval.
+            warn!(
+                account_id = token.account_id,
+                service = token.service.as_str(),
+                error = ?err,
+                "Error refreshing oauth token"
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn refresh(token: &StoredOAuthToken) -> anyhow::Result<()> {
+    let provider = provider(&token.service)
+        .ok_or_else(|| anyhow::anyhow!("{} is not configured", token.service))?;
+    let refresh_token = token
+        .refresh_token
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("token has no refresh token"))?;
+
+    match provider.refresh_tokens(refresh_token).await {
+        Ok(tokens) => {
+            database::update_refreshed_oauth_token(
+                &pg(),
+                token,
+                &tokens.access_token,
+                tokens.refresh_token.as_ref().or(Some(refresh_token)),
+                tokens.expires(),
+            )
+            .await?;
+        }
+        Err(TokenError::Rejected) => {
+            info!(
+                account_id = token.account_id,
+                service = token.service.as_str(),
+                "OAuth token was rejected while refreshing, marking invalid"
+            );
+            database::invalidate_oauth_token(&pg(), token).await?;
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
+/// Revokes a token ncog no longer stores. Failures are only logged, since
+/// the token has already been forgotten.
+pub async fn revoke(token: StoredOAuthToken) {
+    let provider = match provider(&token.service) {
+        Some(provider) => provider,
+        None => return,
+    };
+
+    let mut tokens = vec![(token.access_token.as_str(), "access_token")];
+    if let Some(refresh_token) = &token.refresh_token {
+        tokens.push((refresh_token.as_str(), "refresh_token"));
+    }
+    for (value, token_type_hint) in tokens {
+        match provider.revoke_token(value, token_type_hint).await {
+            // Rejected tokens are already unusable.
+            Ok(_) | Err(TokenError::Rejected) => {}
+            Err(err) => warn!(
+                account_id = token.account_id,
+                service = token.service.as_str(),
+                error = ?err,
+                "Error revoking oauth token"
+            ),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use super::{ProviderConfig, ProviderEndpoints};
+
+pub const NAME: &str = "twitch";
+
+/// Twitch's endpoints and profile layout. Only `TWITCH_CLIENT_ID` and
+/// `TWITCH_CLIENT_SECRET` need to be set.
+pub fn defaults() -> ProviderConfig {
+    ProviderConfig {
+        name: NAME.to_owned(),
+        endpoints: ProviderEndpoints {
+            authorize_url: "https://id.twitch.tv/oauth2/authorize".to_owned(),
+            token_url: "https://id.twitch.tv/oauth2/token".to_owned(),
+            userinfo_url: "https://api.twitch.tv/helix/users".to_owned(),
+            revoke_url: Some("https://id.twitch.tv/oauth2/revoke".to_owned()),
+            jwks_url: Some("https://id.twitch.tv/oauth2/keys".to_owned()),
+            issuer: Some("https://id.twitch.tv/oauth2".to_owned()),
+        },
+        scopes: vec!["openid".to_owned()],
+        user_id_pointer: "/data/0/id".to_owned(),
+        user_login_pointer: "/data/0/login".to_owned(),
+        user_display_name_pointer: Some("/data/0/display_name".to_owned()),
+        send_client_id_header: true,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,727 @@
+//! ncog as an OpenID Connect provider, which lets registered applications log
+//! users in with the authorization code flow. Every application must use PKCE
+//! (RFC 7636) with the S256 method, and applications with a secret must also
+//! authenticate when exchanging codes.
+
+use crate::{
+    database::{self, Application, OidcAuthorizationRequest},
+    oauth::random_token,
+    signing_keys, webserver_base_url, well_known,
+};
+use chrono::{Duration, Utc};
+use ncog_migrations::{pg, sqlx};
+use ncog_shared::{AuthorizationRequest, UserProfile};
+use openssl::{memcmp, sha::sha256};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use url::{Host, Url};
+use uuid::Uuid;
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+/// How long the user has to answer an authentication request.
+const AUTHORIZATION_REQUEST_LIFETIME_MINUTES: i64 = 10;
+const AUTHORIZATION_CODE_LIFETIME_SECONDS: i64 = 60;
+const ACCESS_TOKEN_LIFETIME_SECONDS: i64 = 60 * 60;
+const ID_TOKEN_LIFETIME_SECONDS: i64 = 10 * 60;
+
+/// The scopes applications can request, of which `openid` is required.
+/// `profile` adds the user's names to the ID token and userinfo.
+pub const SUPPORTED_SCOPES: &[&str] = &["openid", "profile"];
+
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let authorize = warp::get()
+        .and(warp::path!("authorize"))
+        .and(warp::query())
+        .and_then(authorize);
+    let token = warp::post()
+        .and(warp::path!("token"))
+        .and(warp::header::optional("authorization"))
+        .and(warp::body::content_length_limit(16 * 1024))
+        .and(warp::body::form())
+        .and_then(token);
+    let userinfo = warp::get()
+        .or(warp::post())
+        .unify()
+        .and(warp::path!("userinfo"))
+        .and(warp::header::optional("authorization"))
+        .and_then(userinfo);
+    authorize.or(token).or(userinfo)
+}
+
+fn hash(secret: &str) -> [u8; 32] {
+    sha256(secret.as_bytes())
+}
+
+/// Generates a new client secret, returning it along with the hash to store.
+pub fn generate_client_secret() -> (String, [u8; 32]) {
+    let client_secret = random_token();
+    let hash = hash(&client_secret);
+    (client_secret, hash)
+}
+
+/// The S256 PKCE code challenge for `code_verifier`.
+fn code_challenge(code_verifier: &str) -> String {
+    base64::encode_config(hash(code_verifier), base64::URL_SAFE_NO_PAD)
+}
+
+/// Why a redirect uri can't be registered.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidRedirectUri {
+    #[error("{0} is not a valid url")]
+    Malformed(String),
+    #[error("{0} must use https, or http with a loopback address")]
+    Insecure(String),
+    #[error("{0} must not contain a fragment")]
+    Fragment(String),
+}
+
+/// Checks that `redirect_uri` can be registered. Authorization codes are sent
+/// to it, so it must use https unless it stays on the user's machine, and it
+/// can't contain a fragment (RFC 6749 section 3.1.2).
+pub fn validate_redirect_uri(redirect_uri: &str) -> Result<(), InvalidRedirectUri> {
+    let url = Url::parse(redirect_uri)
+        .map_err(|_| InvalidRedirectUri::Malformed(redirect_uri.to_owned()))?;
+    let secure = match url.scheme() {
+        "https" => true,
+        "http" => match url.host() {
+            Some(Host::Domain(domain)) => domain.eq_ignore_ascii_case("localhost"),
+            Some(Host::Ipv4(address)) => address.is_loopback(),
+            Some(Host::Ipv6(address)) => address.is_loopback(),
+            None => false,
+        },
+        _ => false,
+    };
+    if !secure {
+        return Err(InvalidRedirectUri::Insecure(redirect_uri.to_owned()));
+    }
+    if url.fragment().is_some() {
+        return Err(InvalidRedirectUri::Fragment(redirect_uri.to_owned()));
+    }
+    Ok(())
+}
+
+/// Adds `params` and `state` to the application's redirect uri.
+fn redirect_url(
+    redirect_uri: &str,
+    params: &[(&str, &str)],
+    state: Option<&str>,
+) -> anyhow::Result<String> {
+    let mut url = Url::parse(redirect_uri)?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(params);
+        if let Some(state) = state {
+            query.append_pair("state", state);
+        }
+    }
+    Ok(url.to_string())
+}
+
+fn found(url: &str) -> warp::reply::Response {
+    warp::reply::with_header(StatusCode::FOUND, "Location", url).into_response()
+}
+
+#[derive(Deserialize)]
+struct AuthorizeParams {
+    client_id: Option<String>,
+    redirect_uri: Option<String>,
+    response_type: Option<String>,
+    scope: Option<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: Option<String>,
+    code_challenge_method: Option<String>,
+}
+
+async fn authorize(params: AuthorizeParams) -> Result<warp::reply::Response, Infallible> {
+    match start_authorization(params).await {
+        Ok(response) => Ok(response),
+        Err(err) => {
+            error!("Error starting authorization: {:?}", err);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Validates the request and sends the user to the consent page. Until the
+/// redirect uri is known to be registered, errors are shown to the user
+/// rather than redirected to the application.
+async fn start_authorization(params: AuthorizeParams) -> anyhow::Result<warp::reply::Response> {
+    let application = match &params.client_id {
+        Some(client_id) => database::get_application(&pg(), client_id).await?,
+        None => None,
+    };
+    let application = match application {
+        Some(application) => application,
+        None => {
+            return Ok(
+                warp::reply::with_status("unknown client_id", StatusCode::BAD_REQUEST)
+                    .into_response(),
+            )
+        }
+    };
+    let redirect_uri = match params.redirect_uri {
+        Some(redirect_uri)
+            if database::application_allows_redirect_uri(&pg(), &application.id, &redirect_uri)
+                .await? =>
+        {
+            redirect_uri
+        }
+        _ => {
+            return Ok(warp::reply::with_status(
+                "redirect_uri is not registered for this client",
+                StatusCode::BAD_REQUEST,
+            )
+            .into_response())
+        }
+    };
+
+    let state = params.state.as_deref();
+    let error = |error: &str, description: &str| -> anyhow::Result<warp::reply::Response> {
+        Ok(found(&redirect_url(
+            &redirect_uri,
+            &[("error", error), ("error_description", description)],
+            state,
+        )?))
+    };
+
+    if params.response_type.as_deref() != Some("code") {
+        return error(
+            "unsupported_response_type",
+            "only the code response type is supported",
+        );
+    }
+    // Unsupported scopes are ignored rather than refused.
+    let scopes = params
+        .scope
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter(|scope| SUPPORTED_SCOPES.contains(scope))
+        .collect::<Vec<_>>();
+    if !scopes.contains(&"openid") {
+        return error("invalid_scope", "the openid scope is required");
+    }
+    let code_challenge = match (
+        &params.code_challenge,
+        params.code_challenge_method.as_deref(),
+    ) {
+        (Some(code_challenge), Some("S256")) => code_challenge.clone(),
+        _ => return error("invalid_request", "PKCE with the S256 method is required"),
+    };
+
+    let request = OidcAuthorizationRequest {
+        id: random_token(),
+        application_id: application.id,
+        redirect_uri: redirect_uri.clone(),
+        scope: scopes.join(" "),
+        state: params.state.clone(),
+        nonce: params.nonce,
+        code_challenge,
+    };
+    let expires_at = Utc::now() + Duration::minutes(AUTHORIZATION_REQUEST_LIFETIME_MINUTES);
+    let mut tx = pg().begin().await?;
+    database::delete_expired_oidc_grants(&mut tx).await?;
+    database::create_oidc_authorization_request(&mut tx, &request, expires_at).await?;
+    tx.commit().await?;
+
+    Ok(found(
+        &webserver_base_url()
+            .path_and_query(format!("/authorize/{}", request.id).as_str())
+            .build()?
+            .to_string(),
+    ))
+}
+
+/// Loads a request for the consent page. The installation viewing it is
+/// remembered so that it can return to the request after logging in.
+pub async fn view_request(
+    request_id: &str,
+    installation_id: Uuid,
+) -> anyhow::Result<Option<AuthorizationRequest>> {
+    let request = match database::view_oidc_authorization_request(
+        &pg(),
+        request_id,
+        installation_id,
+    )
+    .await?
+    {
+        Some(request) => request,
+        None => return Ok(None),
+    };
+    let application = database::get_application(&pg(), &request.application_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("application {} not found", request.application_id))?;
+    Ok(Some(AuthorizationRequest {
+        id: request.id,
+        application_name: application.name,
+        redirect_uri: request.redirect_uri,
+        scopes: request.scope.split(' ').map(str::to_owned).collect(),
+    }))
+}
+
+/// Answers a request on behalf of the account, returning the url to send the
+/// user back to the application with. Returns `None` if the request expired
+/// or was already answered.
+pub async fn answer_request(
+    request_id: &str,
+    account_id: i64,
+    approved: bool,
+) -> anyhow::Result<Option<String>> {
+    let mut tx = pg().begin().await?;
+    let request = match database::take_oidc_authorization_request(&mut tx, request_id).await? {
+        Some(request) => request,
+        None => return Ok(None),
+    };
+
+    let url = if approved {
+        let code = random_token();
+        let expires_at = Utc::now() + Duration::seconds(AUTHORIZATION_CODE_LIFETIME_SECONDS);
+        database::create_oidc_authorization_code(
+            &mut tx,
+            &hash(&code),
+            &request,
+            account_id,
+            expires_at,
+        )
+        .await?;
+        redirect_url(
+            &request.redirect_uri,
+            &[("code", &code)],
+            request.state.as_deref(),
+        )?
+    } else {
+        redirect_url(
+            &request.redirect_uri,
+            &[("error", "access_denied")],
+            request.state.as_deref(),
+        )?
+    };
+    tx.commit().await?;
+
+    Ok(Some(url))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: Option<String>,
+    code: Option<String>,
+    redirect_uri: Option<String>,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    code_verifier: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    expires_in: i64,
+    id_token: String,
+    scope: String,
+}
+
+/// The error responses defined in section 5.2 of RFC 6749.
+#[derive(Debug, thiserror::Error)]
+enum TokenError {
+    #[error("{0}")]
+    InvalidRequest(&'static str),
+    #[error("client authentication failed")]
+    InvalidClient,
+    #[error("the authorization code is invalid, expired, or was already used")]
+    InvalidGrant,
+    #[error("only the authorization_code grant type is supported")]
+    UnsupportedGrantType,
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0}")]
+    Server(#[from] anyhow::Error),
+}
+
+impl TokenError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidClient => "invalid_client",
+            Self::InvalidGrant => "invalid_grant",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::Database(_) | Self::Server(_) => "server_error",
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidClient => StatusCode::UNAUTHORIZED,
+            Self::Database(_) | Self::Server(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: &'static str,
+    error_description: String,
+}
+
+async fn token(
+    authorization: Option<String>,
+    request: TokenRequest,
+) -> Result<warp::reply::Response, Infallible> {
+    let response = match exchange_code(authorization, request).await {
+        Ok(tokens) => warp::reply::json(&tokens).into_response(),
+        Err(err) => {
+            if let TokenError::Database(_) | TokenError::Server(_) = err {
+                error!("Error exchanging authorization code: {:?}", err);
+            }
+            let description = match err.status() {
+                StatusCode::INTERNAL_SERVER_ERROR => "an unexpected error occurred".to_owned(),
+                _ => err.to_string(),
+            };
+            let mut response = warp::reply::with_status(
+                warp::reply::json(&ErrorResponse {
+                    error: err.code(),
+                    error_description: description,
+                }),
+                err.status(),
+            )
+            .into_response();
+            if let TokenError::InvalidClient = err {
+                response
+                    .headers_mut()
+                    .insert("WWW-Authenticate", "Basic".parse().unwrap());
+            }
+            response
+        }
+    };
+
+    Ok(warp::reply::with_header(
+        warp::reply::with_header(response, "Cache-Control", "no-store"),
+        "Pragma",
+        "no-cache",
+    )
+    .into_response())
+}
+
+/// Reads the client's credentials from HTTP Basic authentication, falling
+/// back to the request body.
+fn client_credentials(
+    authorization: Option<String>,
+    request: &TokenRequest,
+) -> Result<(String, Option<String>), TokenError> {
+    if let Some(credentials) = authorization
+        .as_deref()
+        .and_then(|authorization| authorization.strip_prefix("Basic "))
+    {
+        let credentials = base64::decode(credentials.trim())
+            .ok()
+            .and_then(|credentials| String::from_utf8(credentials).ok())
+            .ok_or(TokenError::InvalidClient)?;
+        let mut parts = credentials.splitn(2, ':');
+        let client_id = parts.next().unwrap_or_default().to_owned();
+        return Ok((client_id, parts.next().map(str::to_owned)));
+    }
+
+    let client_id = request
+        .client_id
+        .clone()
+        .ok_or(TokenError::InvalidRequest("client_id is required"))?;
+    Ok((client_id, request.client_secret.clone()))
+}
+
+fn authenticate_client(
+    application: &Application,
+    client_secret: Option<&str>,
+) -> Result<(), TokenError> {
+    match (&application.client_secret_hash, client_secret) {
+        // Public clients are authenticated by PKCE alone.
+        (None, _) => Ok(()),
+        (Some(secret_hash), Some(client_secret))
+            if secret_hash.len() == 32 && memcmp::eq(secret_hash, &hash(client_secret)) =>
+        {
+            Ok(())
+        }
+        _ => Err(TokenError::InvalidClient),
+    }
+}
+
+#[derive(Serialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    exp: i64,
+    iat: i64,
+    auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(flatten)]
+    profile: Option<ProfileClaims>,
+}
+
+/// The standard claims granted by the `profile` scope.
+#[derive(Serialize)]
+struct ProfileClaims {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preferred_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+fn profile_claims(scope: &str, profile: &UserProfile) -> Option<ProfileClaims> {
+    if scope.split(' ').any(|scope| scope == "profile") {
+        Some(ProfileClaims {
+            preferred_username: profile.login.clone(),
+            name: profile.display_name.clone(),
+        })
+    } else {
+        None
+    }
+}
+
+async fn exchange_code(
+    authorization: Option<String>,
+    request: TokenRequest,
+) -> Result<TokenResponse, TokenError> {
+    if request.grant_type.as_deref() != Some("authorization_code") {
+        return Err(TokenError::UnsupportedGrantType);
+    }
+    let (client_id, client_secret) = client_credentials(authorization, &request)?;
+    let application = database::get_application(&pg(), &client_id)
+        .await?
+        .ok_or(TokenError::InvalidClient)?;
+    authenticate_client(&application, client_secret.as_deref())?;
+
+    let code = request
+        .code
+        .as_deref()
+        .ok_or(TokenError::InvalidRequest("code is required"))?;
+    let code_verifier = request
+        .code_verifier
+        .as_deref()
+        .ok_or(TokenError::InvalidRequest("code_verifier is required"))?;
+    let grant = database::take_oidc_authorization_code(&pg(), &hash(code))
+        .await?
+        .ok_or(TokenError::InvalidGrant)?;
+    if grant.application_id != application.id
+        || request.redirect_uri.as_ref() != Some(&grant.redirect_uri)
+        || code_challenge(code_verifier) != grant.code_challenge
+    {
+        return Err(TokenError::InvalidGrant);
+    }
+
+    // Suspended accounts can't log into applications.
+    if database::get_account_suspension(&pg(), grant.account_id)
+        .await?
+        .map_or(false, |suspension| suspension.is_active())
+    {
+        return Err(TokenError::InvalidGrant);
+    }
+    let profile = database::get_profile_by_account_id(&pg(), grant.account_id)
+        .await?
+        .ok_or(TokenError::InvalidGrant)?;
+
+    let access_token = random_token();
+    let now = Utc::now();
+    database::create_oidc_access_token(
+        &pg(),
+        &hash(&access_token),
+        &application.id,
+        grant.account_id,
+        &grant.scope,
+        now + Duration::seconds(ACCESS_TOKEN_LIFETIME_SECONDS),
+    )
+    .await?;
+
+    let id_token = signing_keys::sign(&IdTokenClaims {
+        iss: well_known::issuer(),
+        sub: grant.account_id.to_string(),
+        aud: application.id,
+        exp: (now + Duration::seconds(ID_TOKEN_LIFETIME_SECONDS)).timestamp(),
+        iat: now.timestamp(),
+        auth_time: grant.auth_time.timestamp(),
+        nonce: grant.nonce,
+        profile: profile_claims(&grant.scope, &profile),
+    })
+    .await?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer",
+        expires_in: ACCESS_TOKEN_LIFETIME_SECONDS,
+        id_token,
+        scope: grant.scope,
+    })
+}
+
+#[derive(Serialize)]
+struct UserInfo {
+    sub: String,
+    #[serde(flatten)]
+    profile: Option<ProfileClaims>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ncog_profile: Option<UserProfile>,
+}
+
+async fn userinfo(authorization: Option<String>) -> Result<warp::reply::Response, Infallible> {
+    match load_userinfo(authorization).await {
+        Ok(Some(userinfo)) => Ok(warp::reply::json(&userinfo).into_response()),
+        Ok(None) => Ok(warp::reply::with_header(
+            StatusCode::UNAUTHORIZED,
+            "WWW-Authenticate",
+            r#"Bearer error="invalid_token""#,
+        )
+        .into_response()),
+        Err(err) => {
+            error!("Error loading userinfo: {:?}", err);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn load_userinfo(authorization: Option<String>) -> anyhow::Result<Option<UserInfo>> {
+    let access_token = match authorization
+        .as_deref()
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+    {
+        Some(access_token) => access_token.trim(),
+        None => return Ok(None),
+    };
+    let (account_id, scope) =
+        match database::get_oidc_access_token(&pg(), &hash(access_token)).await? {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+    // Suspending deletes the account's tokens, but a code exchanged while
+    // the suspension was being saved can still produce one.
+    if database::get_account_suspension(&pg(), account_id)
+        .await?
+        .map_or(false, |suspension| suspension.is_active())
+    {
+        return Ok(None);
+    }
+    let profile = match database::get_profile_by_account_id(&pg(), account_id).await? {
+        Some(profile) => profile,
+        None => return Ok(None),
+    };
+
+    let profile_claims = profile_claims(&scope, &profile);
+    Ok(Some(UserInfo {
+        sub: account_id.to_string(),
+        ncog_profile: profile_claims.as_ref().map(|_| profile.clone()),
+        profile: profile_claims,
+    }))
+}
+
+/// Handles `ncog-server applications <create|create-public|add-redirect-uri>`.
+pub async fn command(args: &[String]) -> anyhow::Result<()> {
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [command @ "create", name, redirect_uris @ ..]
+        | [command @ "create-public", name, redirect_uris @ ..]
+            if !redirect_uris.is_empty() =>
+        {
+            let application_id = random_token();
+            let client_secret = match *command {
+                "create" => Some(generate_client_secret()),
+                _ => None,
+            };
+            let mut tx = pg().begin().await?;
+            database::create_application(
+                &mut tx,
+                &application_id,
+                name,
+                client_secret.as_ref().map(|(_, hash)| &hash[..]),
+                None,
+            )
+            .await?;
+            for redirect_uri in redirect_uris {
+                validate_redirect_uri(redirect_uri)?;
+                database::add_application_redirect_uri(&mut tx, &application_id, redirect_uri)
+                    .await?;
+            }
+            tx.commit().await?;
+            println!("client_id: {}", application_id);
+            // Only the hash is stored, so this is the only time it's shown.
+            if let Some((client_secret, _)) = client_secret {
+                println!("client_secret: {}", client_secret);
+            }
+        }
+        ["add-redirect-uri", application_id, redirect_uri] => {
+            validate_redirect_uri(redirect_uri)?;
+            database::add_application_redirect_uri(&pg(), application_id, redirect_uri).await?;
+        }
+        _ => anyhow::bail!(
+            "usage: applications <create|create-public> NAME REDIRECT_URI... | add-redirect-uri CLIENT_ID REDIRECT_URI"
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_challenge_matches_rfc_7636() {
+        // The example from Appendix B of RFC 7636.
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mJ92K2EqlwcSd97XqRTD7-UjT7qiSs"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn redirect_url_keeps_existing_query() {
+        assert_eq!(
+            redirect_url(
+                "https://example.com/callback?app=1",
+                &[("code", "abc")],
+                Some("xyz")
+            )
+            .unwrap(),
+            "https://example.com/callback?app=1&code=abc&state=xyz"
+        );
+    }
+
+    #[test]
+    fn redirect_uris_must_be_secure() {
+        for redirect_uri in &[
+            "https://example.com/callback",
+            "https://example.com/callback?app=1",
+            "http://localhost:8080/callback",
+            "http://127.0.0.1/callback",
+            "http://[::1]:8080/callback",
+        ] {
+            assert!(
+                validate_redirect_uri(redirect_uri).is_ok(),
+                "rejected {}",
+                redirect_uri
+            );
+        }
+
+        assert!(matches!(
+            validate_redirect_uri("not a url"),
+            Err(InvalidRedirectUri::Malformed(_))
+        ));
+        for redirect_uri in &[
+            "http://example.com/callback",
+            "http://localhost.example.com/callback",
+            "http://192.168.1.1/callback",
+            "javascript:alert(1)",
+            "ftp://example.com/callback",
+        ] {
+            assert!(
+                matches!(
+                    validate_redirect_uri(redirect_uri),
+                    Err(InvalidRedirectUri::Insecure(_))
+                ),
+                "accepted {}",
+                redirect_uri
+            );
+        }
+        assert!(matches!(
+            validate_redirect_uri("https://example.com/callback#token"),
+            Err(InvalidRedirectUri::Fragment(_))
+        ));
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{database, websockets::ConnectedAccount, websockets::NcogServer};
+use crate::{database, signing_keys, websockets::ConnectedAccount, websockets::NcogServer};
 use basws_server::{Handle, Server};
 use ncog_migrations::{pg, sqlx};
-use ncog_shared::NcogResponse;
+use ncog_shared::{LoginFailure, NcogResponse};
 use sqlx::{executor::Executor, postgres::PgListener};
 use std::collections::HashSet;
 use uuid::Uuid;
@@ -10,7 +10,16 @@ pub async fn pg_notify_loop(websockets: Server<NcogServer>) -> Result<(), anyhow
     let pool = pg();
     let mut listener = PgListener::from_pool(&pool).await?;
     listener
-        .listen_all(vec!["installation_login", "world_update", "role_updated"])
+        .listen_all(vec![
+            "installation_login",
+            "installation_login_failed",
+            "world_update",
+            "role_updated",
+            "account_roles_updated",
+            "account_suspension_updated",
+            "accounts_merged",
+            "jwt_signing_keys_updated",
+        ])
         .await?;
     while let Ok(notification) = listener.recv().await {
         info!(
@@ -21,42 +30,144 @@ pub async fn pg_notify_loop(websockets: Server<NcogServer>) -> Result<(), anyhow
         if notification.channel() == "installation_login" {
             // The payload is the installation_id that logged in.
             let installation_id = Uuid::parse_str(notification.payload())?;
-            if let Ok(account) = ConnectedAccount::lookup(installation_id).await {
-                let user = account.user.clone();
-                websockets
-                    .associate_installation_with_account(installation_id, Handle::new(account))
-                    .await?;
-
-                websockets
-                    .send_to_installation_id(installation_id, NcogResponse::Authenticated(user))
-                    .await;
-            }
+            authenticate_installation(&websockets, installation_id).await?;
+        } else if notification.channel() == "installation_login_failed" {
+            // The payload is the installation_id and the LoginFailure code.
+            let mut parts = notification.payload().splitn(2, ' ');
+            let installation_id = Uuid::parse_str(parts.next().unwrap_or_default())?;
+            let failure = parts
+                .next()
+                .and_then(LoginFailure::from_code)
+                .unwrap_or(LoginFailure::ServerError);
+            websockets
+                .send_to_installation_id(installation_id, NcogResponse::LoginFailed(failure))
+                .await;
         } else if notification.channel() == "role_updated" {
             let role_id = notification.payload().parse::<i64>()?;
+            // Accounts can receive a role through another role that includes it.
+            let affected_accounts = database::iam_accounts_with_effective_role(&pg(), role_id)
+                .await?
+                .into_iter()
+                .collect::<HashSet<_>>();
             let mut refreshed_accounts = HashSet::new();
             for client in websockets.connected_clients().await {
                 if let Some(account) = client.account().await {
                     let mut account = account.write().await;
                     if !refreshed_accounts.contains(&account.user.profile.id)
-                        && account.user.permissions.role_ids.contains(&role_id)
+                        && (affected_accounts.contains(&account.user.profile.id)
+                            || account.user.permissions.role_ids.contains(&role_id))
                     {
                         refreshed_accounts.insert(account.user.profile.id);
                         account.user.permissions =
                             database::load_permissions_for(&pg(), account.user.profile.id).await?;
+                        account.refresh_refusal().await?;
                         websockets
-                            .send_to_account_id(
-                                account.user.profile.id,
-                                NcogResponse::Authenticated(account.user.clone()),
-                            )
+                            .send_to_account_id(account.user.profile.id, account.status_response())
                             .await;
                     }
                 }
             }
+        } else if notification.channel() == "account_roles_updated" {
+            let account_id = notification.payload().parse::<i64>()?;
+            refresh_account_permissions(&websockets, account_id).await?;
+        } else if notification.channel() == "account_suspension_updated" {
+            // Suspensions take effect on live sessions immediately, rather
+            // than waiting for the clients to reconnect.
+            let account_id = notification.payload().parse::<i64>()?;
+            let mut status_response = None;
+            for client in websockets.connected_clients().await {
+                if let Some(account) = client.account().await {
+                    let mut account = account.write().await;
+                    if account.user.profile.id == account_id {
+                        account.refresh_refusal().await?;
+                        status_response = Some(account.status_response());
+                    }
+                }
+            }
+
+            if let Some(response) = status_response {
+                websockets.send_to_account_id(account_id, response).await;
+            }
+        } else if notification.channel() == "accounts_merged" {
+            // The payload is the source and target account ids. The source's
+            // installations now belong to the target, which may have gained
+            // roles.
+            let mut parts = notification.payload().splitn(2, ' ');
+            let source_account_id = parts.next().unwrap_or_default().parse::<i64>()?;
+            let target_account_id = parts.next().unwrap_or_default().parse::<i64>()?;
+            let mut installation_ids = HashSet::new();
+            for client in websockets.connected_clients().await {
+                if let Some(account) = client.account().await {
+                    if account.read().await.user.profile.id == source_account_id {
+                        if let Some(installation) = client.installation().await {
+                            installation_ids.insert(installation.id);
+                        }
+                    }
+                }
+            }
+
+            refresh_account_permissions(&websockets, target_account_id).await?;
+            for installation_id in installation_ids {
+                authenticate_installation(&websockets, installation_id).await?;
+            }
+        } else if notification.channel() == "jwt_signing_keys_updated" {
+            signing_keys::invalidate().await;
         }
     }
     panic!("Error on postgres listening");
 }
 
+/// Associates the installation's connections with its account, telling them
+/// whether they are now authenticated.
+async fn authenticate_installation(
+    websockets: &Server<NcogServer>,
+    installation_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    match ConnectedAccount::connect(installation_id).await {
+        Ok(Ok(account)) => {
+            let user = account.user.clone();
+            websockets
+                .associate_installation_with_account(installation_id, Handle::new(account))
+                .await?;
+
+            websockets
+                .send_to_installation_id(installation_id, NcogResponse::Authenticated(user))
+                .await;
+        }
+        Ok(Err(refusal)) => {
+            websockets
+                .send_to_installation_id(installation_id, NcogResponse::ConnectionRefused(refusal))
+                .await;
+        }
+        Err(_) => {}
+    }
+    Ok(())
+}
+
+/// Reloads the permissions of the account's connected sessions.
+async fn refresh_account_permissions(
+    websockets: &Server<NcogServer>,
+    account_id: i64,
+) -> Result<(), anyhow::Error> {
+    let mut status_response = None;
+    for client in websockets.connected_clients().await {
+        if let Some(account) = client.account().await {
+            let mut account = account.write().await;
+            if account.user.profile.id == account_id {
+                account.user.permissions =
+                    database::load_permissions_for(&pg(), account_id).await?;
+                account.refresh_refusal().await?;
+                status_response = Some(account.status_response());
+            }
+        }
+    }
+
+    if let Some(response) = status_response {
+        websockets.send_to_account_id(account_id, response).await;
+    }
+    Ok(())
+}
+
 pub async fn notify<S: ToString>(channel: &'static str, payload: S) -> Result<(), sqlx::Error> {
     let mut connection = pg().acquire().await?;
     connection
@@ -0,0 +1,405 @@
+//! RSA keys used to sign identity tokens.
+//!
+//! The newest active key signs tokens. A replacement is published for
+//! `PUBLICATION_DELAY_SECONDS` before it activates, so that clients that
+//! cached the key set before it existed refetch it before seeing a token it
+//! signed. The keys it replaces keep signing until then, and once retired
+//! remain published for `RETIREMENT_GRACE_HOURS` so that tokens they signed
+//! can still be verified by clients that cached the key set.
+
+use crate::{
+    database::{self, StoredSigningKey},
+    oauth::random_token,
+    pubsub, well_known,
+};
+use chrono::{DateTime, Duration, Utc};
+use ncog_migrations::pg;
+use ncog_shared::{
+    jsonwebtoken::{self, Algorithm, EncodingKey, Header},
+    jwk::JwtKey,
+};
+use openssl::rsa::Rsa;
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// How often the signing key is checked for rotation.
+const ROTATION_CHECK_INTERVAL_SECONDS: u64 = 60 * 60;
+/// How long a key signs tokens before it is replaced.
+const ROTATION_DAYS: i64 = 30;
+/// How long retired keys remain published.
+const RETIREMENT_GRACE_HOURS: i64 = 24;
+/// How long a new key is published before it signs tokens, which is as long
+/// as clients may cache the key set.
+const PUBLICATION_DELAY_SECONDS: i64 = well_known::JWKS_MAX_AGE_SECONDS as i64;
+const RSA_KEY_BITS: u32 = 2048;
+
+/// The keys loaded from the database, which are reloaded whenever another
+/// server changes them.
+struct SigningKeys {
+    signing_key_id: String,
+    encoding_key: EncodingKey,
+    public_keys: Vec<JwtKey>,
+    /// When a key next activates or retires, after which the keys need to be
+    /// reloaded.
+    changes_at: Option<DateTime<Utc>>,
+}
+
+lazy_static::lazy_static! {
+    static ref LOADED_KEYS: RwLock<Option<Arc<SigningKeys>>> = RwLock::new(None);
+}
+
+/// Signs `claims`, including the signing key's id in the token's header.
+pub async fn sign<T: Serialize>(claims: &T) -> anyhow::Result<String> {
+    let keys = loaded_keys().await?;
+    let header = Header {
+        kid: Some(keys.signing_key_id.clone()),
+        ..Header::new(Algorithm::RS256)
+    };
+    Ok(jsonwebtoken::encode(&header, claims, &keys.encoding_key)?)
+}
+
+/// Verifies a token signed by `sign`, returning its claims. Returns `None` if
+/// the token is malformed, has expired, or wasn't signed by a published key.
+pub async fn verify<T: DeserializeOwned>(token: &str) -> anyhow::Result<Option<T>> {
+    let key_id = match jsonwebtoken::decode_header(token) {
+        Ok(header) => header.kid,
+        Err(_) => return Ok(None),
+    };
+    let keys = loaded_keys().await?;
+    Ok(keys
+        .public_keys
+        .iter()
+        .find(|key| Some(&key.key_id) == key_id.as_ref())
+        .and_then(|key| key.parse_token::<T>(token).ok())
+        .map(|token| token.claims))
+}
+
+/// The keys that tokens may currently be signed with.
+pub async fn public_keys() -> anyhow::Result<Vec<JwtKey>> {
+    Ok(loaded_keys().await?.public_keys.clone())
+}
+
+/// Discards the loaded keys so that they are reloaded when next used.
+pub async fn invalidate() {
+    let mut loaded_keys = LOADED_KEYS.write().await;
+    *loaded_keys = None;
+}
+
+async fn loaded_keys() -> anyhow::Result<Arc<SigningKeys>> {
+    if let Some(keys) = LOADED_KEYS.read().await.as_ref() {
+        if keys.is_current(Utc::now()) {
+            return Ok(keys.clone());
+        }
+    }
+
+    let mut loaded_keys = LOADED_KEYS.write().await;
+    if let Some(keys) = loaded_keys.as_ref() {
+        if keys.is_current(Utc::now()) {
+            return Ok(keys.clone());
+        }
+    }
+
+    let mut stored_keys = published_keys().await?;
+    if Replacement::IfMissing.is_needed(&stored_keys, Utc::now()) {
+        replace_signing_key(Replacement::IfMissing).await?;
+        stored_keys = published_keys().await?;
+    }
+    let keys = Arc::new(SigningKeys::new(stored_keys, Utc::now())?);
+    *loaded_keys = Some(keys.clone());
+    Ok(keys)
+}
+
+async fn published_keys() -> anyhow::Result<Vec<StoredSigningKey>> {
+    Ok(database::list_jwt_signing_keys(&pg(), retirement_cutoff()).await?)
+}
+
+fn retirement_cutoff() -> DateTime<Utc> {
+    Utc::now() - Duration::hours(RETIREMENT_GRACE_HOURS)
+}
+
+/// Whether `key` signs tokens at `now`.
+fn is_active(key: &StoredSigningKey, now: DateTime<Utc>) -> bool {
+    key.activates_at <= now && key.retired_at.map_or(true, |retired_at| retired_at > now)
+}
+
+impl SigningKeys {
+    /// Publishes every stored key, signing with the newest one active at
+    /// `now`.
+    fn new(stored_keys: Vec<StoredSigningKey>, now: DateTime<Utc>) -> anyhow::Result<Self> {
+        // Keys are listed newest first.
+        let signing_key = stored_keys
+            .iter()
+            .find(|key| is_active(key, now))
+            .ok_or_else(|| anyhow::anyhow!("no active signing key"))?;
+        let changes_at = stored_keys
+            .iter()
+            .flat_map(|key| std::iter::once(key.activates_at).chain(key.retired_at))
+            .filter(|changes_at| *changes_at > now)
+            .min();
+        Ok(Self {
+            signing_key_id: signing_key.id.clone(),
+            encoding_key: EncodingKey::from_rsa_pem(&signing_key.private_key)?,
+            public_keys: stored_keys
+                .iter()
+                .map(|key| JwtKey {
+                    key_id: key.id.clone(),
+                    key_type: "RSA".to_string(),
+                    public_use: "sig".to_string(),
+                    rsa_e: key.rsa_e.clone(),
+                    rsa_n: key.rsa_n.clone(),
+                    algorithm: "RS256".to_string(),
+                })
+                .collect(),
+            changes_at,
+        })
+    }
+
+    fn is_current(&self, now: DateTime<Utc>) -> bool {
+        self.changes_at.map_or(true, |changes_at| now < changes_at)
+    }
+}
+
+/// When `replace_signing_key` generates a new key.
+enum Replacement {
+    Always,
+    IfMissing,
+    IfCreatedBefore(DateTime<Utc>),
+}
+
+impl Replacement {
+    fn is_needed(&self, stored_keys: &[StoredSigningKey], now: DateTime<Utc>) -> bool {
+        match self {
+            Replacement::Always => true,
+            Replacement::IfMissing => !stored_keys.iter().any(|key| is_active(key, now)),
+            // Keys that are scheduled to retire have already been replaced.
+            Replacement::IfCreatedBefore(cutoff) => stored_keys
+                .iter()
+                .filter(|key| key.retired_at.is_none())
+                .map(|key| key.created_at)
+                .max()
+                .map_or(true, |created_at| created_at < *cutoff),
+        }
+    }
+}
+
+struct GeneratedKey {
+    id: String,
+    private_key: Vec<u8>,
+    rsa_n: String,
+    rsa_e: String,
+}
+
+fn generate_key() -> anyhow::Result<GeneratedKey> {
+    let rsa = Rsa::generate(RSA_KEY_BITS)?;
+    Ok(GeneratedKey {
+        id: format!("sig-{}", random_token()),
+        private_key: rsa.private_key_to_pem()?,
+        rsa_n: base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+        rsa_e: base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+    })
+}
+
+/// Generates a new signing key if `replacement` calls for it, returning the
+/// new key's id. The key activates after `PUBLICATION_DELAY_SECONDS`, at
+/// which point the other keys retire. Servers check again once the keys are
+/// locked, so only one of them replaces the key.
+async fn replace_signing_key(replacement: Replacement) -> anyhow::Result<Option<String>> {
+    let key = tokio::task::spawn_blocking(generate_key).await??;
+
+    let mut tx = pg().begin().await?;
+    database::lock_jwt_signing_keys(&mut tx).await?;
+    let now = Utc::now();
+    let stored_keys = database::list_jwt_signing_keys(&mut tx, retirement_cutoff()).await?;
+    if !replacement.is_needed(&stored_keys, now) {
+        return Ok(None);
+    }
+    // Until a key activates nothing can be signed, so a key replacing a
+    // missing one activates immediately.
+    let activates_at = if Replacement::IfMissing.is_needed(&stored_keys, now) {
+        now
+    } else {
+        now + Duration::seconds(PUBLICATION_DELAY_SECONDS)
+    };
+    database::insert_jwt_signing_key(
+        &mut tx,
+        &key.id,
+        &key.private_key,
+        &key.rsa_n,
+        &key.rsa_e,
+        activates_at,
+    )
+    .await?;
+    database::retire_other_jwt_signing_keys(&mut tx, &key.id, activates_at).await?;
+    tx.commit().await?;
+
+    info!(
+        "Generated jwt signing key {}, which activates at {}",
+        key.id, activates_at
+    );
+    pubsub::notify("jwt_signing_keys_updated", &key.id).await?;
+    Ok(Some(key.id))
+}
+
+/// Replaces the signing key once it is `ROTATION_DAYS` old, and deletes
+/// retired keys once they are no longer published.
+pub async fn rotation_loop() {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        ROTATION_CHECK_INTERVAL_SECONDS,
+    ));
+    loop {
+        interval.tick().await;
+        if let Err(err) = rotate().await {
+            error!("Error rotating jwt signing keys: {:?}", err);
+        }
+    }
+}
+
+async fn rotate() -> anyhow::Result<()> {
+    let deleted = database::delete_retired_jwt_signing_keys(&pg(), retirement_cutoff()).await?;
+    if deleted > 0 {
+        info!("Deleted {} retired jwt signing keys", deleted);
+    }
+
+    let replacement = Replacement::IfCreatedBefore(Utc::now() - Duration::days(ROTATION_DAYS));
+    if replacement.is_needed(&published_keys().await?, Utc::now()) {
+        replace_signing_key(replacement).await?;
+    } else if deleted > 0 {
+        pubsub::notify("jwt_signing_keys_updated", "").await?;
+    }
+    Ok(())
+}
+
+/// Handles `ncog-server jwt-keys <list|generate|retire KEY_ID>`.
+pub async fn command(args: &[String]) -> anyhow::Result<()> {
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["list"] => {
+            let now = Utc::now();
+            for key in published_keys().await? {
+                let status = match key.retired_at {
+                    Some(retired_at) if retired_at <= now => format!("retired {}", retired_at),
+                    _ if key.activates_at > now => format!("activates {}", key.activates_at),
+                    Some(retired_at) => format!("active\tretires {}", retired_at),
+                    None => "active".to_owned(),
+                };
+                println!("{}\tcreated {}\t{}", key.id, key.created_at, status);
+            }
+        }
+        ["generate"] => {
+            if let Some(key_id) = replace_signing_key(Replacement::Always).await? {
+                println!("Generated {}", key_id);
+            }
+        }
+        ["retire", key_id] => {
+            if !database::retire_jwt_signing_key(&pg(), key_id).await? {
+                anyhow::bail!("{} is not an active signing key", key_id);
+            }
+            println!("Retired {}", key_id);
+            pubsub::notify("jwt_signing_keys_updated", key_id).await?;
+            // Retiring the last active key would leave nothing to sign with.
+            if let Some(key_id) = replace_signing_key(Replacement::IfMissing).await? {
+                println!("Generated {}", key_id);
+            }
+        }
+        _ => anyhow::bail!("usage: jwt-keys <list|generate|retire KEY_ID>"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored_key(
+        generated: &GeneratedKey,
+        id: &str,
+        created_at: DateTime<Utc>,
+        activates_at: DateTime<Utc>,
+        retired_at: Option<DateTime<Utc>>,
+    ) -> StoredSigningKey {
+        StoredSigningKey {
+            id: id.to_owned(),
+            private_key: generated.private_key.clone(),
+            rsa_n: generated.rsa_n.clone(),
+            rsa_e: generated.rsa_e.clone(),
+            created_at,
+            activates_at,
+            retired_at,
+        }
+    }
+
+    /// A key that has signed for `ROTATION_DAYS` and the pending key
+    /// replacing it, newest first.
+    fn rotating_keys(now: DateTime<Utc>) -> Vec<StoredSigningKey> {
+        let generated = generate_key().unwrap();
+        let activates_at = now + Duration::seconds(PUBLICATION_DELAY_SECONDS);
+        vec![
+            stored_key(&generated, "pending", now, activates_at, None),
+            stored_key(
+                &generated,
+                "current",
+                now - Duration::days(ROTATION_DAYS),
+                now - Duration::days(ROTATION_DAYS),
+                Some(activates_at),
+            ),
+        ]
+    }
+
+    #[test]
+    fn pending_keys_are_published_before_signing() {
+        let now = Utc::now();
+        let activates_at = now + Duration::seconds(PUBLICATION_DELAY_SECONDS);
+
+        let keys = SigningKeys::new(rotating_keys(now), now).unwrap();
+        assert_eq!(keys.signing_key_id, "current");
+        let published = keys
+            .public_keys
+            .iter()
+            .map(|key| key.key_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(published, vec!["pending", "current"]);
+        assert_eq!(keys.changes_at, Some(activates_at));
+        assert!(keys.is_current(activates_at - Duration::seconds(1)));
+        assert!(!keys.is_current(activates_at));
+
+        let keys = SigningKeys::new(rotating_keys(now), activates_at).unwrap();
+        assert_eq!(keys.signing_key_id, "pending");
+        assert_eq!(keys.public_keys.len(), 2);
+        assert_eq!(keys.changes_at, None);
+    }
+
+    #[test]
+    fn replacement_needs() {
+        let now = Utc::now();
+        let keys = rotating_keys(now);
+        let rotation_cutoff = now - Duration::days(ROTATION_DAYS) + Duration::hours(1);
+
+        assert!(!Replacement::IfMissing.is_needed(&keys, now));
+        // The pending key already replaces the expiring one.
+        assert!(!Replacement::IfCreatedBefore(rotation_cutoff).is_needed(&keys, now));
+        let generated = generate_key().unwrap();
+        let expiring = stored_key(
+            &generated,
+            "expiring",
+            rotation_cutoff - Duration::hours(2),
+            rotation_cutoff - Duration::hours(2),
+            None,
+        );
+        assert!(Replacement::IfCreatedBefore(rotation_cutoff).is_needed(&[expiring], now));
+        assert!(Replacement::Always.is_needed(&keys, now));
+
+        // A key that hasn't activated yet can't sign in the meantime.
+        assert!(Replacement::IfMissing.is_needed(&keys[..1], now));
+        assert!(Replacement::IfMissing.is_needed(&[], now));
+    }
+
+    #[test]
+    fn generated_key_ids_are_unique() {
+        assert_ne!(generate_key().unwrap().id, generate_key().unwrap().id);
+    }
+}
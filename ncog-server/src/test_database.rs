@@ -0,0 +1,45 @@
+//! Connections for tests that run against the database named by
+//! `DATABASE_URL`. Tests should make their changes within a transaction that
+//! is never committed, so that they leave nothing behind.
+
+use ncog_migrations::sqlx::PgPool;
+use std::sync::Once;
+
+static CONFIGURE_ENVIRONMENT: Once = Once::new();
+
+lazy_static::lazy_static! {
+    static ref MIGRATED: tokio::sync::Mutex<bool> = tokio::sync::Mutex::new(false);
+}
+
+/// Connects to the test database, migrating it the first time it's called.
+/// Each test has its own pool, because connections can't be shared between
+/// the runtimes of separate tests.
+pub async fn pool() -> PgPool {
+    // Tests run on separate threads, so the environment is only changed once,
+    // before any test has a pool to use.
+    CONFIGURE_ENVIRONMENT.call_once(|| {
+        dotenv::dotenv().ok();
+        // Stored secrets need a master key, which development environments may
+        // not configure.
+        if std::env::var("ENCRYPTION_KEYS").is_err() {
+            std::env::set_var(
+                "ENCRYPTION_KEYS",
+                format!("test={}", base64::encode([7; 32])),
+            );
+        }
+    });
+
+    let pool = PgPool::new(&std::env::var("DATABASE_URL").expect("DATABASE_URL not set"))
+        .await
+        .expect("Error connecting to the test database");
+
+    let mut migrated = MIGRATED.lock().await;
+    if !*migrated {
+        ncog_migrations::run_all_on(&pool)
+            .await
+            .expect("Error migrating the test database");
+        *migrated = true;
+    }
+
+    pool
+}
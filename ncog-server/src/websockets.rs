@@ -1,13 +1,10 @@
-use super::{database, twitch};
+use super::{database, device, identity_tokens, oauth, oidc, signing_keys};
 use async_trait::async_trait;
-use chrono::{Duration, Utc};
 use ncog_migrations::pg;
 use ncog_shared::{
-    jsonwebtoken::{self, EncodingKey},
-    jwk::JwtKey,
-    ncog_protocol_version_requirements,
-    permissions::Claim,
-    AuthenticatedUser, IdentityVerificationClaims, NcogRequest, NcogResponse, OAuthProvider,
+    connect_claim, ncog_protocol_version_requirements,
+    permissions::{Claim, Statement},
+    AuthenticatedUser, ConnectionRefusal, NcogRequest, NcogResponse,
 };
 use uuid::Uuid;
 mod iam;
@@ -16,12 +13,49 @@ use basws_server::prelude::*;
 #[async_trait]
 pub trait ConnectedAccountHandle {
     async fn permission_allowed(&self, claim: &Claim) -> Result<(), anyhow::Error>;
+    /// Checks that the account holds everything `statement` grants, which
+    /// prevents anyone from handing out permissions they don't have.
+    async fn grant_allowed(&self, statement: &Statement) -> Result<(), anyhow::Error>;
+    /// Checks that replacing `previous` with `next` doesn't grant anything the
+    /// account doesn't hold, including the claims a removed deny blocked.
+    async fn statement_change_allowed(
+        &self,
+        previous: Option<&Statement>,
+        next: Option<&Statement>,
+    ) -> Result<(), anyhow::Error>;
 }
 
 fn permission_denied(claim: &Claim) -> Result<(), anyhow::Error> {
     anyhow::bail!("permission denied for accessing {:?}", claim)
 }
 
+fn grant_denied(statement: &Statement) -> Result<(), anyhow::Error> {
+    anyhow::bail!("permission denied for granting {:?}", statement)
+}
+
+fn statement_change_denied(
+    previous: Option<&Statement>,
+    next: Option<&Statement>,
+) -> Result<(), anyhow::Error> {
+    anyhow::bail!(
+        "permission denied for changing {:?} to {:?}",
+        previous,
+        next
+    )
+}
+
+fn not_authenticated_error() -> NcogResponse {
+    NcogResponse::Error {
+        message: Some("this request requires being authenticated".to_string()),
+    }
+}
+
+fn authorization_request_not_found() -> NcogResponse {
+    NcogResponse::Error {
+        message: Some("this request has expired or was already answered".to_string()),
+    }
+}
+
 #[async_trait]
 impl ConnectedAccountHandle for ConnectedClient<NcogServer> {
     async fn permission_allowed(&self, claim: &Claim) -> Result<(), anyhow::Error> {
@@ -31,23 +65,73 @@ impl ConnectedAccountHandle for ConnectedClient<NcogServer> {
 
         permission_denied(claim)
     }
+
+    async fn grant_allowed(&self, statement: &Statement) -> Result<(), anyhow::Error> {
+        if let Some(account) = self.account().await {
+            return account.grant_allowed(statement).await;
+        }
+
+        grant_denied(statement)
+    }
+
+    async fn statement_change_allowed(
+        &self,
+        previous: Option<&Statement>,
+        next: Option<&Statement>,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(account) = self.account().await {
+            return account.statement_change_allowed(previous, next).await;
+        }
+
+        statement_change_denied(previous, next)
+    }
 }
 
 #[async_trait]
 impl ConnectedAccountHandle for Handle<ConnectedAccount> {
     async fn permission_allowed(&self, claim: &Claim) -> Result<(), anyhow::Error> {
         let account = self.read().await;
-        if account.user.permissions.allowed(&claim) {
+        if account.refusal.is_none() && account.user.permissions.allowed(&claim) {
             Ok(())
         } else {
             permission_denied(claim)
         }
     }
+
+    async fn grant_allowed(&self, statement: &Statement) -> Result<(), anyhow::Error> {
+        let account = self.read().await;
+        if account.refusal.is_none() && account.user.permissions.allows_statement(statement) {
+            Ok(())
+        } else {
+            grant_denied(statement)
+        }
+    }
+
+    async fn statement_change_allowed(
+        &self,
+        previous: Option<&Statement>,
+        next: Option<&Statement>,
+    ) -> Result<(), anyhow::Error> {
+        let account = self.read().await;
+        if account.refusal.is_none()
+            && account
+                .user
+                .permissions
+                .allows_statement_change(previous, next)
+        {
+            Ok(())
+        } else {
+            statement_change_denied(previous, next)
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ConnectedAccount {
     pub user: AuthenticatedUser,
+    /// Set when the account was suspended or lost the `connect_claim` while
+    /// connected. Every permission check fails until it is cleared.
+    pub refusal: Option<ConnectionRefusal>,
 }
 
 impl ConnectedAccount {
@@ -61,8 +145,42 @@ impl ConnectedAccount {
                 profile,
                 permissions,
             },
+            refusal: None,
+        })
+    }
+
+    /// Looks up the account associated with `installation_id`, returning why
+    /// it may not connect if it's suspended or lacks the `connect_claim`.
+    pub async fn connect(installation_id: Uuid) -> anyhow::Result<Result<Self, ConnectionRefusal>> {
+        let mut account = Self::lookup(installation_id).await?;
+        account.refresh_refusal().await?;
+        Ok(match account.refusal.take() {
+            Some(refusal) => Err(refusal),
+            None => Ok(account),
         })
     }
+
+    pub async fn refresh_refusal(&mut self) -> anyhow::Result<()> {
+        self.refusal = if let Some(suspension) =
+            database::get_account_suspension(&pg(), self.user.profile.id).await?
+        {
+            Some(ConnectionRefusal::Suspended(suspension))
+        } else if !self.user.permissions.allowed(&connect_claim()) {
+            Some(ConnectionRefusal::NotPermitted)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// The response that tells the account's clients whether they're
+    /// authenticated.
+    pub fn status_response(&self) -> NcogResponse {
+        match &self.refusal {
+            Some(refusal) => NcogResponse::ConnectionRefused(refusal.clone()),
+            None => NcogResponse::Authenticated(self.user.clone()),
+        }
+    }
 }
 
 impl Identifiable for ConnectedAccount {
@@ -91,55 +209,250 @@ impl ServerLogic for NcogServer {
         request: Self::Request,
         _server: &Server<Self>,
     ) -> anyhow::Result<RequestHandling<Self::Response>> {
+        // Accounts that were cut off while connected may still sign in again.
+        if !matches!(
+            request,
+            NcogRequest::AuthenticationUrl(_)
+                | NcogRequest::RequestDeviceAuthorization
+                | NcogRequest::ListPublicJwtKeys
+        ) {
+            if let Some(account) = client.account().await {
+                let account = account.read().await;
+                if let Some(refusal) = &account.refusal {
+                    return Ok(RequestHandling::Respond(NcogResponse::ConnectionRefused(
+                        refusal.clone(),
+                    )));
+                }
+            }
+        }
+
         match request {
-            NcogRequest::AuthenticationUrl(provider) => match provider {
-                OAuthProvider::Twitch => {
-                    if let Some(installation) = client.installation().await {
-                        Ok(RequestHandling::Respond(NcogResponse::AuthenticateAtUrl {
-                            url: twitch::authorization_url(installation.id),
+            NcogRequest::AuthenticationUrl(provider_name) => {
+                let provider = match oauth::provider(&provider_name) {
+                    Some(provider) => provider,
+                    None => {
+                        return Ok(RequestHandling::Respond(NcogResponse::Error {
+                            message: Some(format!("unknown provider {}", provider_name)),
                         }))
+                    }
+                };
+                if let Some(installation) = client.installation().await {
+                    Ok(RequestHandling::Respond(NcogResponse::AuthenticateAtUrl {
+                        url: oauth::authorization_url(provider.as_ref(), installation.id, None)
+                            .await?,
+                    }))
+                } else {
+                    anyhow::bail!("Requested authentication URL without being connected")
+                }
+            }
+            NcogRequest::RequestDeviceAuthorization => {
+                if let Some(installation) = client.installation().await {
+                    Ok(RequestHandling::Respond(NcogResponse::DeviceAuthorization(
+                        device::start(installation.id).await?,
+                    )))
+                } else {
+                    anyhow::bail!("Requested device authorization without being connected")
+                }
+            }
+            NcogRequest::AuthorizeDevice { user_code } => match client.account().await {
+                Some(account) => {
+                    let account_id = account.read().await.id();
+                    if device::authorize(&user_code, account_id).await? {
+                        Ok(RequestHandling::Respond(NcogResponse::DeviceAuthorized))
                     } else {
-                        anyhow::bail!("Requested authentication URL without being connected")
+                        Ok(RequestHandling::Respond(NcogResponse::Error {
+                            message: Some(
+                                "this code has expired or was already entered".to_string(),
+                            ),
+                        }))
                     }
                 }
+                None => Ok(RequestHandling::Respond(not_authenticated_error())),
             },
-            NcogRequest::IAM(iam_request) => iam::handle_request(client, iam_request).await,
-            NcogRequest::ListPublicJwtKeys => {
-                Ok(RequestHandling::Respond(NcogResponse::JwtPublicKeys(vec![
-                                JwtKey {
-                                    key_id: "sig-1602646606".to_string(),
-                                    key_type: "RSA".to_string(),
-                                    public_use: "sig".to_string(),
-                                    rsa_e: "AQAB".to_string(),
-                                    rsa_n: "uJx3D72SGsVZWny06jHms1o0TE8NMn2xDuBODnFzZbTxp0M_KXfPRZbW0fTVm3NRmaZ9c1MgQI0TyZ-zNw832dkNuHUaIXzRFWD2OuuRWmpHwfjMN1o6RZ5phfzJcbd1Qf0AmZLsAAIWSlPvWrow2GOIizqLcVokHx0dWkeMDQrWnLIQmcYKG5P18HpJPg_51X7A301ejavo91CZfWmKdHPN8If6ZB1koMJ8CAqPbfAtkapmrHZ6AA7m2fKNsVukKHzeqlYpEFcVEjWFIjZvtl8zz6y2ZXpsW7whu7MP816Jn0pOmaPmll41U7jbx703x6hiobozeK_QNaipCquFhQ".to_string(),
-                                    algorithm: "RS256".to_string(),
-                                }])))
+            NcogRequest::LinkIdentityUrl(provider_name) => {
+                let provider = match oauth::provider(&provider_name) {
+                    Some(provider) => provider,
+                    None => {
+                        return Ok(RequestHandling::Respond(NcogResponse::Error {
+                            message: Some(format!("unknown provider {}", provider_name)),
+                        }))
+                    }
+                };
+                match (client.installation().await, client.account().await) {
+                    (Some(installation), Some(account)) => {
+                        let account_id = account.read().await.id();
+                        Ok(RequestHandling::Respond(NcogResponse::AuthenticateAtUrl {
+                            url: oauth::authorization_url(
+                                provider.as_ref(),
+                                installation.id,
+                                Some(account_id),
+                            )
+                            .await?,
+                        }))
+                    }
+                    _ => Ok(RequestHandling::Respond(not_authenticated_error())),
+                }
+            }
+            NcogRequest::ListLinkedIdentities => match client.account().await {
+                Some(account) => {
+                    let account_id = account.read().await.id();
+                    Ok(RequestHandling::Respond(NcogResponse::LinkedIdentities(
+                        database::list_external_identities(&pg(), account_id).await?,
+                    )))
+                }
+                None => Ok(RequestHandling::Respond(not_authenticated_error())),
+            },
+            NcogRequest::UnlinkIdentity {
+                provider,
+                external_id,
+            } => match client.account().await {
+                Some(account) => {
+                    let account_id = account.read().await.id();
+                    let mut tx = pg().begin().await?;
+                    database::lock_account(&mut tx, account_id).await?;
+                    let identities =
+                        database::list_external_identities(&mut tx, account_id).await?;
+                    if identities.len() <= 1 {
+                        return Ok(RequestHandling::Respond(NcogResponse::Error {
+                            message: Some("the last linked identity can't be unlinked".to_string()),
+                        }));
+                    }
+                    if !database::delete_external_identity(
+                        &mut tx,
+                        account_id,
+                        &provider,
+                        &external_id,
+                    )
+                    .await?
+                    {
+                        return Ok(RequestHandling::Respond(NcogResponse::Error {
+                            message: Some("identity not found".to_string()),
+                        }));
+                    }
+                    let unlinked_token = database::take_identity_oauth_token(
+                        &mut tx,
+                        account_id,
+                        &provider,
+                        &external_id,
+                    )
+                    .await?;
+                    let identities =
+                        database::list_external_identities(&mut tx, account_id).await?;
+                    tx.commit().await?;
+
+                    if let Some(token) = unlinked_token {
+                        tokio::spawn(oauth::revoke(token));
+                    }
+
+                    Ok(RequestHandling::Respond(NcogResponse::LinkedIdentities(
+                        identities,
+                    )))
+                }
+                None => Ok(RequestHandling::Respond(not_authenticated_error())),
+            },
+            NcogRequest::GetAuthorizationRequest(request_id) => {
+                let installation = client.installation().await.ok_or_else(|| {
+                    anyhow::anyhow!("Requested authorization request without being connected")
+                })?;
+                match oidc::view_request(&request_id, installation.id).await? {
+                    Some(request) => Ok(RequestHandling::Respond(
+                        NcogResponse::AuthorizationRequest(request),
+                    )),
+                    None => Ok(RequestHandling::Respond(authorization_request_not_found())),
+                }
+            }
+            NcogRequest::AnswerAuthorizationRequest { id, approved } => {
+                match client.account().await {
+                    Some(account) => {
+                        let account_id = account.read().await.id();
+                        match oidc::answer_request(&id, account_id, approved).await? {
+                            Some(url) => {
+                                Ok(RequestHandling::Respond(NcogResponse::RedirectTo { url }))
+                            }
+                            None => Ok(RequestHandling::Respond(authorization_request_not_found())),
+                        }
+                    }
+                    None => Ok(RequestHandling::Respond(not_authenticated_error())),
+                }
             }
-            NcogRequest::RequestIdentityVerificationToken { nonce, audience } => {
+            NcogRequest::IAM(iam_request) => iam::handle_request(client, iam_request).await,
+            NcogRequest::ListPublicJwtKeys => Ok(RequestHandling::Respond(
+                NcogResponse::JwtPublicKeys(signing_keys::public_keys().await?),
+            )),
+            NcogRequest::ListRevokedIdentityTokens => Ok(RequestHandling::Respond(
+                NcogResponse::RevokedIdentityTokens(
+                    database::list_revoked_identity_verification_tokens(&pg()).await?,
+                ),
+            )),
+            NcogRequest::RequestIdentityVerificationToken {
+                nonce,
+                audience,
+                services,
+            } => {
                 if let Some(account) = client.account().await {
-                    let account = account.read().await;
-                    let encoding_key = EncodingKey::from_rsa_pem(std::env!("JWK_RSA_PRIVATE_KEY_PEM").as_bytes())?;
-                    let issuance_time = Utc::now();
-                    let expiration_time = issuance_time.checked_add_signed(Duration::minutes(5)).unwrap();
-                    let issuance_time = issuance_time.timestamp() as u64;
-                    let expiration_time = expiration_time.timestamp() as u64;
-                    let subject = account.user.profile.id.to_string();
-                    let claims = IdentityVerificationClaims {
-                        issuer: "https://ncog.id/v1/ws".to_string(),
-                        subject,
+                    // Tokens are only issued for audiences registered to an
+                    // application.
+                    let application =
+                        match database::get_application_by_audience(&pg(), &audience).await? {
+                            Some(application) => application,
+                            None => {
+                                return Ok(RequestHandling::Respond(NcogResponse::Error {
+                                    message: Some(format!("unknown audience {}", audience)),
+                                }))
+                            }
+                        };
+                    let user = account.read().await.user.clone();
+                    let installation_id = client
+                        .installation()
+                        .await
+                        .map(|installation| installation.id);
+                    let token = match identity_tokens::issue(
+                        &user,
+                        installation_id,
                         audience,
                         nonce,
-                        issuance_time,
-                        expiration_time,
-                        ncog_profile: account.user.profile.clone(),
-                        ncog_permissions: account.user.permissions.clone().into(),
+                        services,
+                    )
+                    .await?
+                    {
+                        Ok(token) => token,
+                        Err(refusal) => {
+                            return Ok(RequestHandling::Respond(NcogResponse::Error {
+                                message: Some(refusal.message().to_string()),
+                            }))
+                        }
                     };
-                    let token = jsonwebtoken::encode(&jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256), &claims, &encoding_key)?;
-                    Ok(RequestHandling::Respond(NcogResponse::IdentityVerificationToken {  token }))
+                    Ok(RequestHandling::Respond(
+                        NcogResponse::IdentityVerificationToken {
+                            token,
+                            application_name: application.name,
+                        },
+                    ))
                 } else {
-                    Ok(RequestHandling::Respond(NcogResponse::Error { message: Some("cannot request identity verification without being authenticated".to_string())}))
+                    Ok(RequestHandling::Respond(NcogResponse::Error {
+                        message: Some(
+                            "cannot request identity verification without being authenticated"
+                                .to_string(),
+                        ),
+                    }))
                 }
             }
+            NcogRequest::IntrospectIdentityToken(token) => Ok(RequestHandling::Respond(
+                NcogResponse::IdentityTokenIntrospection(
+                    identity_tokens::introspect(&token).await?,
+                ),
+            )),
+            NcogRequest::RevokeIdentityTokens => match client.account().await {
+                Some(account) => {
+                    let account_id = account.read().await.id();
+                    database::revoke_identity_verification_tokens_for_account(&pg(), account_id)
+                        .await?;
+                    Ok(RequestHandling::Respond(
+                        NcogResponse::IdentityTokensRevoked,
+                    ))
+                }
+                None => Ok(RequestHandling::Respond(not_authenticated_error())),
+            },
         }
     }
 
@@ -147,9 +460,10 @@ impl ServerLogic for NcogServer {
         &self,
         installation_id: Uuid,
     ) -> anyhow::Result<Option<Handle<Self::Account>>> {
-        Ok(ConnectedAccount::lookup(installation_id)
+        Ok(ConnectedAccount::connect(installation_id)
             .await
             .ok()
+            .and_then(Result::ok)
             .map(Handle::new))
     }
 
@@ -176,10 +490,17 @@ impl ServerLogic for NcogServer {
         if let Some(account) = client.account().await {
             let account = account.read().await;
 
-            Ok(RequestHandling::Respond(NcogResponse::Authenticated(
-                account.user.clone(),
-            )))
+            Ok(RequestHandling::Respond(account.status_response()))
         } else {
+            // The account lookup doesn't return refused accounts, so check
+            // again to tell the client why it isn't authenticated.
+            if let Some(installation) = client.installation().await {
+                if let Ok(Err(refusal)) = ConnectedAccount::connect(installation.id).await {
+                    return Ok(RequestHandling::Respond(NcogResponse::ConnectionRefused(
+                        refusal,
+                    )));
+                }
+            }
             Ok(RequestHandling::Respond(NcogResponse::Unauthenticated))
         }
     }
@@ -195,7 +516,11 @@ impl ServerLogic for NcogServer {
         if let Some(installation) = client.installation().await {
             if let Some(account) = client.account().await {
                 let account_id = {
-                    let account = account.read().await;
+                    let mut account = account.write().await;
+                    account.refresh_refusal().await?;
+                    if let Some(refusal) = &account.refusal {
+                        anyhow::bail!("account {} refused: {:?}", account.id(), refusal);
+                    }
                     account.id()
                 };
                 database::set_installation_account_id(&pg(), installation.id, Some(account_id))
@@ -1,41 +1,52 @@
 use crate::{
-    database,
+    database::{self, ListCursor},
+    oauth::random_token,
+    oidc,
     websockets::{ConnectedAccountHandle, ConnectedClient},
 };
 use basws_server::RequestHandling;
-use ncog_migrations::pg;
+use ncog_migrations::{pg, sqlx};
 use ncog_shared::{
     iam::{
-        roles_delete_claim, roles_list_claim, roles_read_claim, roles_update_claim,
-        users_list_claim, users_read_claim, IAMRequest, IAMResponse,
+        applications_create_claim, applications_delete_claim, applications_list_claim,
+        applications_read_claim, applications_update_claim, audit_log_read_claim,
+        roles_assign_claim, roles_delete_claim, roles_list_claim, roles_read_claim,
+        roles_update_claim, users_list_claim, users_merge_claim, users_read_claim,
+        users_read_permissions_claim, users_revoke_tokens_claim, users_suspend_claim,
+        users_update_claim, ApplicationSummary, IAMRequest, IAMResponse, PermissionStatement,
+        RoleSummary,
     },
+    permissions::{Claim, Statement},
     NcogResponse,
 };
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{postgres::Postgres, Executor};
+use std::str::FromStr;
+use uuid::Uuid;
 
 pub async fn handle_request(
     client_handle: &ConnectedClient<super::NcogServer>,
     request: IAMRequest,
 ) -> anyhow::Result<RequestHandling<NcogResponse>> {
     match request {
-        IAMRequest::UsersList => {
+        IAMRequest::UsersList(query) => {
+            // Listing pages through every user, so it needs read access to
+            // all of them rather than filtering each page, which would leave
+            // the cursor and total count out of step with the entries.
             client_handle
                 .permission_allowed(&users_list_claim())
                 .await?;
+            client_handle
+                .permission_allowed(&users_read_claim(None))
+                .await?;
 
-            let mut users = Vec::new();
-
-            for user in database::iam_list_users(&pg()).await? {
-                if client_handle
-                    .permission_allowed(&users_read_claim(user.id))
-                    .await
-                    .is_ok()
-                {
-                    users.push(user);
-                }
-            }
+            let cursor = parse_list_cursor(&query.cursor)?;
+            let page =
+                database::iam_list_users(&pg(), &query, cursor, list_limit(query.limit)).await?;
 
             Ok(RequestHandling::Respond(NcogResponse::IAM(
-                IAMResponse::UsersList(users),
+                IAMResponse::UsersList(page),
             )))
         }
         IAMRequest::UsersGetProfile(account_id) => {
@@ -52,25 +63,233 @@ pub async fn handle_request(
                 None => anyhow::bail!("Unknown user id {}", account_id),
             }
         }
-        IAMRequest::RolesList => {
+        IAMRequest::UserAddRole {
+            account_id,
+            role_id,
+        } => {
             client_handle
-                .permission_allowed(&roles_list_claim())
+                .permission_allowed(&users_update_claim(Some(account_id)))
+                .await?;
+            client_handle
+                .permission_allowed(&roles_assign_claim(Some(role_id)))
                 .await?;
+            role_grant_allowed(client_handle, role_id).await?;
 
-            let mut roles = Vec::new();
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            database::iam_add_account_role(&mut tx, account_id, role_id).await?;
+            actor
+                .record(
+                    &mut tx,
+                    "UserAddRole",
+                    None,
+                    Some(&json!({ "account_id": account_id, "role_id": role_id })),
+                )
+                .await?;
+            tx.commit().await?;
 
-            for role in database::iam_list_roles(&pg()).await? {
-                if client_handle
-                    .permission_allowed(&roles_read_claim(role.id))
-                    .await
-                    .is_ok()
-                {
-                    roles.push(role);
+            broadcast_account_roles_changed(account_id).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::UserRoleAdded {
+                    account_id,
+                    role_id,
+                },
+            )))
+        }
+        IAMRequest::UserRemoveRole {
+            account_id,
+            role_id,
+        } => {
+            client_handle
+                .permission_allowed(&users_update_claim(Some(account_id)))
+                .await?;
+            client_handle
+                .permission_allowed(&roles_assign_claim(Some(role_id)))
+                .await?;
+            role_removal_allowed(client_handle, role_id).await?;
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            database::iam_remove_account_role(&mut tx, account_id, role_id).await?;
+            actor
+                .record(
+                    &mut tx,
+                    "UserRemoveRole",
+                    Some(&json!({ "account_id": account_id, "role_id": role_id })),
+                    None,
+                )
+                .await?;
+            tx.commit().await?;
+
+            broadcast_account_roles_changed(account_id).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::UserRoleRemoved {
+                    account_id,
+                    role_id,
+                },
+            )))
+        }
+        IAMRequest::UserSuspend {
+            account_id,
+            reason,
+            until,
+        } => {
+            client_handle
+                .permission_allowed(&users_suspend_claim(Some(account_id)))
+                .await?;
+
+            let actor = Actor::of(client_handle).await;
+            if actor.account_id == Some(account_id) {
+                return Ok(RequestHandling::Respond(NcogResponse::Error {
+                    message: Some("You cannot suspend your own account".to_string()),
+                }));
+            }
+
+            let mut tx = pg().begin().await?;
+            let before = database::get_account_suspension(&mut tx, account_id).await?;
+            database::iam_suspend_account(&mut tx, account_id, &reason, until).await?;
+            // Tokens already handed to game servers and OpenID Connect
+            // clients would otherwise stay trusted until they expire.
+            database::revoke_identity_verification_tokens_for_account(&mut tx, account_id).await?;
+            database::delete_oidc_grants_for_account(&mut tx, account_id).await?;
+            let after = database::get_account_suspension(&mut tx, account_id).await?;
+            actor
+                .record(&mut tx, "UserSuspend", before.as_ref(), after.as_ref())
+                .await?;
+            tx.commit().await?;
+
+            broadcast_account_suspension_changed(account_id).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::UserSuspended(account_id),
+            )))
+        }
+        IAMRequest::UserUnsuspend(account_id) => {
+            client_handle
+                .permission_allowed(&users_suspend_claim(Some(account_id)))
+                .await?;
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let before = database::get_account_suspension(&mut tx, account_id).await?;
+            database::iam_unsuspend_account(&mut tx, account_id).await?;
+            actor
+                .record(&mut tx, "UserUnsuspend", before.as_ref(), None)
+                .await?;
+            tx.commit().await?;
+
+            broadcast_account_suspension_changed(account_id).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::UserUnsuspended(account_id),
+            )))
+        }
+        IAMRequest::UserRevokeIdentityTokens(account_id) => {
+            client_handle
+                .permission_allowed(&users_revoke_tokens_claim(Some(account_id)))
+                .await?;
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            database::revoke_identity_verification_tokens_for_account(&mut tx, account_id).await?;
+            actor
+                .record(
+                    &mut tx,
+                    "UserRevokeIdentityTokens",
+                    None,
+                    Some(&json!({ "account_id": account_id })),
+                )
+                .await?;
+            tx.commit().await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::UserIdentityTokensRevoked(account_id),
+            )))
+        }
+        IAMRequest::UserMerge {
+            source_account_id,
+            target_account_id,
+        } => {
+            client_handle
+                .permission_allowed(&users_merge_claim(Some(source_account_id)))
+                .await?;
+            client_handle
+                .permission_allowed(&users_merge_claim(Some(target_account_id)))
+                .await?;
+
+            if source_account_id == target_account_id {
+                return Ok(RequestHandling::Respond(NcogResponse::Error {
+                    message: Some("An account cannot be merged into itself".to_string()),
+                }));
+            }
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            // Locking in a consistent order keeps concurrent merges of the
+            // same accounts from deadlocking.
+            database::lock_account(&mut tx, source_account_id.min(target_account_id)).await?;
+            database::lock_account(&mut tx, source_account_id.max(target_account_id)).await?;
+            let source = database::iam_get_user(&mut tx, source_account_id).await?;
+            let target = database::iam_get_user(&mut tx, target_account_id).await?;
+            match (&source, &target) {
+                (Some(source), Some(target))
+                    if source.merged_into_id.is_none() && target.merged_into_id.is_none() => {}
+                _ => {
+                    return Ok(RequestHandling::Respond(NcogResponse::Error {
+                        message: Some(
+                            "Both accounts must exist and not already be merged".to_string(),
+                        ),
+                    }))
                 }
             }
 
+            let conflicting_tokens = database::take_conflicting_oauth_tokens(
+                &mut tx,
+                source_account_id,
+                target_account_id,
+            )
+            .await?;
+            let installation_ids =
+                database::iam_merge_accounts(&mut tx, source_account_id, target_account_id).await?;
+            let before = json!({ "source": source, "target": target });
+            let after = json!({
+                "source": database::iam_get_user(&mut tx, source_account_id).await?,
+                "target": database::iam_get_user(&mut tx, target_account_id).await?,
+                "installation_ids": installation_ids,
+            });
+            actor
+                .record(&mut tx, "UserMerge", Some(&before), Some(&after))
+                .await?;
+            tx.commit().await?;
+
+            for token in conflicting_tokens {
+                tokio::spawn(crate::oauth::revoke(token));
+            }
+            broadcast_accounts_merged(source_account_id, target_account_id).await?;
+
             Ok(RequestHandling::Respond(NcogResponse::IAM(
-                IAMResponse::RolesList(roles),
+                IAMResponse::UsersMerged {
+                    source_account_id,
+                    target_account_id,
+                },
+            )))
+        }
+        IAMRequest::RolesList(query) => {
+            client_handle
+                .permission_allowed(&roles_list_claim())
+                .await?;
+            client_handle
+                .permission_allowed(&roles_read_claim(None))
+                .await?;
+
+            let cursor = parse_list_cursor(&query.cursor)?;
+            let page =
+                database::iam_list_roles(&pg(), &query, cursor, list_limit(query.limit)).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::RolesList(page),
             )))
         }
         IAMRequest::RoleGet(role_id) => {
@@ -78,7 +297,8 @@ pub async fn handle_request(
                 .permission_allowed(&roles_read_claim(Some(role_id)))
                 .await?;
 
-            let role = database::iam_get_role(&pg(), role_id).await?;
+            let mut connection = pg().acquire().await?;
+            let role = database::iam_get_role(&mut connection, role_id).await?;
 
             match role {
                 Some(role) => Ok(RequestHandling::Respond(NcogResponse::IAM(
@@ -92,7 +312,26 @@ pub async fn handle_request(
                 .permission_allowed(&roles_update_claim(role.id))
                 .await?;
 
-            let role_id = database::iam_update_role(&pg(), &role).await?;
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let before = match role.id {
+                Some(role_id) => database::iam_get_role(&mut tx, role_id)
+                    .await?
+                    .map(|before| RoleSummary {
+                        id: before.id,
+                        name: before.name,
+                    }),
+                None => None,
+            };
+            let role_id = database::iam_update_role(&mut tx, &role).await?;
+            let after = RoleSummary {
+                id: Some(role_id),
+                name: role.name,
+            };
+            actor
+                .record(&mut tx, "RoleSave", before.as_ref(), Some(&after))
+                .await?;
+            tx.commit().await?;
 
             Ok(RequestHandling::Respond(NcogResponse::IAM(
                 IAMResponse::RoleSaved(role_id),
@@ -103,13 +342,85 @@ pub async fn handle_request(
                 .permission_allowed(&roles_delete_claim(Some(role_id)))
                 .await?;
 
+            // Deleting the role deletes its statements and inclusions, which
+            // lifts its denies and those it inherits
+            role_removal_allowed(client_handle, role_id).await?;
+
+            let actor = Actor::of(client_handle).await;
             let mut tx = pg().begin().await?;
+            let before = database::iam_get_role(&mut tx, role_id).await?;
+            // The role is gone by the time other servers hear about it, so
+            // the accounts it applied to are refreshed instead.
+            let account_ids = database::iam_accounts_with_effective_role(&mut tx, role_id).await?;
             database::iam_delete_role(&mut tx, role_id).await?;
+            actor
+                .record(&mut tx, "RoleDelete", before.as_ref(), None)
+                .await?;
+            tx.commit().await?;
+
+            for account_id in account_ids {
+                broadcast_account_roles_changed(account_id).await?;
+            }
 
             Ok(RequestHandling::Respond(NcogResponse::IAM(
                 IAMResponse::RoleDeleted(role_id),
             )))
         }
+        IAMRequest::RoleIncludedRolesSave {
+            role_id,
+            included_role_ids,
+        } => {
+            client_handle
+                .permission_allowed(&roles_update_claim(Some(role_id)))
+                .await?;
+
+            // Including a role grants its statements to everyone with this
+            // role, and no longer including one lifts its denies
+            let currently_included = database::iam_included_role_ids(&pg(), role_id).await?;
+            for included_role_id in included_role_ids.iter() {
+                if !currently_included.contains(included_role_id) {
+                    role_grant_allowed(client_handle, *included_role_id).await?;
+                }
+            }
+            for included_role_id in currently_included.iter() {
+                if !included_role_ids.contains(included_role_id) {
+                    role_removal_allowed(client_handle, *included_role_id).await?;
+                }
+            }
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            database::iam_lock_role_inclusions(&mut tx).await?;
+            database::iam_clear_role_inclusions(&mut tx, role_id).await?;
+            for included_role_id in included_role_ids.iter().copied() {
+                if included_role_id == role_id
+                    || database::iam_role_includes(&mut tx, included_role_id, role_id).await?
+                {
+                    return Ok(RequestHandling::Respond(NcogResponse::Error {
+                        message: Some(format!(
+                            "including role {} in role {} would create a cycle",
+                            included_role_id, role_id
+                        )),
+                    }));
+                }
+                database::iam_add_role_inclusion(&mut tx, role_id, included_role_id).await?;
+            }
+            actor
+                .record(
+                    &mut tx,
+                    "RoleIncludedRolesSave",
+                    Some(&json!({ "role_id": role_id, "included_role_ids": currently_included })),
+                    Some(&json!({ "role_id": role_id, "included_role_ids": included_role_ids })),
+                )
+                .await?;
+            tx.commit().await?;
+
+            broadcast_role_changed(Some(role_id)).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::RoleIncludedRolesSaved(role_id),
+            )))
+        }
         IAMRequest::PermissionStatementGet(id) => {
             let statement = database::iam_get_permission_statement(&pg(), id).await?;
             client_handle
@@ -121,13 +432,48 @@ pub async fn handle_request(
             )))
         }
         IAMRequest::PermissionStatementSave(statement) => {
-            // TODO Validate that the user can edit the currently assigned role
-
             client_handle
                 .permission_allowed(&roles_update_claim(statement.role_id))
                 .await?;
 
-            let statement_id = database::iam_update_permission_statement(&pg(), &statement).await?;
+            let existing = match statement.id {
+                Some(id) => Some(database::iam_get_permission_statement(&pg(), id).await?),
+                None => None,
+            };
+
+            // Moving a statement out of a role is an update to that role too
+            if let Some(existing) = &existing {
+                if existing.role_id != statement.role_id {
+                    client_handle
+                        .permission_allowed(&roles_update_claim(existing.role_id))
+                        .await?;
+                }
+            }
+
+            client_handle
+                .statement_change_allowed(
+                    existing.as_ref().map(Statement::from).as_ref(),
+                    Some(&Statement::from(&statement)),
+                )
+                .await?;
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let statement_id =
+                database::iam_update_permission_statement(&mut tx, &statement).await?;
+            let after = PermissionStatement {
+                id: Some(statement_id),
+                ..statement.clone()
+            };
+            actor
+                .record(
+                    &mut tx,
+                    "PermissionStatementSave",
+                    existing.as_ref(),
+                    Some(&after),
+                )
+                .await?;
+            tx.commit().await?;
 
             broadcast_role_changed(statement.role_id).await?;
 
@@ -141,8 +487,17 @@ pub async fn handle_request(
             client_handle
                 .permission_allowed(&roles_update_claim(statement.role_id))
                 .await?;
+            client_handle
+                .statement_change_allowed(Some(&Statement::from(&statement)), None)
+                .await?;
 
-            database::iam_delete_permission_statement(&pg(), id).await?;
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            database::iam_delete_permission_statement(&mut tx, id).await?;
+            actor
+                .record(&mut tx, "PermissionStatementDelete", Some(&statement), None)
+                .await?;
+            tx.commit().await?;
 
             broadcast_role_changed(statement.role_id).await?;
 
@@ -150,7 +505,465 @@ pub async fn handle_request(
                 IAMResponse::PermissionStatementDeleted(id),
             )))
         }
+        IAMRequest::PermissionExplain(claim) => {
+            let account = client_handle.account().await.ok_or_else(|| {
+                anyhow::anyhow!("cannot explain permissions without being authenticated")
+            })?;
+            let account = account.read().await;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::PermissionExplanation(account.user.permissions.explain(&claim)),
+            )))
+        }
+        IAMRequest::PermissionsSimulate { account_id, claims } => {
+            let is_own_account = match client_handle.account().await {
+                Some(account) => account.read().await.user.profile.id == account_id,
+                None => false,
+            };
+            if !is_own_account {
+                client_handle
+                    .permission_allowed(&users_read_permissions_claim(Some(account_id)))
+                    .await?;
+            }
+
+            if database::get_profile_by_account_id(&pg(), account_id)
+                .await?
+                .is_none()
+            {
+                anyhow::bail!("Unknown user id {}", account_id);
+            }
+
+            let permissions = database::load_permissions_for(&pg(), account_id).await?;
+            let explanations = claims
+                .iter()
+                .map(|claim| permissions.explain(claim))
+                .collect();
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::PermissionsSimulated {
+                    account_id,
+                    explanations,
+                },
+            )))
+        }
+        IAMRequest::AuditLogQuery(query) => {
+            client_handle
+                .permission_allowed(&audit_log_read_claim())
+                .await?;
+
+            let limit = query
+                .limit
+                .unwrap_or(AUDIT_LOG_PAGE_SIZE)
+                .max(1)
+                .min(AUDIT_LOG_PAGE_SIZE);
+            let entries = database::iam_query_audit_log(&pg(), &query, limit).await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::AuditLog(entries),
+            )))
+        }
+        IAMRequest::ApplicationsList(query) => {
+            client_handle
+                .permission_allowed(&applications_list_claim())
+                .await?;
+
+            let actor = Actor::of(client_handle).await;
+            let cursor = parse_list_cursor(&query.cursor)?;
+            let mut page =
+                database::iam_list_applications(&pg(), &query, cursor, list_limit(query.limit))
+                    .await?;
+
+            let mut applications = Vec::new();
+            for application in page.entities {
+                if actor.owns(&application)
+                    || client_handle
+                        .permission_allowed(&applications_read_claim(application.id.as_deref()))
+                        .await
+                        .is_ok()
+                {
+                    applications.push(application);
+                }
+            }
+            page.entities = applications;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::ApplicationsList(page),
+            )))
+        }
+        IAMRequest::ApplicationGet(application_id) => {
+            application_access_allowed(
+                client_handle,
+                &application_id,
+                &applications_read_claim(Some(&application_id)),
+            )
+            .await?;
+
+            let mut connection = pg().acquire().await?;
+            match database::iam_get_application(&mut connection, &application_id).await? {
+                Some(application) => Ok(RequestHandling::Respond(NcogResponse::IAM(
+                    IAMResponse::Application(application),
+                ))),
+                None => anyhow::bail!("Unknown application id {}", application_id),
+            }
+        }
+        IAMRequest::ApplicationSave {
+            mut application,
+            public,
+        } => {
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let (application_id, client_secret) = match application.id.clone() {
+                Some(application_id) => {
+                    let before: ApplicationSummary =
+                        database::get_application(&mut tx, &application_id)
+                            .await?
+                            .ok_or_else(|| {
+                                anyhow::anyhow!("Unknown application id {}", application_id)
+                            })?
+                            .into();
+                    application_access_allowed(
+                        client_handle,
+                        &application_id,
+                        &applications_update_claim(Some(&application_id)),
+                    )
+                    .await?;
+                    // Owners can't hand their applications to someone else
+                    // without the IAM permission.
+                    if before.owner_account_id != application.owner_account_id {
+                        client_handle
+                            .permission_allowed(&applications_update_claim(Some(&application_id)))
+                            .await?;
+                    }
+
+                    database::iam_update_application(&mut tx, &application).await?;
+                    actor
+                        .record(
+                            &mut tx,
+                            "ApplicationSave",
+                            Some(&before),
+                            Some(&application),
+                        )
+                        .await?;
+                    (application_id, None)
+                }
+                None => {
+                    client_handle
+                        .permission_allowed(&applications_create_claim())
+                        .await?;
+
+                    let application_id = random_token();
+                    application.id = Some(application_id.clone());
+                    if application.owner_account_id.is_none() {
+                        application.owner_account_id = actor.account_id;
+                    }
+                    let client_secret = if public {
+                        None
+                    } else {
+                        Some(oidc::generate_client_secret())
+                    };
+                    database::create_application(
+                        &mut tx,
+                        &application_id,
+                        &application.name,
+                        client_secret.as_ref().map(|(_, hash)| &hash[..]),
+                        application.owner_account_id,
+                    )
+                    .await?;
+                    actor
+                        .record(&mut tx, "ApplicationSave", None, Some(&application))
+                        .await?;
+                    (
+                        application_id,
+                        client_secret.map(|(client_secret, _)| client_secret),
+                    )
+                }
+            };
+            tx.commit().await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::ApplicationSaved {
+                    application_id,
+                    client_secret,
+                },
+            )))
+        }
+        IAMRequest::ApplicationDelete(application_id) => {
+            client_handle
+                .permission_allowed(&applications_delete_claim(Some(&application_id)))
+                .await?;
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let before = database::iam_get_application(&mut tx, &application_id).await?;
+            database::iam_delete_application(&mut tx, &application_id).await?;
+            actor
+                .record(&mut tx, "ApplicationDelete", before.as_ref(), None)
+                .await?;
+            tx.commit().await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::ApplicationDeleted(application_id),
+            )))
+        }
+        IAMRequest::ApplicationAudiencesSave {
+            application_id,
+            audiences,
+        } => {
+            application_access_allowed(
+                client_handle,
+                &application_id,
+                &applications_update_claim(Some(&application_id)),
+            )
+            .await?;
+
+            let mut audiences = audiences
+                .iter()
+                .map(|audience| audience.trim().to_owned())
+                .filter(|audience| !audience.is_empty())
+                .collect::<Vec<_>>();
+            audiences.sort();
+            audiences.dedup();
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let before = database::iam_get_application(&mut tx, &application_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Unknown application id {}", application_id))?;
+            database::iam_clear_application_audiences(&mut tx, &application_id).await?;
+            for audience in audiences.iter() {
+                if database::get_application_by_audience(&mut tx, audience)
+                    .await?
+                    .is_some()
+                {
+                    return Ok(RequestHandling::Respond(NcogResponse::Error {
+                        message: Some(format!(
+                            "the audience {} belongs to another application",
+                            audience
+                        )),
+                    }));
+                }
+                database::iam_add_application_audience(&mut tx, &application_id, audience).await?;
+            }
+            actor
+                .record(
+                    &mut tx,
+                    "ApplicationAudiencesSave",
+                    Some(
+                        &json!({ "application_id": application_id, "audiences": before.audiences }),
+                    ),
+                    Some(&json!({ "application_id": application_id, "audiences": audiences })),
+                )
+                .await?;
+            tx.commit().await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::ApplicationAudiencesSaved(application_id),
+            )))
+        }
+        IAMRequest::ApplicationRedirectUrisSave {
+            application_id,
+            redirect_uris,
+        } => {
+            application_access_allowed(
+                client_handle,
+                &application_id,
+                &applications_update_claim(Some(&application_id)),
+            )
+            .await?;
+
+            let mut redirect_uris = redirect_uris
+                .iter()
+                .map(|redirect_uri| redirect_uri.trim().to_owned())
+                .filter(|redirect_uri| !redirect_uri.is_empty())
+                .collect::<Vec<_>>();
+            redirect_uris.sort();
+            redirect_uris.dedup();
+            if let Err(err) = redirect_uris
+                .iter()
+                .try_for_each(|redirect_uri| oidc::validate_redirect_uri(redirect_uri))
+            {
+                return Ok(RequestHandling::Respond(NcogResponse::Error {
+                    message: Some(err.to_string()),
+                }));
+            }
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            let before = database::iam_get_application(&mut tx, &application_id)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Unknown application id {}", application_id))?;
+            database::iam_clear_application_redirect_uris(&mut tx, &application_id).await?;
+            for redirect_uri in redirect_uris.iter() {
+                database::add_application_redirect_uri(&mut tx, &application_id, redirect_uri)
+                    .await?;
+            }
+            actor
+                .record(
+                    &mut tx,
+                    "ApplicationRedirectUrisSave",
+                    Some(&json!({ "application_id": application_id, "redirect_uris": before.redirect_uris })),
+                    Some(&json!({ "application_id": application_id, "redirect_uris": redirect_uris })),
+                )
+                .await?;
+            tx.commit().await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::ApplicationRedirectUrisSaved(application_id),
+            )))
+        }
+        IAMRequest::ApplicationRotateSecret(application_id) => {
+            application_access_allowed(
+                client_handle,
+                &application_id,
+                &applications_update_claim(Some(&application_id)),
+            )
+            .await?;
+
+            let (client_secret, client_secret_hash) = oidc::generate_client_secret();
+
+            let actor = Actor::of(client_handle).await;
+            let mut tx = pg().begin().await?;
+            database::iam_set_application_client_secret_hash(
+                &mut tx,
+                &application_id,
+                &client_secret_hash,
+            )
+            .await?;
+            // Only the fact that the secret changed is recorded.
+            actor
+                .record(
+                    &mut tx,
+                    "ApplicationRotateSecret",
+                    None,
+                    Some(&json!({ "application_id": application_id })),
+                )
+                .await?;
+            tx.commit().await?;
+
+            Ok(RequestHandling::Respond(NcogResponse::IAM(
+                IAMResponse::ApplicationSecretRotated {
+                    application_id,
+                    client_secret,
+                },
+            )))
+        }
+    }
+}
+
+const AUDIT_LOG_PAGE_SIZE: i64 = 100;
+const LIST_PAGE_SIZE: i64 = 50;
+const MAX_LIST_PAGE_SIZE: i64 = 500;
+
+fn list_limit(limit: Option<i64>) -> i64 {
+    limit
+        .unwrap_or(LIST_PAGE_SIZE)
+        .max(1)
+        .min(MAX_LIST_PAGE_SIZE)
+}
+
+fn parse_list_cursor<I: FromStr>(cursor: &Option<String>) -> anyhow::Result<Option<ListCursor<I>>> {
+    match cursor {
+        Some(cursor) => match ListCursor::parse(cursor) {
+            Some(cursor) => Ok(Some(cursor)),
+            None => anyhow::bail!("invalid list cursor"),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The account and installation making a change, for the audit log.
+struct Actor {
+    account_id: Option<i64>,
+    installation_id: Option<Uuid>,
+}
+
+impl Actor {
+    async fn of(client_handle: &ConnectedClient<super::NcogServer>) -> Self {
+        let account_id = match client_handle.account().await {
+            Some(account) => Some(account.read().await.user.profile.id),
+            None => None,
+        };
+        let installation_id = client_handle
+            .installation()
+            .await
+            .map(|installation| installation.id);
+        Self {
+            account_id,
+            installation_id,
+        }
+    }
+
+    /// Whether the account making the change owns the application.
+    fn owns(&self, application: &ApplicationSummary) -> bool {
+        self.account_id.is_some() && application.owner_account_id == self.account_id
+    }
+
+    /// Records a change in the audit log. This should be executed within the
+    /// same transaction as the change itself.
+    async fn record<E, T>(
+        &self,
+        executor: E,
+        request_kind: &str,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> anyhow::Result<()>
+    where
+        E: Send + Executor<Database = Postgres>,
+        T: Serialize + Sync,
+    {
+        database::iam_insert_audit_log_entry(
+            executor,
+            self.account_id,
+            self.installation_id,
+            request_kind,
+            before.map(serde_json::to_string).transpose()?,
+            after.map(serde_json::to_string).transpose()?,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// Owners can read and update their own applications without `claim`.
+async fn application_access_allowed(
+    client_handle: &ConnectedClient<super::NcogServer>,
+    application_id: &str,
+    claim: &Claim,
+) -> anyhow::Result<()> {
+    if let Some(application) = database::get_application(&pg(), application_id).await? {
+        let actor = Actor::of(client_handle).await;
+        if actor.owns(&application.into()) {
+            return Ok(());
+        }
+    }
+    client_handle.permission_allowed(claim).await
+}
+
+async fn role_grant_allowed(
+    client_handle: &ConnectedClient<super::NcogServer>,
+    role_id: i64,
+) -> anyhow::Result<()> {
+    for statement in database::iam_effective_role_statements(&pg(), role_id).await? {
+        client_handle.grant_allowed(&statement).await?;
+    }
+    Ok(())
+}
+
+/// Taking `role_id` away from an account, or from a role that includes it,
+/// lifts its denies and those it inherits, which the editor must not be
+/// subject to.
+async fn role_removal_allowed(
+    client_handle: &ConnectedClient<super::NcogServer>,
+    role_id: i64,
+) -> anyhow::Result<()> {
+    for statement in database::iam_effective_role_statements(&pg(), role_id).await? {
+        if !statement.allow {
+            client_handle
+                .statement_change_allowed(Some(&statement), None)
+                .await?;
+        }
     }
+    Ok(())
 }
 
 async fn broadcast_role_changed(role_id: Option<i64>) -> Result<(), anyhow::Error> {
@@ -159,3 +972,25 @@ async fn broadcast_role_changed(role_id: Option<i64>) -> Result<(), anyhow::Erro
     }
     Ok(())
 }
+
+async fn broadcast_account_roles_changed(account_id: i64) -> Result<(), anyhow::Error> {
+    crate::pubsub::notify("account_roles_updated", account_id).await?;
+    Ok(())
+}
+
+async fn broadcast_account_suspension_changed(account_id: i64) -> Result<(), anyhow::Error> {
+    crate::pubsub::notify("account_suspension_updated", account_id).await?;
+    Ok(())
+}
+
+async fn broadcast_accounts_merged(
+    source_account_id: i64,
+    target_account_id: i64,
+) -> Result<(), anyhow::Error> {
+    crate::pubsub::notify(
+        "accounts_merged",
+        format!("{} {}", source_account_id, target_account_id),
+    )
+    .await?;
+    Ok(())
+}
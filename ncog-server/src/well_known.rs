@@ -0,0 +1,147 @@
+//! Standard discovery documents, which let off-the-shelf JWT and OpenID
+//! Connect libraries verify the tokens ncog issues.
+
+use crate::{api_server_base_url, oidc, signing_keys};
+use ncog_shared::jwk::JwtKeySet;
+use serde::Serialize;
+use std::convert::Infallible;
+use warp::{http::StatusCode, Filter, Rejection, Reply};
+
+/// New keys are published this long before they sign tokens and retired keys
+/// stay published for a day, so a cached key set always includes the keys
+/// tokens are signed with.
+pub const JWKS_MAX_AGE_SECONDS: u64 = 15 * 60;
+const CONFIGURATION_MAX_AGE_SECONDS: u64 = 24 * 60 * 60;
+
+/// The `iss` of tokens signed by ncog, which is where its discovery documents
+/// are served from.
+pub fn issuer() -> String {
+    api_url("/").trim_end_matches('/').to_owned()
+}
+
+fn api_url(path: &str) -> String {
+    api_server_base_url()
+        .path_and_query(path)
+        .build()
+        .unwrap()
+        .to_string()
+}
+
+/// The OpenID Provider Metadata from OpenID Connect Discovery 1.0.
+#[derive(Serialize)]
+struct OpenIdConfiguration {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+    jwks_uri: String,
+    scopes_supported: &'static [&'static str],
+    response_types_supported: Vec<&'static str>,
+    grant_types_supported: Vec<&'static str>,
+    code_challenge_methods_supported: Vec<&'static str>,
+    token_endpoint_auth_methods_supported: Vec<&'static str>,
+    subject_types_supported: Vec<&'static str>,
+    id_token_signing_alg_values_supported: Vec<&'static str>,
+    claims_supported: Vec<&'static str>,
+}
+
+impl OpenIdConfiguration {
+    fn new() -> Self {
+        Self {
+            issuer: issuer(),
+            authorization_endpoint: api_url("/authorize"),
+            token_endpoint: api_url("/token"),
+            userinfo_endpoint: api_url("/userinfo"),
+            jwks_uri: api_url("/.well-known/jwks.json"),
+            scopes_supported: oidc::SUPPORTED_SCOPES,
+            response_types_supported: vec!["code"],
+            grant_types_supported: vec!["authorization_code"],
+            code_challenge_methods_supported: vec!["S256"],
+            token_endpoint_auth_methods_supported: vec![
+                "client_secret_basic",
+                "client_secret_post",
+                "none",
+            ],
+            subject_types_supported: vec!["public"],
+            id_token_signing_alg_values_supported: vec!["RS256"],
+            claims_supported: vec![
+                "iss",
+                "sub",
+                "aud",
+                "exp",
+                "iat",
+                "auth_time",
+                "nonce",
+                "preferred_username",
+                "name",
+                "ncog_profile",
+                "ncog_permissions",
+            ],
+        }
+    }
+}
+
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Copy {
+    let jwks = warp::path!(".well-known" / "jwks.json").and_then(jwks);
+    let configuration =
+        warp::path!(".well-known" / "openid-configuration").and_then(openid_configuration);
+    warp::get().and(jwks.or(configuration))
+}
+
+async fn jwks() -> Result<warp::reply::Response, Infallible> {
+    match signing_keys::public_keys().await {
+        Ok(keys) => Ok(cached(
+            warp::reply::json(&JwtKeySet { keys }),
+            JWKS_MAX_AGE_SECONDS,
+        )),
+        Err(err) => {
+            error!("Error loading jwt signing keys: {:?}", err);
+            Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+async fn openid_configuration() -> Result<warp::reply::Response, Infallible> {
+    Ok(cached(
+        warp::reply::json(&OpenIdConfiguration::new()),
+        CONFIGURATION_MAX_AGE_SECONDS,
+    ))
+}
+
+fn cached(reply: impl Reply, max_age_seconds: u64) -> warp::reply::Response {
+    warp::reply::with_header(
+        reply,
+        "Cache-Control",
+        format!("public, max-age={}", max_age_seconds),
+    )
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn openid_configuration_is_cached() {
+        let response = warp::test::request()
+            .path("/.well-known/openid-configuration")
+            .reply(&routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["Cache-Control"],
+            format!("public, max-age={}", CONFIGURATION_MAX_AGE_SECONDS)
+        );
+
+        let configuration: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(configuration["issuer"], issuer());
+        assert_eq!(
+            configuration["jwks_uri"],
+            format!("{}/.well-known/jwks.json", issuer())
+        );
+        assert_eq!(
+            configuration["token_endpoint"],
+            format!("{}/token", issuer())
+        );
+    }
+}
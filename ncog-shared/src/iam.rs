@@ -1,39 +1,187 @@
-use crate::permissions::Claim;
+use crate::{
+    permissions::{Claim, PermissionExplanation, Statement},
+    AccountSuspension,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum IAMRequest {
-    UsersList,
+    UsersList(ListQuery<UserSort>),
     UsersGetProfile(i64),
-    RolesList,
+    UserAddRole {
+        account_id: i64,
+        role_id: i64,
+    },
+    UserRemoveRole {
+        account_id: i64,
+        role_id: i64,
+    },
+    UserSuspend {
+        account_id: i64,
+        reason: Option<String>,
+        until: Option<DateTime<Utc>>,
+    },
+    UserUnsuspend(i64),
+    /// Revokes every identity verification token issued to the account that
+    /// hasn't expired.
+    UserRevokeIdentityTokens(i64),
+    /// Moves everything owned by the source account to the target account,
+    /// then retires the source account.
+    UserMerge {
+        source_account_id: i64,
+        target_account_id: i64,
+    },
+    RolesList(ListQuery<RoleSort>),
     RoleGet(i64),
     RoleSave(RoleSummary),
     RoleDelete(i64),
+    RoleIncludedRolesSave {
+        role_id: i64,
+        included_role_ids: Vec<i64>,
+    },
     PermissionStatementGet(i64),
     PermissionStatementSave(PermissionStatement),
     PermissionStatemenetDelete(i64),
+    PermissionExplain(Claim),
+    PermissionsSimulate {
+        account_id: i64,
+        claims: Vec<Claim>,
+    },
+    AuditLogQuery(AuditLogQuery),
+    ApplicationsList(ListQuery<ApplicationSort>),
+    ApplicationGet(String),
+    /// Registers a new application when `id` is `None`. New applications are
+    /// issued a secret unless `public` is set.
+    ApplicationSave {
+        application: ApplicationSummary,
+        public: bool,
+    },
+    ApplicationDelete(String),
+    ApplicationAudiencesSave {
+        application_id: String,
+        audiences: Vec<String>,
+    },
+    ApplicationRedirectUrisSave {
+        application_id: String,
+        redirect_uris: Vec<String>,
+    },
+    /// Replaces the application's secret, which invalidates the previous one.
+    ApplicationRotateSecret(String),
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum IAMResponse {
-    UsersList(Vec<User>),
-    RolesList(Vec<RoleSummary>),
+    UsersList(ListPage<User>),
+    RolesList(ListPage<RoleSummary>),
     UserProfile(User),
+    UserRoleAdded {
+        account_id: i64,
+        role_id: i64,
+    },
+    UserRoleRemoved {
+        account_id: i64,
+        role_id: i64,
+    },
+    UserSuspended(i64),
+    UserUnsuspended(i64),
+    UserIdentityTokensRevoked(i64),
+    UsersMerged {
+        source_account_id: i64,
+        target_account_id: i64,
+    },
     Role(Role),
     RoleSaved(i64),
     RoleDeleted(i64),
+    RoleIncludedRolesSaved(i64),
     PermissionStatement(PermissionStatement),
     PermissionStatementSaved(i64),
     PermissionStatementDeleted(i64),
+    PermissionExplanation(PermissionExplanation),
+    PermissionsSimulated {
+        account_id: i64,
+        explanations: Vec<PermissionExplanation>,
+    },
+    AuditLog(Vec<AuditLogEntry>),
+    ApplicationsList(ListPage<ApplicationSummary>),
+    Application(Application),
+    /// Includes the application's secret when one was issued, which is the
+    /// only time it is available.
+    ApplicationSaved {
+        application_id: String,
+        client_secret: Option<String>,
+    },
+    ApplicationDeleted(String),
+    ApplicationAudiencesSaved(String),
+    ApplicationRedirectUrisSaved(String),
+    ApplicationSecretRotated {
+        application_id: String,
+        client_secret: String,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct User {
     pub id: Option<i64>,
-    pub screenname: Option<String>,
+    pub login: Option<String>,
+    pub display_name: Option<String>,
     pub created_at: DateTime<Utc>,
     pub roles: Vec<RoleSummary>,
+    /// The account's suspension, if one is in effect.
+    pub suspension: Option<AccountSuspension>,
+    /// Set once the account has been merged into another and retired.
+    pub merged_into_id: Option<i64>,
+}
+
+/// Options for requesting one page of a list, where `S` is the list's sort
+/// key.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ListQuery<S> {
+    /// Only include entries whose name contains this text, ignoring case.
+    pub search: Option<String>,
+    pub sort: S,
+    pub descending: bool,
+    /// The `next_cursor` of the previous page, or `None` for the first page.
+    pub cursor: Option<String>,
+    /// The maximum number of entries to return, capped by the server.
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ListPage<T> {
+    pub entities: Vec<T>,
+    /// The number of entries matching the search across all pages.
+    pub total_count: i64,
+    /// Pass as `ListQuery::cursor` to request the next page. `None` when this
+    /// is the last page.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserSort {
+    Id,
+    Login,
+    DisplayName,
+    CreatedAt,
+}
+
+impl Default for UserSort {
+    fn default() -> Self {
+        Self::Id
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoleSort {
+    Id,
+    Name,
+}
+
+impl Default for RoleSort {
+    fn default() -> Self {
+        Self::Name
+    }
 }
 
 pub fn users_list_claim() -> Claim {
@@ -52,6 +200,23 @@ pub fn users_create_claim() -> Claim {
     Claim::new("iam", Some("users"), None, "create")
 }
 
+pub fn users_read_permissions_claim(id: Option<i64>) -> Claim {
+    Claim::new("iam", Some("users"), id, "read-permissions")
+}
+
+pub fn users_suspend_claim(id: Option<i64>) -> Claim {
+    Claim::new("iam", Some("users"), id, "suspend")
+}
+
+pub fn users_revoke_tokens_claim(id: Option<i64>) -> Claim {
+    Claim::new("iam", Some("users"), id, "revoke-tokens")
+}
+
+/// Required for both the source and target accounts of a merge.
+pub fn users_merge_claim(id: Option<i64>) -> Claim {
+    Claim::new("iam", Some("users"), id, "merge")
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct RoleSummary {
     pub id: Option<i64>,
@@ -78,11 +243,19 @@ pub fn roles_delete_claim(id: Option<i64>) -> Claim {
     Claim::new("iam", Some("roles"), id, "delete")
 }
 
+/// Required to add or remove the role from an account, in addition to
+/// `users_update_claim` for the account.
+pub fn roles_assign_claim(id: Option<i64>) -> Claim {
+    Claim::new("iam", Some("roles"), id, "assign")
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct Role {
     pub id: Option<i64>,
     pub name: String,
     pub permission_statements: Vec<PermissionStatement>,
+    /// Roles whose permission statements are also granted by this role.
+    pub included_roles: Vec<RoleSummary>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
@@ -92,7 +265,7 @@ pub struct PermissionStatement {
 
     pub service: Option<String>,
     pub resource_type: Option<String>,
-    pub resource_id: Option<i64>,
+    pub resource_id: Option<String>,
 
     pub action: Option<String>,
 
@@ -100,3 +273,105 @@ pub struct PermissionStatement {
 
     pub comment: Option<String>,
 }
+
+impl From<&PermissionStatement> for Statement {
+    fn from(statement: &PermissionStatement) -> Self {
+        Self {
+            id: statement.id,
+            role_id: statement.role_id,
+            service: statement.service.clone(),
+            resource_type: statement.resource_type.clone(),
+            resource_id: statement.resource_id.clone(),
+            action: statement.action.clone(),
+            allow: statement.allow,
+        }
+    }
+}
+
+/// Filters for `IAMRequest::AuditLogQuery`. Entries are returned newest first.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct AuditLogQuery {
+    pub account_id: Option<i64>,
+    pub installation_id: Option<Uuid>,
+    pub request_kind: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Only return entries older than this entry, for paging.
+    pub before_id: Option<i64>,
+    /// The maximum number of entries to return, capped by the server.
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuditLogEntry {
+    pub id: i64,
+    /// The account that made the change.
+    pub account_id: Option<i64>,
+    pub installation_id: Option<Uuid>,
+    pub request_kind: String,
+    /// The affected data before the change, as JSON.
+    pub before: Option<String>,
+    /// The affected data after the change, as JSON.
+    pub after: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A third-party application registered to use ncog's identity tokens or
+/// OpenID Connect.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ApplicationSummary {
+    /// The application's OAuth `client_id`, which is assigned when the
+    /// application is registered.
+    pub id: Option<String>,
+    pub name: String,
+    /// The account that manages the application. Owners can edit their
+    /// applications without any IAM permissions.
+    pub owner_account_id: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Application {
+    pub summary: ApplicationSummary,
+    /// Public applications have no secret and rely on PKCE alone.
+    pub has_client_secret: bool,
+    /// The `audience` values identity verification tokens can be requested
+    /// for. Each audience belongs to a single application.
+    pub audiences: Vec<String>,
+    pub redirect_uris: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ApplicationSort {
+    Name,
+}
+
+impl Default for ApplicationSort {
+    fn default() -> Self {
+        Self::Name
+    }
+}
+
+pub fn applications_list_claim() -> Claim {
+    Claim::new("iam", Some("applications"), None, "list")
+}
+
+pub fn applications_read_claim(id: Option<&str>) -> Claim {
+    Claim::with_resource_id("iam", Some("applications"), id.map(str::to_owned), "read")
+}
+
+pub fn applications_update_claim(id: Option<&str>) -> Claim {
+    Claim::with_resource_id("iam", Some("applications"), id.map(str::to_owned), "update")
+}
+
+pub fn applications_create_claim() -> Claim {
+    Claim::new("iam", Some("applications"), None, "create")
+}
+
+pub fn applications_delete_claim(id: Option<&str>) -> Claim {
+    Claim::with_resource_id("iam", Some("applications"), id.map(str::to_owned), "delete")
+}
+
+pub fn audit_log_read_claim() -> Claim {
+    Claim::new("iam", Some("audit-log"), None, "read")
+}
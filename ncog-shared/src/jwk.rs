@@ -19,6 +19,12 @@ pub struct JwtKey {
     pub public_use: String,
 }
 
+/// A JSON Web Key Set, as served from a `jwks_uri`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JwtKeySet {
+    pub keys: Vec<JwtKey>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("only rsa keys are supported currently")]
@@ -9,26 +9,68 @@ pub mod permissions;
 pub use fluent_templates;
 pub use jsonwebtoken;
 use jwk::JwtKey;
-use permissions::{JsonPermissionSet, PermissionSet};
+use permissions::{Claim, JsonPermissionSet, PermissionSet};
 
 pub fn ncog_protocol_version() -> Version {
-    Version::parse("0.0.1").unwrap()
+    Version::parse("0.0.2").unwrap()
 }
 
 pub fn ncog_protocol_version_requirements() -> VersionReq {
-    VersionReq::parse("=0.0.1").unwrap()
+    VersionReq::parse("=0.0.2").unwrap()
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum NcogRequest {
-    AuthenticationUrl(OAuthProvider),
+    /// Requests the url to log in with the named OAuth provider, such as
+    /// `twitch`.
+    AuthenticationUrl(String),
+    /// Starts a device authorization (RFC 8628) for clients that can't open
+    /// a browser. The server responds with `DeviceAuthorization`, and the
+    /// installation is authenticated once the user enters the code.
+    RequestDeviceAuthorization,
+    /// Authenticates the installation waiting on `user_code` as the logged
+    /// in account.
+    AuthorizeDevice {
+        user_code: String,
+    },
+    /// Requests the url to link an identity from the named OAuth provider to
+    /// the logged in account.
+    LinkIdentityUrl(String),
+    ListLinkedIdentities,
+    /// Detaches an identity from the logged in account. The last remaining
+    /// identity can't be unlinked.
+    UnlinkIdentity {
+        provider: String,
+        external_id: String,
+    },
+    /// Loads an OpenID Connect authentication request awaiting the user's
+    /// consent.
+    GetAuthorizationRequest(String),
+    /// Approves or denies an authentication request as the logged in
+    /// account. The server responds with `RedirectTo` to send the browser
+    /// back to the application.
+    AnswerAuthorizationRequest {
+        id: String,
+        approved: bool,
+    },
     IAM(iam::IAMRequest),
     ListPublicJwtKeys,
-    RequestIdentityVerificationToken { nonce: [u8; 32], audience: String },
-}
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub enum OAuthProvider {
-    Twitch,
+    /// Lists the identity verification tokens that were revoked before they
+    /// expired.
+    ListRevokedIdentityTokens,
+    RequestIdentityVerificationToken {
+        nonce: [u8; 32],
+        audience: String,
+        /// The services whose permissions the relying party needs. The token
+        /// only contains permissions that apply to these services.
+        services: Vec<String>,
+    },
+    /// Checks whether an identity verification token is still active,
+    /// returning the current permissions of its account if so.
+    IntrospectIdentityToken(String),
+    /// Revokes every identity verification token issued to the logged in
+    /// account that hasn't expired.
+    RevokeIdentityTokens,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -50,8 +92,15 @@ pub struct IdentityVerificationClaims {
     pub expiration_time: u64,
     #[serde(rename = "iat")]
     pub issuance_time: u64,
+    /// Unique to each token, which lets relying parties check it against the
+    /// revocation list.
+    #[serde(rename = "jti")]
+    pub jwt_id: String,
     pub nonce: [u8; 32],
     pub ncog_profile: UserProfile,
+    /// The services the token was requested for.
+    pub ncog_services: Vec<String>,
+    /// The account's permissions, limited to `ncog_services`.
     pub ncog_permissions: JsonPermissionSet,
 }
 
@@ -93,14 +142,179 @@ pub struct AuthenticatedUser {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum NcogResponse {
     JwtPublicKeys(Vec<JwtKey>),
-    IdentityVerificationToken { token: String },
-    AuthenticateAtUrl { url: String },
+    RevokedIdentityTokens(Vec<RevokedIdentityToken>),
+    IdentityTokenIntrospection(IdentityTokenIntrospection),
+    IdentityTokensRevoked,
+    /// A signed `IdentityVerificationClaims` token, along with the name of
+    /// the registered application its audience belongs to so that the user
+    /// can be shown who they are identifying to.
+    IdentityVerificationToken {
+        token: String,
+        application_name: String,
+    },
+    AuthenticateAtUrl {
+        url: String,
+    },
+    DeviceAuthorization(DeviceAuthorization),
+    /// The device whose code was entered has been logged in.
+    DeviceAuthorized,
     Authenticated(AuthenticatedUser),
     Unauthenticated,
-    Error { message: Option<String> },
+    ConnectionRefused(ConnectionRefusal),
+    LoginFailed(LoginFailure),
+    LinkedIdentities(Vec<LinkedIdentity>),
+    AuthorizationRequest(AuthorizationRequest),
+    RedirectTo {
+        url: String,
+    },
+    Error {
+        message: Option<String>,
+    },
     IAM(iam::IAMResponse),
 }
 
+/// Why logging in with an OAuth provider failed.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoginFailure {
+    /// The user declined to grant access at the provider.
+    Denied,
+    /// The login was tampered with, expired, or was already completed.
+    InvalidState,
+    /// The provider reported an error or rejected the login.
+    Provider,
+    /// The identity being linked already belongs to another account.
+    IdentityInUse,
+    UnknownProvider,
+    ServerError,
+}
+
+impl LoginFailure {
+    /// A short identifier suitable for urls.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Denied => "denied",
+            Self::InvalidState => "invalid-state",
+            Self::Provider => "provider",
+            Self::IdentityInUse => "identity-in-use",
+            Self::UnknownProvider => "unknown-provider",
+            Self::ServerError => "server-error",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "denied" => Some(Self::Denied),
+            "invalid-state" => Some(Self::InvalidState),
+            "provider" => Some(Self::Provider),
+            "identity-in-use" => Some(Self::IdentityInUse),
+            "unknown-provider" => Some(Self::UnknownProvider),
+            "server-error" => Some(Self::ServerError),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an identity verification token can still be trusted, modeled
+/// after OAuth 2.0 Token Introspection (RFC 7662). Inactive tokens only
+/// report `active`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct IdentityTokenIntrospection {
+    pub active: bool,
+    #[serde(rename = "jti", default, skip_serializing_if = "Option::is_none")]
+    pub jwt_id: Option<String>,
+    #[serde(rename = "sub", default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(rename = "aud", default, skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    #[serde(rename = "exp", default, skip_serializing_if = "Option::is_none")]
+    pub expiration_time: Option<u64>,
+    /// The account's current profile, which may have changed since the token
+    /// was issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ncog_profile: Option<UserProfile>,
+    /// The account's current permissions, limited to the services the token
+    /// was requested for. These may have changed since the token was issued.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ncog_permissions: Option<JsonPermissionSet>,
+}
+
+impl IdentityTokenIntrospection {
+    pub fn inactive() -> Self {
+        Self::default()
+    }
+}
+
+/// An identity verification token that must no longer be trusted, even
+/// though it hasn't expired.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RevokedIdentityToken {
+    #[serde(rename = "jti")]
+    pub jwt_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// An identity from an OAuth provider that can be used to log into an
+/// account.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LinkedIdentity {
+    pub provider: String,
+    pub external_id: String,
+    pub login: Option<String>,
+    pub display_name: Option<String>,
+    pub linked_at: DateTime<Utc>,
+}
+
+/// An application asking to identify the user with OpenID Connect.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuthorizationRequest {
+    pub id: String,
+    pub application_name: String,
+    /// Where the user is sent after answering the request.
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+}
+
+/// A pending device authorization. The user logs in at `verification_uri`
+/// and enters `user_code` to authenticate the installation that requested it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeviceAuthorization {
+    pub user_code: String,
+    pub verification_uri: String,
+    /// `verification_uri` with the code filled in, suitable for a QR code.
+    pub verification_uri_complete: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Why the server refused to authenticate an account.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ConnectionRefusal {
+    Suspended(AccountSuspension),
+    /// The account isn't allowed the `connect_claim`.
+    NotPermitted,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AccountSuspension {
+    pub reason: Option<String>,
+    pub suspended_at: DateTime<Utc>,
+    /// When the suspension ends, or `None` if it lasts until lifted.
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl AccountSuspension {
+    pub fn is_active(&self) -> bool {
+        match self.until {
+            Some(until) => until > current_datetime(),
+            None => true,
+        }
+    }
+}
+
+/// Required for an account to be authenticated on a connection.
+pub fn connect_claim() -> Claim {
+    Claim::new("ncog", None, None, "connect")
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub struct UserProfile {
     pub id: i64,
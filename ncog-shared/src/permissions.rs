@@ -1,11 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Claim {
     service: String,
     resource_type: Option<String>,
-    resource_id: Option<i64>,
+    resource_id: Option<String>,
     action: String,
 }
 
@@ -15,6 +15,22 @@ impl Claim {
         resource_type: Option<S>,
         resource_id: Option<i64>,
         action: S,
+    ) -> Self {
+        Self::with_resource_id(
+            service,
+            resource_type,
+            resource_id.map(|id| id.to_string()),
+            action,
+        )
+    }
+
+    /// Creates a claim for a resource identified by a string, such as a UUID
+    /// or a slug.
+    pub fn with_resource_id<S: Into<String>>(
+        service: S,
+        resource_type: Option<S>,
+        resource_id: Option<String>,
+        action: S,
     ) -> Self {
         Self {
             service: service.into(),
@@ -23,13 +39,31 @@ impl Claim {
             action: action.into(),
         }
     }
+
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    pub fn resource_type(&self) -> Option<&str> {
+        self.resource_type.as_deref()
+    }
+
+    pub fn resource_id(&self) -> Option<&str> {
+        self.resource_id.as_deref()
+    }
+
+    pub fn action(&self) -> &str {
+        &self.action
+    }
 }
 
+#[derive(Debug)]
 pub struct Statement {
+    pub id: Option<i64>,
     pub role_id: Option<i64>,
     pub service: Option<String>,
     pub resource_type: Option<String>,
-    pub resource_id: Option<i64>,
+    pub resource_id: Option<String>,
 
     pub action: Option<String>,
 
@@ -49,14 +83,27 @@ impl Statement {
         allow: bool,
     ) -> Self {
         Self {
+            id: None,
             role_id,
             service: service.map(|s| s.into()),
             resource_type: resource_type.map(|s| s.into()),
-            resource_id,
+            resource_id: resource_id.map(|id| id.to_string()),
             action: action.map(|s| s.into()),
             allow,
         }
     }
+
+    #[cfg(test)]
+    fn with_id(mut self, id: i64) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    #[cfg(test)]
+    fn with_resource_id(mut self, resource_id: &str) -> Self {
+        self.resource_id = Some(resource_id.to_owned());
+        self
+    }
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -127,7 +174,7 @@ impl From<JsonResourcePermissions> for ResourceTypePermission {
                 if resource.is_empty() {
                     None
                 } else {
-                    Some(resource.parse().unwrap_or_default())
+                    Some(resource)
                 },
                 ResourcePermission::from(in_service_permission),
             );
@@ -150,7 +197,11 @@ impl From<JsonActionPermissions> for ResourcePermission {
                 } else {
                     Some(service)
                 },
-                in_service_permission,
+                ActionPermission {
+                    allow: in_service_permission,
+                    role_id: None,
+                    statement_id: None,
+                },
             );
         }
 
@@ -173,14 +224,13 @@ impl From<PermissionSet> for JsonPermissionSet {
                     resource_type_permission.resource_permissions.iter()
                 {
                     let mut action_permissions = HashMap::new();
-                    for (action, allowed) in resource_permission.action_permissions.iter() {
-                        action_permissions.insert(action.clone().unwrap_or_default(), *allowed);
+                    for (action, permission) in resource_permission.action_permissions.iter() {
+                        action_permissions
+                            .insert(action.clone().unwrap_or_default(), permission.allow);
                     }
 
-                    resource_permissions.insert(
-                        resource.map(|id| id.to_string()).unwrap_or_default(),
-                        action_permissions,
-                    );
+                    resource_permissions
+                        .insert(resource.clone().unwrap_or_default(), action_permissions);
                 }
 
                 resource_type_permissions.insert(
@@ -223,21 +273,293 @@ impl From<Vec<Statement>> for PermissionSet {
 
 impl PermissionSet {
     pub fn allowed(&self, claim: &Claim) -> bool {
-        if let Some(service_permission) = self.service_permissions.get(&Some(claim.service.clone()))
+        self.decide(claim)
+            .map(|decision| decision.allowed)
+            .unwrap_or(false)
+    }
+
+    /// Explains how `claim` is resolved against this set, including which
+    /// statement produced the decision.
+    pub fn explain(&self, claim: &Claim) -> PermissionExplanation {
+        PermissionExplanation {
+            claim: claim.clone(),
+            decision: self.decide(claim),
+        }
+    }
+
+    /// Returns true if every claim `statement` applies to is allowed by this
+    /// set, which is what's required to hand the statement to someone else.
+    /// Deny statements never grant anything, so they are always allowed.
+    ///
+    /// This is conservative: any deny in this set that could overlap the
+    /// statement refuses it, even if a more specific allow would win.
+    pub fn allows_statement(&self, statement: &Statement) -> bool {
+        if !statement.allow {
+            return true;
+        }
+
+        self.allows_scope(statement_scope(statement))
+    }
+
+    /// Returns true if every claim within `scope` is allowed.
+    fn allows_scope(&self, scope: [Option<&str>; 4]) -> bool {
+        for (path, permission) in self.action_permissions() {
+            if !permission.allow
+                && path
+                    .iter()
+                    .zip(scope.iter())
+                    .all(|(key, scope)| intersects(key, *scope))
+            {
+                return false;
+            }
+        }
+
+        self.service_permissions
+            .iter()
+            .any(|(service, service_permission)| {
+                covers(service, scope[0]) && service_permission.allows_scope(&scope[1..])
+            })
+    }
+
+    /// Returns true if replacing `previous` with `next` only grants claims this
+    /// set allows. `None` stands for a statement being created or deleted.
+    ///
+    /// Removing a deny grants everything it blocked, so unless `next` denies
+    /// at least as much within the same role, every claim `previous` denied
+    /// must be allowed by this set.
+    pub fn allows_statement_change(
+        &self,
+        previous: Option<&Statement>,
+        next: Option<&Statement>,
+    ) -> bool {
+        if let Some(next) = next {
+            if !self.allows_statement(next) {
+                return false;
+            }
+        }
+
+        match previous {
+            Some(previous) if !previous.allow => {
+                let still_denied = next
+                    .map(|next| {
+                        !next.allow
+                            && next.role_id == previous.role_id
+                            && statement_scope(next)
+                                .iter()
+                                .zip(statement_scope(previous).iter())
+                                .all(|(key, scope)| covers(&key.map(str::to_owned), *scope))
+                    })
+                    .unwrap_or(false);
+                still_denied || self.allows_scope(statement_scope(previous))
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns the part of this set that can affect claims for `services`,
+    /// which is everything a service needs to make its own decisions without
+    /// learning about grants in other services.
+    ///
+    /// Statements that apply to every service, and patterns matching one of
+    /// `services`, are kept so that each claim in those services is decided
+    /// the same way as by the full set.
+    pub fn limited_to_services<S: AsRef<str>>(&self, services: &[S]) -> Self {
+        let service_permissions = self
+            .service_permissions
+            .iter()
+            .filter(|(service, _)| {
+                service.is_none()
+                    || services
+                        .iter()
+                        .any(|scope| intersects(service, Some(scope.as_ref())))
+            })
+            .map(|(service, permission)| (service.clone(), permission.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut limited = Self {
+            service_permissions,
+            role_ids: HashSet::new(),
+        };
+        limited.role_ids = limited
+            .action_permissions()
+            .into_iter()
+            .filter_map(|(_, permission)| permission.role_id)
+            .filter(|role_id| self.role_ids.contains(role_id))
+            .collect();
+        limited
+    }
+
+    fn action_permissions(&self) -> Vec<([&Option<String>; 4], &ActionPermission)> {
+        let mut permissions = Vec::new();
+        for (service, service_permission) in self.service_permissions.iter() {
+            for (resource_type, resource_type_permission) in
+                service_permission.resource_type_permissions.iter()
+            {
+                for (resource_id, resource_permission) in
+                    resource_type_permission.resource_permissions.iter()
+                {
+                    for (action, permission) in resource_permission.action_permissions.iter() {
+                        permissions
+                            .push(([service, resource_type, resource_id, action], permission));
+                    }
+                }
+            }
+        }
+        permissions
+    }
+
+    fn decide(&self, claim: &Claim) -> Option<PermissionDecision> {
+        for (service, service_permission) in
+            matching_entries(&self.service_permissions, Some(&claim.service))
         {
-            if let Some(allowed) = service_permission.allowed(claim) {
-                return allowed;
+            if let Some(mut decision) = service_permission.decide(claim) {
+                decision.service = service.clone();
+                return Some(decision);
             }
         }
 
-        if let Some(generic_permission) = self.service_permissions.get(&None) {
-            if let Some(allowed) = generic_permission.allowed(claim) {
-                return allowed;
+        None
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PermissionExplanation {
+    pub claim: Claim,
+    /// The statement that decided the claim, or `None` if no statement
+    /// matched and the claim was denied by default.
+    pub decision: Option<PermissionDecision>,
+}
+
+impl PermissionExplanation {
+    pub fn allowed(&self) -> bool {
+        self.decision
+            .as_ref()
+            .map(|decision| decision.allowed)
+            .unwrap_or(false)
+    }
+}
+
+/// The path taken through a `PermissionSet` to reach a decision. Each level
+/// holds the statement value that matched, where `None` means the statement
+/// applied to any value at that level.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PermissionDecision {
+    pub allowed: bool,
+    pub service: Option<String>,
+    pub resource_type: Option<String>,
+    pub resource_id: Option<String>,
+    pub action: Option<String>,
+    pub role_id: Option<i64>,
+    pub statement_id: Option<i64>,
+}
+
+/// The values a statement applies to, from the service down to the action.
+fn statement_scope(statement: &Statement) -> [Option<&str>; 4] {
+    [
+        statement.service.as_deref(),
+        statement.resource_type.as_deref(),
+        statement.resource_id.as_deref(),
+        statement.action.as_deref(),
+    ]
+}
+
+/// Statement values containing a `*` are treated as glob patterns, where each
+/// `*` matches any sequence of characters (including none).
+pub fn is_pattern(value: &str) -> bool {
+    value.contains('*')
+}
+
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let prefix = parts.next().unwrap_or_default();
+    if !value.starts_with(prefix) {
+        return false;
+    }
+
+    let mut remaining = &value[prefix.len()..];
+    let parts = parts.collect::<Vec<_>>();
+    let (suffix, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return remaining.is_empty(),
+    };
+
+    for part in middle {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    remaining.ends_with(suffix)
+}
+
+fn pattern_specificity(pattern: &str) -> usize {
+    pattern.chars().filter(|c| *c != '*').count()
+}
+
+/// Returns true if a statement keyed by `key` applies to every value `scope`
+/// applies to. A `scope` of `None` includes values that aren't set at all.
+fn covers(key: &Option<String>, scope: Option<&str>) -> bool {
+    match (key, scope) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(key), Some(scope)) => {
+            key == scope || (is_pattern(key) && pattern_matches(key, scope))
+        }
+    }
+}
+
+/// Returns true if there may be a value that both `key` and `scope` apply to.
+/// Two patterns are always assumed to overlap.
+fn intersects(key: &Option<String>, scope: Option<&str>) -> bool {
+    match (key, scope) {
+        (None, _) | (_, None) => true,
+        (Some(key), Some(scope)) => match (is_pattern(key), is_pattern(scope)) {
+            (false, false) => key == scope,
+            (true, false) => pattern_matches(key, scope),
+            (false, true) => pattern_matches(scope, key),
+            (true, true) => true,
+        },
+    }
+}
+
+/// Returns the entries that apply to `value`, ordered from most to least
+/// specific: the exact match first, then matching patterns (the ones with the
+/// most literal characters first), and finally the `None` entry.
+fn matching_entries<'a, T>(
+    entries: &'a HashMap<Option<String>, T>,
+    value: Option<&str>,
+) -> Vec<(&'a Option<String>, &'a T)> {
+    let mut matches = Vec::new();
+
+    if let Some(value) = value {
+        if let Some((key, entry)) = entries.get_key_value(&Some(value.to_owned())) {
+            if !is_pattern(value) {
+                matches.push((key, entry));
             }
         }
 
-        false
+        let mut patterns = entries
+            .iter()
+            .filter(|(key, _)| match key {
+                Some(key) => is_pattern(key) && pattern_matches(key, value),
+                None => false,
+            })
+            .collect::<Vec<_>>();
+        patterns.sort_by(|(a, _), (b, _)| {
+            let a = a.as_deref().unwrap_or_default();
+            let b = b.as_deref().unwrap_or_default();
+            pattern_specificity(b)
+                .cmp(&pattern_specificity(a))
+                .then_with(|| a.cmp(b))
+        });
+        matches.extend(patterns);
+    }
+
+    if let Some(generic) = entries.get_key_value(&None) {
+        matches.push(generic);
     }
+
+    matches
 }
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -253,22 +575,52 @@ impl ServicePermission {
     }
 
     pub fn allowed(&self, claim: &Claim) -> Option<bool> {
-        if let Some(claimed_type) = &claim.resource_type {
-            if let Some(resource_type_permission) = self
-                .resource_type_permissions
-                .get(&Some(claimed_type.clone()))
-            {
-                return resource_type_permission.allowed(claim);
-            }
-        }
+        self.decide(claim).map(|decision| decision.allowed)
+    }
 
-        if let Some(generic_permission) = self.resource_type_permissions.get(&None) {
-            return generic_permission.allowed(claim);
+    /// An exact resource type match is the only entry consulted, even if it
+    /// doesn't decide the claim. Patterns and the `None` entry are tried in
+    /// order until one decides.
+    fn decide(&self, claim: &Claim) -> Option<PermissionDecision> {
+        for (resource_type, resource_type_permission) in matching_entries(
+            &self.resource_type_permissions,
+            claim.resource_type.as_deref(),
+        ) {
+            let exact = resource_type
+                .as_deref()
+                .map(|resource_type| !is_pattern(resource_type))
+                .unwrap_or(false);
+            if let Some(mut decision) = resource_type_permission.decide(claim) {
+                decision.resource_type = resource_type.clone();
+                return Some(decision);
+            } else if exact {
+                return None;
+            }
         }
 
         None
     }
 
+    /// Returns true if every claim within `scope` (resource type, resource
+    /// id, and action) is allowed, assuming no deny overlaps it. Exact
+    /// resource type entries hide the others, so each one overlapping the
+    /// scope must allow it too.
+    fn allows_scope(&self, scope: &[Option<&str>]) -> bool {
+        let (resource_type, scope) = (scope[0], &scope[1..]);
+        self.resource_type_permissions
+            .iter()
+            .any(|(key, permission)| covers(key, resource_type) && permission.allows_scope(scope))
+            && self
+                .resource_type_permissions
+                .iter()
+                .filter(|(key, _)| match key {
+                    Some(key) => !is_pattern(key),
+                    None => false,
+                })
+                .filter(|(key, _)| intersects(key, resource_type))
+                .all(|(_, permission)| permission.allows_scope(scope))
+    }
+
     fn apply(&mut self, statement: &Statement) {
         self.resource_type_permissions
             .entry(statement.resource_type.clone())
@@ -279,7 +631,7 @@ impl ServicePermission {
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ResourceTypePermission {
-    resource_permissions: HashMap<Option<i64>, ResourcePermission>,
+    resource_permissions: HashMap<Option<String>, ResourcePermission>,
 }
 
 impl ResourceTypePermission {
@@ -289,26 +641,37 @@ impl ResourceTypePermission {
         perm
     }
     pub fn allowed(&self, claim: &Claim) -> Option<bool> {
-        if let Some(claimed_id) = &claim.resource_id {
-            if let Some(resource_permission) = self.resource_permissions.get(&Some(*claimed_id)) {
-                if let Some(allowed) = resource_permission.allowed(claim) {
-                    return Some(allowed);
-                }
-            }
-        }
+        self.decide(claim).map(|decision| decision.allowed)
+    }
 
-        if let Some(generic_permission) = self.resource_permissions.get(&None) {
-            if let Some(allowed) = generic_permission.allowed(claim) {
-                return Some(allowed);
+    fn decide(&self, claim: &Claim) -> Option<PermissionDecision> {
+        for (resource_id, resource_permission) in
+            matching_entries(&self.resource_permissions, claim.resource_id.as_deref())
+        {
+            if let Some(mut decision) = resource_permission.decide(claim) {
+                decision.resource_id = resource_id.clone();
+                return Some(decision);
             }
         }
 
         None
     }
 
+    /// Returns true if every claim within `scope` (resource id and action) is
+    /// allowed, assuming no deny overlaps it.
+    fn allows_scope(&self, scope: &[Option<&str>]) -> bool {
+        self.resource_permissions.iter().any(|(key, permission)| {
+            covers(key, scope[0])
+                && permission
+                    .action_permissions
+                    .keys()
+                    .any(|action| covers(action, scope[1]))
+        })
+    }
+
     fn apply(&mut self, statement: &Statement) {
         self.resource_permissions
-            .entry(statement.resource_id)
+            .entry(statement.resource_id.clone())
             .and_modify(|rtp| rtp.apply(statement))
             .or_insert_with(|| ResourcePermission::from_statement(statement));
     }
@@ -316,7 +679,24 @@ impl ResourceTypePermission {
 
 #[derive(Default, Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ResourcePermission {
-    action_permissions: HashMap<Option<String>, bool>,
+    action_permissions: HashMap<Option<String>, ActionPermission>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ActionPermission {
+    allow: bool,
+    role_id: Option<i64>,
+    statement_id: Option<i64>,
+}
+
+impl ActionPermission {
+    fn from_statement(statement: &Statement) -> Self {
+        Self {
+            allow: statement.allow,
+            role_id: statement.role_id,
+            statement_id: statement.id,
+        }
+    }
 }
 
 impl ResourcePermission {
@@ -327,22 +707,28 @@ impl ResourcePermission {
     }
 
     pub fn allowed(&self, claim: &Claim) -> Option<bool> {
-        if let Some(action_permission) = self.action_permissions.get(&Some(claim.action.clone())) {
-            return Some(*action_permission);
-        }
-
-        if let Some(generic_permission) = self.action_permissions.get(&None) {
-            return Some(*generic_permission);
-        }
+        self.decide(claim).map(|decision| decision.allowed)
+    }
 
-        None
+    fn decide(&self, claim: &Claim) -> Option<PermissionDecision> {
+        matching_entries(&self.action_permissions, Some(&claim.action))
+            .first()
+            .map(|(action, permission)| PermissionDecision {
+                allowed: permission.allow,
+                service: None,
+                resource_type: None,
+                resource_id: None,
+                action: (*action).clone(),
+                role_id: permission.role_id,
+                statement_id: permission.statement_id,
+            })
     }
 
     fn apply(&mut self, statement: &Statement) {
-        self.action_permissions
-            .entry(statement.action.clone())
-            .and_modify(|allowed| *allowed = statement.allow)
-            .or_insert_with(|| statement.allow);
+        self.action_permissions.insert(
+            statement.action.clone(),
+            ActionPermission::from_statement(statement),
+        );
     }
 }
 
@@ -457,6 +843,33 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn exact_resource_type_does_not_fall_through() {
+        let set = PermissionSet::from(vec![
+            Statement::new(None, Some("svc"), Some("users"), None, Some("read"), true),
+            Statement::new(None, Some("svc"), None, None, Some("update"), true),
+        ]);
+        assert!(set.allowed(&Claim::new("svc", Some("users"), None, "read")));
+        assert!(!set.allowed(&Claim::new("svc", Some("users"), None, "update")));
+        assert!(set.allowed(&Claim::new("svc", Some("roles"), None, "update")));
+        assert!(!set.allows_statement(&Statement::new(
+            None,
+            Some("svc"),
+            None,
+            None,
+            Some("update"),
+            true
+        )));
+        assert!(set.allows_statement(&Statement::new(
+            None,
+            Some("svc"),
+            Some("roles"),
+            None,
+            Some("update"),
+            true
+        )));
+    }
+
     #[test]
     fn json_conversion() {
         let set = test_permissions();
@@ -464,4 +877,436 @@ mod tests {
         let back = PermissionSet::from(json);
         assert_eq!(set, back);
     }
+
+    fn pattern_permissions() -> PermissionSet {
+        PermissionSet::from(vec![
+            // Allow all read-like actions within iam
+            Statement::new(None, Some("iam"), None, None, Some("read*"), true),
+            // Except for reading secrets
+            Statement::new(None, Some("iam"), None, None, Some("read-secrets"), false),
+            // Allow everything for all game resource types
+            Statement::new(None, None, Some("game.*"), None, None, true),
+            // Deny deleting game saves, which is more specific than game.*
+            Statement::new(None, None, Some("game.save*"), None, Some("delete"), false),
+            // Deny everything else for game saves
+            Statement::new(None, None, Some("game.save"), None, None, false),
+            // Allow updates within any service ending with -service
+            Statement::new(None, Some("*-service"), None, None, Some("update"), true),
+            // Anything not covered by a pattern is denied for the pattern services
+            Statement::new(None, Some("*-service"), Some("locked"), None, None, false),
+        ])
+    }
+
+    #[test]
+    fn pattern_matching() {
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("*", "anything"));
+        assert!(pattern_matches("read*", "read"));
+        assert!(pattern_matches("read*", "read-profile"));
+        assert!(!pattern_matches("read*", "unread"));
+        assert!(pattern_matches("*.save", "game.save"));
+        assert!(!pattern_matches("*.save", "game.saves"));
+        assert!(pattern_matches("a*b*c", "abc"));
+        assert!(pattern_matches("a*b*c", "a-b-b-c"));
+        assert!(!pattern_matches("a*a", "a"));
+        assert!(!pattern_matches("exact", "exact-not"));
+    }
+
+    #[test]
+    fn pattern_allows_action() {
+        let set = pattern_permissions();
+        assert!(set.allowed(&Claim::new("iam", Some("users"), None, "read")));
+        assert!(set.allowed(&Claim::new("iam", Some("users"), Some(1), "read-profile")));
+        assert!(!set.allowed(&Claim::new("iam", Some("users"), None, "update")));
+    }
+
+    #[test]
+    fn exact_beats_pattern() {
+        let set = pattern_permissions();
+        assert!(!set.allowed(&Claim::new("iam", Some("users"), None, "read-secrets")));
+        assert!(!set.allowed(&Claim::new(
+            "nonexistant-service",
+            Some("game.save"),
+            None,
+            "read"
+        )));
+    }
+
+    #[test]
+    fn pattern_beats_generic() {
+        let set = pattern_permissions();
+        assert!(set.allowed(&Claim::new(
+            "nonexistant-service",
+            Some("game.level"),
+            None,
+            "read"
+        )));
+        assert!(!set.allowed(&Claim::new(
+            "nonexistant-service",
+            Some("level"),
+            None,
+            "read"
+        )));
+    }
+
+    #[test]
+    fn specific_pattern_beats_broad_pattern() {
+        let set = pattern_permissions();
+        assert!(!set.allowed(&Claim::new(
+            "nonexistant-service",
+            Some("game.saves"),
+            None,
+            "delete"
+        )));
+        assert!(set.allowed(&Claim::new(
+            "nonexistant-service",
+            Some("game.saves"),
+            None,
+            "read"
+        )));
+    }
+
+    #[test]
+    fn pattern_service() {
+        let set = pattern_permissions();
+        assert!(set.allowed(&Claim::new(
+            "chat-service",
+            Some("messages"),
+            None,
+            "update"
+        )));
+        assert!(!set.allowed(&Claim::new("chat-service", Some("locked"), None, "update")));
+        assert!(!set.allowed(&Claim::new("chat", Some("messages"), None, "update")));
+    }
+
+    #[test]
+    fn json_conversion_with_patterns() {
+        let set = pattern_permissions();
+        let json = JsonPermissionSet::from(set.clone());
+        let back = PermissionSet::from(json);
+        assert_eq!(set, back);
+        assert!(back.allowed(&Claim::new("iam", Some("users"), None, "read-profile")));
+        assert!(!back.allowed(&Claim::new("iam", Some("users"), None, "read-secrets")));
+    }
+
+    fn explained_permissions() -> PermissionSet {
+        PermissionSet::from(vec![
+            Statement::new(Some(1), Some("iam"), None, None, Some("read*"), true).with_id(10),
+            Statement::new(
+                Some(2),
+                Some("iam"),
+                Some("roles"),
+                Some(1),
+                Some("update"),
+                false,
+            )
+            .with_id(20),
+        ])
+    }
+
+    #[test]
+    fn explain_pattern_decision() {
+        let set = explained_permissions();
+        let explanation = set.explain(&Claim::new("iam", Some("users"), Some(3), "read"));
+        assert!(explanation.allowed());
+        assert_eq!(
+            explanation.decision,
+            Some(PermissionDecision {
+                allowed: true,
+                service: Some("iam".to_owned()),
+                resource_type: None,
+                resource_id: None,
+                action: Some("read*".to_owned()),
+                role_id: Some(1),
+                statement_id: Some(10),
+            })
+        );
+    }
+
+    #[test]
+    fn explain_specific_decision() {
+        let set = explained_permissions();
+        let explanation = set.explain(&Claim::new("iam", Some("roles"), Some(1), "update"));
+        assert!(!explanation.allowed());
+        assert_eq!(
+            explanation.decision,
+            Some(PermissionDecision {
+                allowed: false,
+                service: Some("iam".to_owned()),
+                resource_type: Some("roles".to_owned()),
+                resource_id: Some("1".to_owned()),
+                action: Some("update".to_owned()),
+                role_id: Some(2),
+                statement_id: Some(20),
+            })
+        );
+    }
+
+    #[test]
+    fn explain_default_deny() {
+        let set = explained_permissions();
+        let claim = Claim::new("iam", Some("roles"), Some(2), "update");
+        let explanation = set.explain(&claim);
+        assert!(!explanation.allowed());
+        assert_eq!(explanation.claim, claim);
+        assert_eq!(explanation.decision, None);
+    }
+
+    fn string_id_permissions() -> PermissionSet {
+        PermissionSet::from(vec![
+            Statement::new(
+                None,
+                Some("games"),
+                Some("lobbies"),
+                None,
+                Some("read"),
+                true,
+            ),
+            Statement::new(
+                None,
+                Some("games"),
+                Some("lobbies"),
+                None,
+                Some("join"),
+                false,
+            )
+            .with_resource_id("6f1c3a2e-5d0b-4a77-9a43-62b1c1f0e8d4"),
+            Statement::new(None, Some("games"), Some("lobbies"), None, None, true)
+                .with_resource_id("public-*"),
+            Statement::new(None, Some("games"), Some("lobbies"), Some(42), None, true),
+        ])
+    }
+
+    #[test]
+    fn string_resource_ids() {
+        let set = string_id_permissions();
+        assert!(!set.allowed(&Claim::with_resource_id(
+            "games",
+            Some("lobbies"),
+            Some("6f1c3a2e-5d0b-4a77-9a43-62b1c1f0e8d4".to_owned()),
+            "join"
+        )));
+        assert!(set.allowed(&Claim::with_resource_id(
+            "games",
+            Some("lobbies"),
+            Some("6f1c3a2e-5d0b-4a77-9a43-62b1c1f0e8d4".to_owned()),
+            "read"
+        )));
+        assert!(set.allowed(&Claim::with_resource_id(
+            "games",
+            Some("lobbies"),
+            Some("public-lobby".to_owned()),
+            "join"
+        )));
+        assert!(!set.allowed(&Claim::with_resource_id(
+            "games",
+            Some("lobbies"),
+            Some("private-lobby".to_owned()),
+            "join"
+        )));
+    }
+
+    #[test]
+    fn numeric_resource_ids_match_strings() {
+        let set = string_id_permissions();
+        assert!(set.allowed(&Claim::new("games", Some("lobbies"), Some(42), "join")));
+        assert!(set.allowed(&Claim::with_resource_id(
+            "games",
+            Some("lobbies"),
+            Some("42".to_owned()),
+            "join"
+        )));
+        assert!(!set.allowed(&Claim::new("games", Some("lobbies"), Some(43), "join")));
+    }
+
+    #[test]
+    fn json_conversion_with_string_ids() {
+        let set = string_id_permissions();
+        let json = JsonPermissionSet::from(set.clone());
+        let back = PermissionSet::from(json);
+        assert_eq!(set, back);
+        assert!(back.allowed(&Claim::with_resource_id(
+            "games",
+            Some("lobbies"),
+            Some("public-lobby".to_owned()),
+            "join"
+        )));
+    }
+
+    fn editor_permissions() -> PermissionSet {
+        PermissionSet::from(vec![
+            Statement::new(None, Some("iam"), Some("roles"), None, None, true),
+            Statement::new(
+                None,
+                Some("iam"),
+                Some("roles"),
+                Some(1),
+                Some("update"),
+                false,
+            ),
+            Statement::new(None, Some("games"), None, None, Some("read*"), true),
+        ])
+    }
+
+    #[test]
+    fn allows_covered_statements() {
+        let set = editor_permissions();
+        assert!(set.allows_statement(&Statement::new(
+            None,
+            Some("iam"),
+            Some("roles"),
+            Some(2),
+            Some("update"),
+            true
+        )));
+        assert!(set.allows_statement(&Statement::new(
+            None,
+            Some("games"),
+            Some("lobbies"),
+            None,
+            Some("read-members"),
+            true
+        )));
+        assert!(set.allows_statement(&Statement::new(
+            None,
+            Some("games"),
+            None,
+            None,
+            Some("read-*"),
+            true
+        )));
+    }
+
+    #[test]
+    fn refuses_uncovered_statements() {
+        let set = editor_permissions();
+        // Broader than anything the editor holds
+        assert!(!set.allows_statement(&Statement::new(None, Some("iam"), None, None, None, true)));
+        assert!(!set.allows_statement(&Statement::new(
+            None,
+            Some("games"),
+            None,
+            None,
+            Some("*"),
+            true
+        )));
+        // Explicitly denied to the editor
+        assert!(!set.allows_statement(&Statement::new(
+            None,
+            Some("iam"),
+            Some("roles"),
+            Some(1),
+            Some("update"),
+            true
+        )));
+        // Overlaps the deny for role 1
+        assert!(!set.allows_statement(&Statement::new(
+            None,
+            Some("iam"),
+            Some("roles"),
+            None,
+            Some("update"),
+            true
+        )));
+    }
+
+    #[test]
+    fn deny_statements_are_always_allowed() {
+        let set = editor_permissions();
+        assert!(set.allows_statement(&Statement::new(None, Some("iam"), None, None, None, false)));
+    }
+
+    #[test]
+    fn deleting_deny_requires_denied_claims() {
+        let set = editor_permissions();
+        let deny = Statement::new(Some(7), Some("iam"), None, None, Some("update"), false);
+        assert!(!set.allows_statement_change(Some(&deny), None));
+
+        let deny = Statement::new(
+            Some(7),
+            Some("games"),
+            None,
+            None,
+            Some("read-scores"),
+            false,
+        );
+        assert!(set.allows_statement_change(Some(&deny), None));
+
+        let allow = Statement::new(Some(7), Some("iam"), None, None, None, true);
+        assert!(set.allows_statement_change(Some(&allow), None));
+    }
+
+    #[test]
+    fn narrowing_deny_requires_denied_claims() {
+        let set = editor_permissions();
+        let deny = Statement::new(Some(7), Some("iam"), None, None, Some("update"), false);
+        let narrowed = Statement::new(
+            Some(7),
+            Some("iam"),
+            Some("roles"),
+            None,
+            Some("update"),
+            false,
+        );
+        assert!(!set.allows_statement_change(Some(&deny), Some(&narrowed)));
+
+        let allowed = Statement::new(Some(7), Some("iam"), None, None, Some("update"), true);
+        assert!(!set.allows_statement_change(Some(&deny), Some(&allowed)));
+
+        let moved = Statement::new(Some(8), Some("iam"), None, None, Some("update"), false);
+        assert!(!set.allows_statement_change(Some(&deny), Some(&moved)));
+
+        let widened = Statement::new(Some(7), Some("iam"), None, None, None, false);
+        assert!(set.allows_statement_change(Some(&deny), Some(&widened)));
+        assert!(set.allows_statement_change(Some(&deny), Some(&deny)));
+    }
+
+    fn multi_service_permissions() -> PermissionSet {
+        PermissionSet::from(vec![
+            Statement::new(Some(1), Some("iam"), Some("users"), None, None, true),
+            Statement::new(
+                Some(2),
+                Some("games"),
+                Some("lobbies"),
+                None,
+                Some("join"),
+                true,
+            ),
+            Statement::new(Some(3), Some("game*"), Some("locked"), None, None, false),
+            Statement::new(Some(4), None, None, None, Some("read"), true),
+        ])
+    }
+
+    #[test]
+    fn limited_to_services_drops_other_services() {
+        let set = multi_service_permissions().limited_to_services(&["games"]);
+        assert!(!set.allowed(&Claim::new("iam", Some("users"), None, "update")));
+        assert!(set.allowed(&Claim::new("iam", Some("users"), None, "read")));
+        assert_eq!(set.role_ids, vec![2, 3, 4].into_iter().collect());
+
+        let json = JsonPermissionSet::from(set);
+        assert!(!json.service_permissions.contains_key("iam"));
+    }
+
+    #[test]
+    fn limited_to_services_keeps_decisions() {
+        let full = multi_service_permissions();
+        let set = full.limited_to_services(&["games"]);
+        for claim in &[
+            Claim::new("games", Some("lobbies"), Some(1), "join"),
+            Claim::new("games", Some("lobbies"), Some(1), "leave"),
+            Claim::new("games", Some("locked"), None, "read"),
+            Claim::new("games", Some("scores"), None, "read"),
+        ] {
+            assert_eq!(set.explain(claim), full.explain(claim));
+        }
+    }
+
+    #[test]
+    fn limited_to_no_services() {
+        let set = multi_service_permissions().limited_to_services::<&str>(&[]);
+        assert!(!set.allowed(&Claim::new("games", Some("lobbies"), None, "join")));
+        assert!(set.allowed(&Claim::new("games", Some("lobbies"), None, "read")));
+        assert_eq!(set.role_ids, vec![4].into_iter().collect());
+    }
 }
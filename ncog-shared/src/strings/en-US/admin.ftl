@@ -16,6 +16,11 @@
     [other] Roles
 }
 
+-application = {$count -> 
+    *[one] Application
+    [other] Applications
+}
+
 -permission-statement = {$count -> 
     *[one] Permission Statement
     [other] Permission Statements
@@ -26,6 +31,8 @@
 -name = Name
 delete = Delete
 cancel = Cancel
+add = Add
+remove = Remove
 delete-irreversable = Deleting cannot be undone.
 
 add-user = {-add-item(type: {-user})}
@@ -33,6 +40,22 @@ edit-user = {-edit-item(type: {-user})}
 list-users = {-list-item(type: {-user(count: 0)})}
 save-user = {-save-item(type: {-user})}
 saved-user = {-saved-item(type: {-user})}
+saved-user-roles = The {-user}'s {-role(count:0)} were updated successfully.
+user-roles-help = {-role(count:0)} are added and removed as soon as they are picked.
+saved-user-suspension = The {-user}'s suspension was updated successfully.
+suspend-user = Suspend {-user}
+unsuspend-user = Lift Suspension
+user-not-suspended = This {-user} is not suspended.
+user-suspended-until = Suspended until {$until}
+user-suspended-indefinitely = Indefinitely
+user-identity-tokens = Identity Tokens
+user-identity-tokens-help = Game servers trust identity tokens until they expire, unless they are revoked. Revoking signs this {-user} out of every game server that checks for revoked tokens.
+revoke-user-identity-tokens = Revoke Identity Tokens
+saved-user-identity-tokens-revoked = The {-user}'s identity tokens were revoked.
+merge-user = Merge {-user}
+merge-user-help = Merging moves this {-user}'s {-role(count:0)}, linked identities, tokens and installations to the target {-user}, then retires this {-user}. Merging cannot be undone.
+saved-user-merge = The {-user} was merged successfully.
+user-merged-into = This {-user} was merged into {-user} {$id}.
 
 add-role = {-add-item(type: {-role})}
 edit-role = {-edit-item(type: {-role})}
@@ -40,6 +63,32 @@ save-role = {-save-item(type: {-role})}
 saved-role = {-saved-item(type: {-role})}
 list-roles = {-list-item(type: {-role(count: 0)})}
 delete-role = {-delete-item(type: {-role})}
+select-role = Select a {-role(count:1)}...
+save-included-roles = Save Included {-role(count:0)}
+saved-included-roles = Included {-role(count:0)} were saved successfully.
+included-roles-help = Accounts with this {-role(count:1)} are also granted the permission statements of every included {-role(count:1)}.
+
+add-application = {-add-item(type: {-application})}
+edit-application = {-edit-item(type: {-application})}
+list-applications = {-list-item(type: {-application(count: 0)})}
+save-application = {-save-item(type: {-application})}
+saved-application = {-saved-item(type: {-application})}
+delete-application = {-delete-item(type: {-application})}
+delete-application-warning = Deleting an {-application} immediately signs out everyone who logged in with it. {delete-irreversable}
+application-confidential = Confidential (server-side, has a client secret)
+application-public = Public (native or browser app, uses PKCE)
+application-audiences-help = Identity verification tokens can only be requested for audiences registered to an {-application}. Separate multiple audiences with spaces.
+save-application-audiences = Save Audiences
+saved-application-audiences = The {-application}'s audiences were saved successfully.
+application-redirect-uris-help = The authorization endpoint only redirects to these URIs. Separate multiple URIs with spaces.
+save-application-redirect-uris = Save Redirect URIs
+saved-application-redirect-uris = The {-application}'s redirect URIs were saved successfully.
+application-client-secret = Client Secret
+application-client-secret-help = Rotating the client secret invalidates the current one immediately.
+application-public-help = This {-application} is public and has no client secret. Rotating issues one, making it confidential.
+application-client-secret-notice = Copy this client secret now. It is not stored and cannot be shown again.
+rotate-application-secret = Rotate Client Secret
+rotated-application-secret = A new client secret was issued.
 
 add-permission-statement = {-add-item(type: {-permission-statement})}
 edit-permission-statement = {-edit-item(type: {-permission-statement})}
@@ -52,15 +101,29 @@ form-field-required = {$field} is required
 form-field-invalid-value = {$field} is not valid.
 
 user-fields-id = {-user(count:1)} Id
-user-fields-screenname = Screen Name
+user-fields-login = Login
+user-fields-display-name = Display Name
 user-fields-created-at = {-created-at}
 user-fields-assigned-roles = Assigned {-role(count:0)}
+user-fields-suspension = Suspension
+user-fields-suspension-reason = Reason
+user-fields-suspension-days = Length in Days
+user-fields-merge-target-id = Target {-user(count:1)} Id
 
 
 role-fields-id = {-role(count:1)} Id
 role-fields-name = {-name}
 role-fields-created-at = {-created-at}
 role-fields-permission-statements = {-permission-statements}
+role-fields-included-roles = Included {-role(count:0)}
+
+application-fields-id = Client Id
+application-fields-name = {-name}
+application-fields-owner-account-id = Owner {-user(count:1)} Id
+application-fields-client-type = Client Type
+application-fields-created-at = {-created-at}
+application-fields-audiences = Audiences
+application-fields-redirect-uris = Redirect URIs
 
 permission-statements-id = {-permission-statement(count:1)} Id
 permission-statements-service = Service
@@ -77,4 +140,30 @@ any-resource-type = Any Resource Type
 any-resource-id = All Resources
 any-action = Any Action
 action-denied = Denied
-action-allowed = Allowed
\ No newline at end of file
+action-allowed = Allowed
+
+permission-simulator = Permission Simulator
+permission-simulator-account-id = {-user(count:1)} Id
+permission-simulator-result = Result
+permission-simulator-explanation = Explanation
+permission-simulator-default-deny = No statement matched, so this is denied by default.
+permission-simulator-from-role = Statement {$statement} from {-role(count:1)} {$role}
+permission-simulator-from-global = Statement {$statement}, which applies to everyone
+add-claim = Add Claim
+simulate-claims = Simulate
+
+audit-log = Audit Log
+audit-log-fields-id = Entry Id
+audit-log-fields-created-at = {-created-at}
+audit-log-fields-account-id = {-user(count:1)} Id
+audit-log-fields-installation-id = Installation Id
+audit-log-fields-request-kind = Request
+audit-log-fields-before = Before
+audit-log-fields-after = After
+audit-log-any-account = Any {-user(count:1)}
+audit-log-any-request-kind = Any Request
+search = Search
+load-more = Load More
+previous-page = Previous
+next-page = Next
+list-total-count = {$count} total
\ No newline at end of file
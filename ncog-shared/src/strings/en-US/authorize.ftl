@@ -0,0 +1,9 @@
+authorize = Authorize Application
+authorize-intro = **{$application}** would like to identify you using your ncog.id account.
+authorize-requires-login = Log in to continue. You will be returned here afterwards.
+authorize-scopes-intro = Signed in as {$name}, allowing this will share:
+authorize-scope-openid = Your ncog.id account id
+authorize-scope-profile = Your username and display name
+authorize-redirect = You will be sent to `{$redirect_uri}` afterwards.
+authorize-allow = Allow
+authorize-deny = Deny
@@ -0,0 +1,6 @@
+device = Log In a Device
+device-intro = Enter the code shown by the game to log it into your ncog.id account. Only enter codes from devices you own.
+device-requires-login = Log in to continue, then return to this page to enter the code.
+device-user-code = Code
+device-authorize = Log In Device
+device-authorized = The device is now logged in. You can return to the game.
@@ -8,4 +8,13 @@ i-agree = I Agree
 
 log-in-with-twitch = Log in with ![Twitch](/providers/twitch.svg)
 
-logging-in = Logging in...
\ No newline at end of file
+logging-in = Logging in...
+
+login-failed = Unable to Log In
+login-failed-denied = Access was not granted to ncog.id, so you were not logged in.
+login-failed-invalid-state = This login has expired or was already completed. Logins must be finished within a few minutes of starting them.
+login-failed-provider = The provider reported an error while logging in. It may be experiencing problems, or the login took too long to complete.
+login-failed-unknown-provider = Logging in with this provider is not supported.
+login-failed-server-error = An unexpected error occurred while logging in.
+login-failed-identity-in-use = That identity is already linked to another account. Log in with it and unlink it there before linking it to this account.
+try-again = Try Again
\ No newline at end of file
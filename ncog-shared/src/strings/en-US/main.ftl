@@ -4,6 +4,7 @@ home = Home
 backoffice = Backoffice
 users = Users
 roles = Roles
+applications = Applications
 
 log-out = Log Out
 log-in = Sign up/Log in
@@ -12,4 +13,9 @@ no-permission = You do not have the required permissions to view this.
 
 not-set = <Not Set>
 
-edit = Edit
\ No newline at end of file
+edit = Edit
+
+account-suspended = Your account has been suspended.
+account-suspended-reason = Reason: {$reason}
+account-suspended-until = The suspension ends {$until}.
+account-not-permitted = Your account is not permitted to sign in.
\ No newline at end of file
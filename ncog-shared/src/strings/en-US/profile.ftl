@@ -0,0 +1,15 @@
+profile = Profile
+profile-requires-login = You must be logged in to view your profile.
+
+linked-identities = Linked Identities
+linked-identities-intro = You can log into your account using any of these identities. At least one identity must remain linked.
+identity-provider = Provider
+identity-login = Name
+identity-linked-at = Linked
+unlink = Unlink
+link-twitch = Link another ![Twitch](/providers/twitch.svg) account
+
+identity-tokens = Game Sessions
+identity-tokens-intro = Games you identified yourself to may trust you for a few minutes afterwards. If you think someone else is using your account, revoke these tokens and unlink any identity you don't recognize.
+revoke-identity-tokens = Revoke Identity Tokens
+identity-tokens-revoked = Your identity tokens were revoked.
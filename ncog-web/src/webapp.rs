@@ -5,7 +5,7 @@ use khonsuweb::static_page::StaticPage;
 use login::Login;
 use ncog_shared::{
     permissions::{Claim, PermissionSet},
-    NcogResponse, UserProfile,
+    ConnectionRefusal, NcogResponse, UserProfile,
 };
 use std::sync::Arc;
 use strings::localize;
@@ -22,17 +22,21 @@ macro_rules! require_permission {
 }
 
 mod api;
+mod authorize;
 mod backoffice;
+mod device;
 mod login;
+mod profile;
 use api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge};
 
 pub struct App {
     link: ComponentLink<Self>,
     show_nav: Option<bool>,
     api: ApiBridge,
-    _route_agent: RouteAgentBridge,
+    route_agent: RouteAgentBridge,
     connected: Option<bool>,
     user: Option<Arc<LoggedInUser>>,
+    connection_refusal: Option<ConnectionRefusal>,
     current_route: String,
 }
 
@@ -88,8 +92,18 @@ pub enum Message {
 pub enum AppRoute {
     #[to = "/_dev/styles"]
     StylesTest,
+    #[to = "/login/error/{reason}"]
+    LogInFailed(String),
     #[to = "/login!"]
     LogIn,
+    #[to = "/profile!"]
+    Profile,
+    #[to = "/authorize/{id}"]
+    Authorize(String),
+    #[to = "/device/{code}"]
+    DeviceWithCode(String),
+    #[to = "/device!"]
+    Device,
     #[to = "/backoffice/users"]
     #[rest]
     BackOfficeUserEdit(EditingId),
@@ -103,6 +117,16 @@ pub enum AppRoute {
     BackOfficeRoleEdit(EditingId),
     #[to = "/backoffice/roles!"]
     BackOfficeRolesList,
+    #[to = "/backoffice/applications/new!"]
+    BackOfficeApplicationNew,
+    #[to = "/backoffice/applications/{id}"]
+    BackOfficeApplicationEdit(String),
+    #[to = "/backoffice/applications!"]
+    BackOfficeApplicationsList,
+    #[to = "/backoffice/permissions/simulator!"]
+    BackOfficePermissionSimulator,
+    #[to = "/backoffice/audit-log!"]
+    BackOfficeAuditLog,
     #[to = "/backoffice!"]
     BackOfficeDashboard,
     #[to = "/!"]
@@ -122,6 +146,19 @@ impl AppRoute {
             }
             AppRoute::StylesTest => style_test(),
             AppRoute::LogIn => html! {<Login />},
+            AppRoute::LogInFailed(reason) => login::login_failed(reason),
+            AppRoute::Profile => {
+                html! { <profile::Profile set_title=set_title.clone() user=user.clone() />}
+            }
+            AppRoute::Authorize(request_id) => {
+                html! { <authorize::Authorize set_title=set_title.clone() user=user.clone() request_id=request_id.clone() />}
+            }
+            AppRoute::Device => {
+                html! { <device::Device set_title=set_title.clone() user=user.clone() />}
+            }
+            AppRoute::DeviceWithCode(user_code) => {
+                html! { <device::Device set_title=set_title.clone() user=user.clone() user_code=Some(user_code.clone()) />}
+            }
             AppRoute::BackOfficeDashboard => {
                 html! { <backoffice::Dashboard set_title=set_title.clone() user=user.clone() />}
             }
@@ -140,6 +177,21 @@ impl AppRoute {
             AppRoute::BackOfficeRolePermissionStatementEdit(role_id, id) => {
                 html! { <backoffice::edit_form::EditForm<backoffice::roles::permission_statements::edit::PermissionStatementForm> set_title=set_title.clone() user=user.clone() editing_id=id owning_id=role_id /> }
             }
+            AppRoute::BackOfficeApplicationsList => {
+                html! { <backoffice::applications::list::ApplicationsList set_title=set_title.clone() user=user.clone() />}
+            }
+            AppRoute::BackOfficeApplicationNew => {
+                html! { <backoffice::applications::edit::ApplicationEditor set_title=set_title.clone() user=user.clone() application_id=None /> }
+            }
+            AppRoute::BackOfficeApplicationEdit(id) => {
+                html! { <backoffice::applications::edit::ApplicationEditor set_title=set_title.clone() user=user.clone() application_id=Some(id.clone()) /> }
+            }
+            AppRoute::BackOfficePermissionSimulator => {
+                html! { <backoffice::permissions::simulator::PermissionSimulator set_title=set_title.clone() user=user.clone() />}
+            }
+            AppRoute::BackOfficeAuditLog => {
+                html! { <backoffice::audit::log::AuditLog set_title=set_title.clone() user=user.clone() />}
+            }
         }
     }
 }
@@ -158,8 +210,9 @@ impl Component for App {
             show_nav: None,
             api,
             user: None,
+            connection_refusal: None,
             connected: None,
-            _route_agent: route_agent,
+            route_agent,
             current_route: "/".to_owned(),
         }
     }
@@ -200,6 +253,18 @@ impl Component for App {
                             profile: user.profile,
                             permissions: user.permissions,
                         }));
+                        self.connection_refusal = None;
+                        true
+                    }
+                    NcogResponse::LoginFailed(failure) => {
+                        self.route_agent.send(RouteRequest::ChangeRoute(Route::from(
+                            AppRoute::LogInFailed(failure.code().to_owned()),
+                        )));
+                        false
+                    }
+                    NcogResponse::ConnectionRefused(refusal) => {
+                        self.user = None;
+                        self.connection_refusal = Some(refusal);
                         true
                     }
                     _ => false,
@@ -233,6 +298,7 @@ impl Component for App {
                             </p>
                         </div>
                     </div>
+                    { self.connection_refusal_notice() }
                     <Router<AppRoute>
                         render = Router::render(move |switch: AppRoute| {
                             switch.render(set_title.clone(), user.clone())
@@ -258,6 +324,44 @@ impl Component for App {
 }
 
 impl App {
+    fn connection_refusal_notice(&self) -> Html {
+        let message = match &self.connection_refusal {
+            Some(ConnectionRefusal::Suspended(suspension)) => {
+                let reason = match &suspension.reason {
+                    Some(reason) => html! {
+                        <p>{ localize!("account-suspended-reason", "reason" => reason.clone()) }</p>
+                    },
+                    None => Html::default(),
+                };
+                let until = match &suspension.until {
+                    Some(until) => html! {
+                        <p>{ localize!("account-suspended-until", "until" => until.to_rfc2822()) }</p>
+                    },
+                    None => Html::default(),
+                };
+                html! {
+                    <>
+                        <p>{ localize("account-suspended") }</p>
+                        { reason }
+                        { until }
+                    </>
+                }
+            }
+            Some(ConnectionRefusal::NotPermitted) => localize("account-not-permitted"),
+            None => return Html::default(),
+        };
+
+        html! {
+            <div class="columns is-centered">
+                <div class="column is-half">
+                    <div class="notification is-danger">
+                        { message }
+                    </div>
+                </div>
+            </div>
+        }
+    }
+
     fn navbar_class(&self) -> &'static str {
         if let Some(state) = self.show_nav {
             if state {
@@ -287,6 +391,9 @@ impl App {
                     <div class="navbar-dropdown is-boxed">
                         <RouterAnchor<AppRoute> route=AppRoute::BackOfficeUsersList classes=self.navbar_class_for("navbar-item", "/backoffice/users") >{ localize("users") }</RouterAnchor<AppRoute>>
                         <RouterAnchor<AppRoute> route=AppRoute::BackOfficeRolesList classes=self.navbar_class_for("navbar-item", "/backoffice/roles") >{ localize("roles") } </RouterAnchor<AppRoute>>
+                        <RouterAnchor<AppRoute> route=AppRoute::BackOfficeApplicationsList classes=self.navbar_class_for("navbar-item", "/backoffice/applications") >{ localize("applications") } </RouterAnchor<AppRoute>>
+                        <RouterAnchor<AppRoute> route=AppRoute::BackOfficePermissionSimulator classes=self.navbar_class_for("navbar-item", "/backoffice/permissions/simulator") >{ localize("permission-simulator") } </RouterAnchor<AppRoute>>
+                        <RouterAnchor<AppRoute> route=AppRoute::BackOfficeAuditLog classes=self.navbar_class_for("navbar-item", "/backoffice/audit-log") >{ localize("audit-log") } </RouterAnchor<AppRoute>>
                     </div>
                 </div>
             }
@@ -337,7 +444,7 @@ impl App {
         if let Some(user) = &self.user {
             html! {
                 <div class="navbar-item">
-                    { user.profile.display_name.clone().unwrap_or_default() }
+                    <RouterAnchor<AppRoute> route=AppRoute::Profile classes=self.navbar_class_for("navbar-item", "/profile") >{ user.profile.display_name.clone().unwrap_or_default() }</RouterAnchor<AppRoute>>
                     <button class="button" onclick=self.link.callback(|_| Message::LogOut)>
                         <strong>{ localize("log-out") }</strong>
                     </button>
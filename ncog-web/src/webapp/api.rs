@@ -42,7 +42,7 @@ impl ClientLogic for NcogApiAgent {
         _original_request_id: Option<u64>,
     ) -> anyhow::Result<()> {
         match response {
-            NcogResponse::AuthenticateAtUrl { url } => {
+            NcogResponse::AuthenticateAtUrl { url } | NcogResponse::RedirectTo { url } => {
                 let window = web_sys::window().expect("Need a window");
                 window
                     .location()
@@ -50,12 +50,17 @@ impl ClientLogic for NcogApiAgent {
                     .expect("Error setting location for redirect");
             }
             NcogResponse::Error { message } => error!("Error from server: {:?}", message),
+            NcogResponse::LoginFailed(failure) => error!("Login failed: {:?}", failure),
+            NcogResponse::ConnectionRefused(refusal) => {
+                error!("Connection refused: {:?}", refusal);
+                self.profile = None;
+            }
             NcogResponse::Authenticated(user) => {
                 self.profile = Some(user.profile);
 
                 let window = web_sys::window().expect("Need a window");
                 if let Ok(path) = window.location().pathname() {
-                    if path.contains("/login") {
+                    if path.contains("/login") && !path.contains("/login/error") {
                         let mut agent = RouteAgentBridge::new(Callback::noop());
                         agent.send(RouteRequest::ReplaceRoute(Route::new_no_state("/")));
                     }
@@ -0,0 +1,175 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    strings::{localize, localize_raw},
+    AppRoute, LoggedInUser,
+};
+use khonsuweb::prelude::*;
+use ncog_shared::{AuthorizationRequest, NcogRequest, NcogResponse};
+use std::sync::Arc;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+/// The consent page applications send users to when logging in with ncog.
+pub struct Authorize {
+    api: ApiBridge,
+    props: Props,
+    link: ComponentLink<Self>,
+    request: Option<AuthorizationRequest>,
+    error: Option<String>,
+    is_answering: bool,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub request_id: String,
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    Answer(bool),
+}
+
+impl Component for Authorize {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        Self {
+            api,
+            props,
+            link,
+            request: None,
+            error: None,
+            is_answering: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::AuthorizationRequest(request) => {
+                        if request.id == self.props.request_id {
+                            self.request = Some(request);
+                        }
+                        true
+                    }
+                    NcogResponse::Error { message } => {
+                        self.error = message;
+                        self.is_answering = false;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::Answer(approved) => {
+                self.api.send(AgentMessage::Request(
+                    NcogRequest::AnswerAuthorizationRequest {
+                        id: self.props.request_id.clone(),
+                        approved,
+                    },
+                ));
+                self.is_answering = true;
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let request_changed = self.props.request_id != props.request_id;
+        self.props = props;
+        if request_changed {
+            self.request = None;
+            self.error = None;
+            self.load_request();
+        }
+        true
+    }
+
+    fn view(&self) -> Html {
+        let content = if let Some(error) = &self.error {
+            html! {
+                <p class="notification is-danger is-light">{ error }</p>
+            }
+        } else if let Some(request) = &self.request {
+            self.render_request(request)
+        } else {
+            html! {
+                <progress class="progress is-primary" max="100"/>
+            }
+        };
+
+        html! {
+            <div class="columns is-centered">
+                <div class="column is-half">
+                    <Title>{ localize_raw("authorize") }</Title>
+                    { content }
+                </div>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, first_render: bool) {
+        if first_render {
+            self.load_request();
+        }
+        self.props.set_title.emit(localize_raw("authorize"));
+    }
+}
+
+impl Authorize {
+    fn load_request(&mut self) {
+        self.api
+            .send(AgentMessage::Request(NcogRequest::GetAuthorizationRequest(
+                self.props.request_id.clone(),
+            )));
+    }
+
+    fn render_request(&self, request: &AuthorizationRequest) -> Html {
+        let intro =
+            localize_html!("authorize-intro", "application" => request.application_name.clone());
+        let user = match &self.props.user {
+            Some(user) => user,
+            None => {
+                // The server returns to this page once the login completes.
+                return html! {
+                    <>
+                        <p>{ intro }</p>
+                        <p>{ localize("authorize-requires-login") }</p>
+                        <RouterButton<AppRoute> route=AppRoute::LogIn classes="button is-primary">
+                            <strong>{ localize_raw("log-in") }</strong>
+                        </RouterButton<AppRoute>>
+                    </>
+                };
+            }
+        };
+
+        html! {
+            <>
+                <p>{ intro }</p>
+                <p>{ localize!("authorize-scopes-intro", "name" => user.profile.display_name.clone().unwrap_or_default()) }</p>
+                <ul>
+                    { request.scopes.iter().map(|scope| html! { <li>{ localize_raw(&format!("authorize-scope-{}", scope)) }</li> }).collect::<Html>() }
+                </ul>
+                <p>{ localize_html!("authorize-redirect", "redirect_uri" => request.redirect_uri.clone()) }</p>
+                <div class="buttons">
+                    <Button
+                        label=localize_raw("authorize-allow")
+                        css_class="is-primary"
+                        disabled=self.is_answering
+                        action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Answer(true)})
+                    />
+                    <Button
+                        label=localize_raw("authorize-deny")
+                        disabled=self.is_answering
+                        action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Answer(false)})
+                    />
+                </div>
+            </>
+        }
+    }
+}
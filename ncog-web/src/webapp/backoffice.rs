@@ -5,8 +5,11 @@ use std::sync::Arc;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+pub mod applications;
+pub mod audit;
 pub mod edit_form;
 pub mod entity_list;
+pub mod permissions;
 pub mod roles;
 pub mod users;
 
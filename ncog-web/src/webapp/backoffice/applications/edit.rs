@@ -0,0 +1,398 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    backoffice::applications::fields::ApplicationFields,
+    has_permission,
+    strings::Namable,
+    AppRoute, LoggedInUser,
+};
+use khonsuweb::{prelude::*, validations::prelude::*};
+use ncog_shared::{
+    iam::{
+        applications_create_claim, applications_update_claim, Application, ApplicationSummary,
+        IAMRequest, IAMResponse,
+    },
+    NcogRequest, NcogResponse,
+};
+use std::{sync::Arc, time::Duration};
+use yew::prelude::*;
+use yew_router::{
+    agent::{RouteAgentBridge, RouteRequest},
+    route::Route,
+};
+
+/// Registers new applications and edits existing ones. Unlike the other
+/// backoffice forms, applications are identified by their `client_id`.
+pub struct ApplicationEditor {
+    api: ApiBridge,
+    props: Props,
+    link: ComponentLink<Self>,
+    application: Option<Application>,
+    name: FormStorage<Option<String>>,
+    owner_account_id: FormStorage<Option<i64>>,
+    public: FormStorage<bool>,
+    audiences: FormStorage<Option<String>>,
+    redirect_uris: FormStorage<Option<String>>,
+    /// A newly issued secret, which can only be shown once.
+    client_secret: Option<String>,
+    flash_message: Option<flash::Message>,
+    is_saving: bool,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+    /// `None` when registering a new application.
+    pub application_id: Option<String>,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    ValueChanged,
+    Save,
+    SaveAudiences,
+    SaveRedirectUris,
+    RotateSecret,
+}
+
+impl Component for ApplicationEditor {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        Self {
+            api,
+            props,
+            link,
+            application: None,
+            name: Default::default(),
+            owner_account_id: Default::default(),
+            public: Default::default(),
+            audiences: Default::default(),
+            redirect_uris: Default::default(),
+            client_secret: None,
+            flash_message: None,
+            is_saving: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::IAM(iam_response) => match iam_response {
+                        IAMResponse::Application(application) => {
+                            if application.summary.id == self.props.application_id {
+                                self.name.update(Some(application.summary.name.clone()));
+                                self.owner_account_id
+                                    .update(application.summary.owner_account_id);
+                                self.audiences.update(Some(application.audiences.join(" ")));
+                                self.redirect_uris
+                                    .update(Some(application.redirect_uris.join(" ")));
+                                self.application = Some(application);
+                            }
+                            true
+                        }
+                        IAMResponse::ApplicationSaved {
+                            application_id,
+                            client_secret,
+                        } => {
+                            self.client_secret = client_secret;
+                            self.saved("saved-application");
+                            let mut agent = RouteAgentBridge::<()>::new(Callback::noop());
+                            agent.send(RouteRequest::ReplaceRoute(Route::from(
+                                AppRoute::BackOfficeApplicationEdit(application_id),
+                            )));
+                            true
+                        }
+                        IAMResponse::ApplicationAudiencesSaved(_) => {
+                            self.saved("saved-application-audiences");
+                            true
+                        }
+                        IAMResponse::ApplicationRedirectUrisSaved(_) => {
+                            self.saved("saved-application-redirect-uris");
+                            true
+                        }
+                        IAMResponse::ApplicationSecretRotated { client_secret, .. } => {
+                            self.client_secret = Some(client_secret);
+                            self.saved("rotated-application-secret");
+                            true
+                        }
+                        _ => false,
+                    },
+                    NcogResponse::Error { message } => {
+                        if let Some(message) = message {
+                            self.flash_message = Some(flash::Message::new(
+                                flash::Kind::Danger,
+                                message,
+                                Duration::from_secs(3),
+                            ));
+                        }
+                        self.is_saving = false;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::ValueChanged => true,
+            Message::Save => {
+                let application = ApplicationSummary {
+                    id: self.props.application_id.clone(),
+                    name: self.name.value().unwrap_or(None).unwrap_or_default(),
+                    owner_account_id: self.owner_account_id.value().unwrap_or(None),
+                };
+                self.send(IAMRequest::ApplicationSave {
+                    application,
+                    public: self.public.unchecked_value(),
+                })
+            }
+            Message::SaveAudiences => {
+                if let Some(application_id) = self.props.application_id.clone() {
+                    self.send(IAMRequest::ApplicationAudiencesSave {
+                        application_id,
+                        audiences: split_list(&self.audiences),
+                    })
+                } else {
+                    false
+                }
+            }
+            Message::SaveRedirectUris => {
+                if let Some(application_id) = self.props.application_id.clone() {
+                    self.send(IAMRequest::ApplicationRedirectUrisSave {
+                        application_id,
+                        redirect_uris: split_list(&self.redirect_uris),
+                    })
+                } else {
+                    false
+                }
+            }
+            Message::RotateSecret => {
+                if let Some(application_id) = self.props.application_id.clone() {
+                    self.send(IAMRequest::ApplicationRotateSecret(application_id))
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let application_changed = self.props.application_id != props.application_id;
+        self.props = props;
+        if application_changed {
+            self.application = None;
+            self.initialize();
+        }
+        true
+    }
+
+    fn view(&self) -> Html {
+        let can_update = match &self.props.application_id {
+            Some(application_id) => {
+                // Owners can edit their applications, which the server
+                // checks, so only non-owners are limited by their permissions.
+                let is_owner = match (&self.application, &self.props.user) {
+                    (Some(application), Some(user)) => {
+                        application.summary.owner_account_id == Some(user.profile.id)
+                    }
+                    _ => false,
+                };
+                is_owner
+                    || has_permission(
+                        &self.props.user,
+                        applications_update_claim(Some(application_id)),
+                    )
+            }
+            None => {
+                require_permission!(&self.props.user, applications_create_claim());
+                true
+            }
+        };
+        let readonly = self.is_saving || !can_update;
+        let errors = ModelValidator::default()
+            .with_field(ApplicationFields::Name, self.name.is_present())
+            .validate();
+        let can_save = !readonly && errors.is_none();
+
+        let existing_fields = match &self.application {
+            Some(application) => html! {
+                <>
+                    <Field<ApplicationFields> field=ApplicationFields::Id errors=None>
+                        <Label text=ApplicationFields::Id.localized_name() />
+                        <input class="input" type="text" value=application.summary.id.clone().unwrap_or_default() readonly=true />
+                    </Field<ApplicationFields>>
+                    <Field<ApplicationFields> field=ApplicationFields::CreatedAt errors=None>
+                        <Label text=ApplicationFields::CreatedAt.localized_name() />
+                        <input class="input" type="text" value=application.created_at.to_rfc2822() readonly=true />
+                    </Field<ApplicationFields>>
+                </>
+            },
+            None => Html::default(),
+        };
+
+        let client_type = if self.props.application_id.is_some() {
+            Html::default()
+        } else {
+            html! {
+                <Field<ApplicationFields> field=ApplicationFields::ClientType errors=None>
+                    <Label text=ApplicationFields::ClientType.localized_name() />
+                    <Radio<ApplicationFields, bool>
+                        field=ApplicationFields::ClientType
+                        errors=None
+                        storage=self.public.clone()
+                        disabled=readonly
+                        on_value_changed=self.link.callback(|_| Message::ValueChanged)
+                        options=vec![(localize!("application-confidential"), false), (localize!("application-public"), true)]
+                        />
+                </Field<ApplicationFields>>
+            }
+        };
+
+        let client_secret = match &self.client_secret {
+            Some(client_secret) => html! {
+                <div class="notification is-warning">
+                    <p>{ localize!("application-client-secret-notice") }</p>
+                    <pre>{ client_secret }</pre>
+                </div>
+            },
+            None => Html::default(),
+        };
+
+        let settings = match &self.application {
+            Some(application) => html! {
+                <>
+                    <section class="section content">
+                        <Title size=3>{ ApplicationFields::Audiences.localized_name() }</Title>
+                        <p>{ localize!("application-audiences-help") }</p>
+                        <Field<ApplicationFields> field=ApplicationFields::Audiences errors=None>
+                            <TextInput<ApplicationFields, String> field=ApplicationFields::Audiences storage=self.audiences.clone() readonly=readonly on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<ApplicationFields>>
+                        <Button
+                            label=localize!("save-application-audiences")
+                            disabled=readonly
+                            css_class="is-primary"
+                            action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::SaveAudiences})
+                            processing=self.is_saving
+                        />
+                    </section>
+                    <section class="section content">
+                        <Title size=3>{ ApplicationFields::RedirectUris.localized_name() }</Title>
+                        <p>{ localize!("application-redirect-uris-help") }</p>
+                        <Field<ApplicationFields> field=ApplicationFields::RedirectUris errors=None>
+                            <TextInput<ApplicationFields, String> field=ApplicationFields::RedirectUris storage=self.redirect_uris.clone() readonly=readonly on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<ApplicationFields>>
+                        <Button
+                            label=localize!("save-application-redirect-uris")
+                            disabled=readonly
+                            css_class="is-primary"
+                            action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::SaveRedirectUris})
+                            processing=self.is_saving
+                        />
+                    </section>
+                    <section class="section content">
+                        <Title size=3>{ localize!("application-client-secret") }</Title>
+                        <p>{ localize!(if application.has_client_secret { "application-client-secret-help" } else { "application-public-help" }) }</p>
+                        <Button
+                            label=localize!("rotate-application-secret")
+                            disabled=readonly
+                            css_class="is-danger"
+                            action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::RotateSecret})
+                            processing=self.is_saving
+                        />
+                    </section>
+                </>
+            },
+            None => Html::default(),
+        };
+
+        let title = if self.props.application_id.is_some() {
+            "edit-application"
+        } else {
+            "add-application"
+        };
+
+        html! {
+            <div>
+                <section class="section content">
+                    <Title>{ localize!(title) }</Title>
+                    <form>
+                        <flash::Flash message=self.flash_message.clone() />
+                        { client_secret }
+                        { existing_fields }
+                        <Field<ApplicationFields> field=ApplicationFields::Name errors=errors.clone()>
+                            <Label text=ApplicationFields::Name.localized_name() />
+                            <TextInput<ApplicationFields, String> field=ApplicationFields::Name storage=self.name.clone() readonly=readonly on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=errors.clone() />
+                        </Field<ApplicationFields>>
+                        <Field<ApplicationFields> field=ApplicationFields::OwnerAccountId errors=None>
+                            <Label text=ApplicationFields::OwnerAccountId.localized_name() />
+                            <TextInput<ApplicationFields, i64> field=ApplicationFields::OwnerAccountId storage=self.owner_account_id.clone() readonly=readonly on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<ApplicationFields>>
+                        { client_type }
+                        <Button
+                            label=localize!("save-application")
+                            disabled=!can_save
+                            css_class="is-primary"
+                            action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Save})
+                            processing=self.is_saving
+                        />
+                    </form>
+                </section>
+
+                { settings }
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, first_render: bool) {
+        if first_render {
+            self.initialize();
+        }
+        self.props
+            .set_title
+            .emit(localize!(if self.props.application_id.is_some() {
+                "edit-application"
+            } else {
+                "add-application"
+            }));
+    }
+}
+
+impl ApplicationEditor {
+    fn initialize(&mut self) {
+        if let Some(application_id) = &self.props.application_id {
+            self.api.send(AgentMessage::Request(NcogRequest::IAM(
+                IAMRequest::ApplicationGet(application_id.clone()),
+            )));
+        }
+    }
+
+    fn send(&mut self, request: IAMRequest) -> ShouldRender {
+        self.api
+            .send(AgentMessage::Request(NcogRequest::IAM(request)));
+        self.is_saving = true;
+        true
+    }
+
+    fn saved(&mut self, save_message: &'static str) {
+        self.flash_message = Some(flash::Message::new(
+            flash::Kind::Success,
+            localize!(save_message),
+            Duration::from_secs(3),
+        ));
+        self.is_saving = false;
+        self.initialize();
+    }
+}
+
+/// Splits a field holding a space-separated list, which is how OAuth lists
+/// scopes and redirect URIs.
+fn split_list(storage: &FormStorage<Option<String>>) -> Vec<String> {
+    storage
+        .unchecked_value()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect()
+}
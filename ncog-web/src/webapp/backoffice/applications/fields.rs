@@ -0,0 +1,26 @@
+use crate::webapp::strings::Namable;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum ApplicationFields {
+    Id,
+    Name,
+    OwnerAccountId,
+    ClientType,
+    CreatedAt,
+    Audiences,
+    RedirectUris,
+}
+
+impl Namable for ApplicationFields {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Id => "application-fields-id",
+            Self::Name => "application-fields-name",
+            Self::OwnerAccountId => "application-fields-owner-account-id",
+            Self::ClientType => "application-fields-client-type",
+            Self::CreatedAt => "application-fields-created-at",
+            Self::Audiences => "application-fields-audiences",
+            Self::RedirectUris => "application-fields-redirect-uris",
+        }
+    }
+}
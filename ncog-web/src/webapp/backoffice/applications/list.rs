@@ -0,0 +1,240 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    backoffice::{
+        applications::fields::ApplicationFields,
+        entity_list::{body::EntityRenderer, pager::Pager, sortable_head, EntityList},
+        render_heading_with_add_button,
+    },
+    has_permission,
+    strings::Namable,
+    AppRoute, LoggedInUser,
+};
+use khonsuweb::prelude::*;
+use ncog_shared::{
+    iam::{
+        applications_create_claim, applications_delete_claim, applications_list_claim,
+        ApplicationSort, ApplicationSummary, IAMRequest, IAMResponse,
+    },
+    NcogRequest, NcogResponse,
+};
+use std::{
+    rc::Rc,
+    sync::{Arc, RwLock},
+};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+const PAGE_SIZE: i64 = 50;
+
+pub struct ApplicationsList {
+    api: ApiBridge,
+    props: Props,
+    applications: Option<Rc<RwLock<Vec<ApplicationSummary>>>>,
+    link: ComponentLink<Self>,
+    pending_delete_id: Option<String>,
+    pager: Pager<ApplicationSort>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    RequestDelete(String),
+    Delete,
+    CancelDelete,
+    Search(String),
+    Sort(ApplicationSort),
+    PreviousPage,
+    NextPage,
+}
+
+impl Component for ApplicationsList {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        Self {
+            props,
+            api,
+            link,
+            applications: None,
+            pending_delete_id: None,
+            pager: Pager::new(ApplicationSort::Name, PAGE_SIZE),
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::IAM(iam_response) => match iam_response {
+                        IAMResponse::ApplicationsList(page) => {
+                            self.pager.received(&page);
+                            self.applications = Some(Rc::new(RwLock::new(page.entities)));
+                            true
+                        }
+                        IAMResponse::ApplicationDeleted(id) => {
+                            if let Some(applications) = &self.applications {
+                                let mut applications =
+                                    applications.write().expect("Error locking applications");
+                                applications
+                                    .retain(|application| application.id.as_ref() != Some(&id));
+                                true
+                            } else {
+                                false
+                            }
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::RequestDelete(id) => {
+                self.pending_delete_id = Some(id);
+                true
+            }
+            Message::CancelDelete => {
+                self.pending_delete_id = None;
+                true
+            }
+            Message::Delete => {
+                if let Some(id) = self.pending_delete_id.take() {
+                    self.api.send(AgentMessage::Request(NcogRequest::IAM(
+                        IAMRequest::ApplicationDelete(id),
+                    )));
+                }
+                true
+            }
+            Message::Search(search) => {
+                self.pager.search(search);
+                self.initialize();
+                true
+            }
+            Message::Sort(sort) => {
+                self.pager.sort_by(sort);
+                self.initialize();
+                true
+            }
+            Message::PreviousPage => {
+                if self.pager.previous_page() {
+                    self.initialize();
+                }
+                true
+            }
+            Message::NextPage => {
+                if self.pager.next_page() {
+                    self.initialize();
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        self.initialize();
+        true
+    }
+
+    fn view(&self) -> Html {
+        require_permission!(&self.props.user, applications_list_claim());
+        let can_create = has_permission(&self.props.user, applications_create_claim());
+        let link = self.link.clone();
+        let user = self.props.user.clone();
+        html!(
+            <div>
+                <Alert
+                    visible=self.pending_delete_id.is_some()
+                    title=localize!("delete-application")
+                    message=localize!("delete-application-warning")
+                    primary_button_action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Delete})
+                    primary_button_label=localize!("delete")
+                    cancel_button_action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::CancelDelete})
+                    cancel_button_label=localize!("cancel")
+                    />
+                <section class="section content">
+                    { render_heading_with_add_button("list-applications", AppRoute::BackOfficeApplicationNew, "add-application", !can_create) }
+
+                    <EntityList<ApplicationSummary>
+                        header=self.head()
+                        row=EntityRenderer::new(move |application: &ApplicationSummary| {
+                            let id = application.id.clone().unwrap();
+                            let can_delete = has_permission(&user, applications_delete_claim(Some(&id)));
+                            let delete_id = id.clone();
+                            html! {
+                                <tr>
+                                    <td>{ &id }</td>
+                                    <td>{ &application.name }</td>
+                                    <td>{ application.owner_account_id.map(|id| id.to_string()).unwrap_or_else(|| localize!("not-set")) }</td>
+                                    <td>
+                                        <div class="field is-grouped">
+                                            <p class="control">
+                                                <RouterButton<AppRoute> route=AppRoute::BackOfficeApplicationEdit(id) classes="button is-primary" >
+                                                    <strong>{ localize!("edit") }</strong>
+                                                </RouterButton<AppRoute>>
+                                            </p>
+                                            <p class="control">
+                                                <Button
+                                                    label=localize!("delete")
+                                                    css_class="is-danger"
+                                                    disabled=!can_delete
+                                                    action=link.callback(move |_| Message::RequestDelete(delete_id.clone()))
+                                                />
+                                            </p>
+                                        </div>
+                                    </td>
+                                </tr>
+                            }
+                        })
+                        entities=self.applications.clone()
+                        on_search=self.link.callback(Message::Search)
+                        paging=self.pager.paging(
+                            self.link.callback(|_| Message::PreviousPage),
+                            self.link.callback(|_| Message::NextPage),
+                        )
+                    />
+                </section>
+            </div>
+        )
+    }
+
+    fn rendered(&mut self, first_render: bool) {
+        if first_render {
+            self.initialize();
+        }
+
+        self.props.set_title.emit(localize!("list-applications"));
+    }
+}
+
+impl ApplicationsList {
+    fn initialize(&mut self) {
+        self.api.send(AgentMessage::Request(NcogRequest::IAM(
+            IAMRequest::ApplicationsList(self.pager.query.clone()),
+        )))
+    }
+
+    fn head(&self) -> Html {
+        let sortable = |field: ApplicationFields, sort: ApplicationSort| {
+            sortable_head(
+                field.localized_name(),
+                self.pager.sort_indicator(sort),
+                self.link.callback(move |_| Message::Sort(sort)),
+            )
+        };
+        html! {
+            <tr>
+                <td>{ ApplicationFields::Id.localized_name() }</td>
+                { sortable(ApplicationFields::Name, ApplicationSort::Name) }
+                <td>{ ApplicationFields::OwnerAccountId.localized_name() }</td>
+                <td></td>
+            </tr>
+        }
+    }
+}
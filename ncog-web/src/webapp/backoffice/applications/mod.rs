@@ -0,0 +1,3 @@
+pub mod edit;
+pub mod fields;
+pub mod list;
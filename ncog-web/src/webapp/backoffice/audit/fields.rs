@@ -0,0 +1,27 @@
+use crate::webapp::strings::Namable;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum AuditLogFields {
+    Id,
+    CreatedAt,
+    AccountId,
+    InstallationId,
+    RequestKind,
+    Before,
+    After,
+}
+
+impl Namable for AuditLogFields {
+    fn name(&self) -> &'static str {
+        use AuditLogFields::*;
+        match self {
+            Id => "audit-log-fields-id",
+            CreatedAt => "audit-log-fields-created-at",
+            AccountId => "audit-log-fields-account-id",
+            InstallationId => "audit-log-fields-installation-id",
+            RequestKind => "audit-log-fields-request-kind",
+            Before => "audit-log-fields-before",
+            After => "audit-log-fields-after",
+        }
+    }
+}
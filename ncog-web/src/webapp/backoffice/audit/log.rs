@@ -0,0 +1,231 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    backoffice::audit::fields::AuditLogFields,
+    strings::Namable,
+    AppRoute, EditingId, LoggedInUser,
+};
+use khonsuweb::prelude::*;
+use ncog_shared::{
+    iam::{audit_log_read_claim, AuditLogEntry, AuditLogQuery, IAMRequest, IAMResponse},
+    NcogRequest, NcogResponse,
+};
+use std::{sync::Arc, time::Duration};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+const PAGE_SIZE: i64 = 50;
+
+pub struct AuditLog {
+    api: ApiBridge,
+    props: Props,
+    link: ComponentLink<Self>,
+    account_id: FormStorage<Option<i64>>,
+    request_kind: FormStorage<Option<String>>,
+    query: AuditLogQuery,
+    entries: Option<Vec<AuditLogEntry>>,
+    has_more: bool,
+    is_loading: bool,
+    flash_message: Option<flash::Message>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    ValueChanged,
+    Search,
+    LoadMore,
+}
+
+impl Component for AuditLog {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        Self {
+            api,
+            props,
+            link,
+            account_id: Default::default(),
+            request_kind: Default::default(),
+            query: AuditLogQuery::default(),
+            entries: None,
+            has_more: false,
+            is_loading: false,
+            flash_message: None,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::IAM(IAMResponse::AuditLog(entries)) => {
+                        self.has_more = entries.len() as i64 >= PAGE_SIZE;
+                        match &mut self.entries {
+                            Some(existing) if self.query.before_id.is_some() => {
+                                existing.extend(entries)
+                            }
+                            _ => self.entries = Some(entries),
+                        }
+                        self.is_loading = false;
+                        true
+                    }
+                    NcogResponse::Error { message } => {
+                        if let Some(message) = message {
+                            self.flash_message = Some(flash::Message::new(
+                                flash::Kind::Danger,
+                                message,
+                                Duration::from_secs(3),
+                            ));
+                        }
+                        self.is_loading = false;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::ValueChanged => true,
+            Message::Search => {
+                self.query = AuditLogQuery {
+                    account_id: self.account_id.value().unwrap_or_default(),
+                    request_kind: self.request_kind.value().unwrap_or_default(),
+                    limit: Some(PAGE_SIZE),
+                    ..Default::default()
+                };
+                self.entries = None;
+                self.send_query();
+                true
+            }
+            Message::LoadMore => {
+                self.query.before_id = self
+                    .entries
+                    .as_ref()
+                    .and_then(|entries| entries.last())
+                    .map(|entry| entry.id);
+                self.send_query();
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        require_permission!(&self.props.user, audit_log_read_claim());
+
+        let entries = match &self.entries {
+            Some(entries) => html! {
+                <table class="table is-hoverable is-striped">
+                    <tr>
+                        <td>{ AuditLogFields::Id.localized_name() }</td>
+                        <td>{ AuditLogFields::CreatedAt.localized_name() }</td>
+                        <td>{ AuditLogFields::AccountId.localized_name() }</td>
+                        <td>{ AuditLogFields::InstallationId.localized_name() }</td>
+                        <td>{ AuditLogFields::RequestKind.localized_name() }</td>
+                        <td>{ AuditLogFields::Before.localized_name() }</td>
+                        <td>{ AuditLogFields::After.localized_name() }</td>
+                    </tr>
+                    <tbody>
+                        { entries.iter().map(render_entry).collect::<Html>() }
+                    </tbody>
+                </table>
+            },
+            None if self.is_loading => html! {
+                <progress class="progress is-primary" max="100"/>
+            },
+            None => Html::default(),
+        };
+
+        html! {
+            <div>
+                <section class="section content">
+                    <Title>{localize!("audit-log")}</Title>
+                    <form>
+                        <flash::Flash message=self.flash_message.clone() />
+                        <Field<AuditLogFields> field=AuditLogFields::AccountId errors=None>
+                            <Label text=AuditLogFields::AccountId.localized_name() />
+                            <TextInput<AuditLogFields, i64> field=AuditLogFields::AccountId storage=self.account_id.clone() readonly=self.is_loading on_value_changed=self.link.callback(|_| Message::ValueChanged) placeholder=localize!("audit-log-any-account") errors=None />
+                        </Field<AuditLogFields>>
+                        <Field<AuditLogFields> field=AuditLogFields::RequestKind errors=None>
+                            <Label text=AuditLogFields::RequestKind.localized_name() />
+                            <TextInput<AuditLogFields, String> field=AuditLogFields::RequestKind storage=self.request_kind.clone() readonly=self.is_loading on_value_changed=self.link.callback(|_| Message::ValueChanged) placeholder=localize!("audit-log-any-request-kind") errors=None />
+                        </Field<AuditLogFields>>
+                        <Button
+                            label=localize!("search")
+                            css_class="is-primary"
+                            disabled=self.is_loading
+                            action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Search})
+                            processing=self.is_loading
+                        />
+                    </form>
+                </section>
+
+                <section class="section content">
+                    { entries }
+                    <Button
+                        label=localize!("load-more")
+                        css_class="is-info"
+                        disabled=self.is_loading || !self.has_more
+                        action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::LoadMore})
+                    />
+                </section>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, first_render: bool) {
+        if first_render {
+            self.link.send_message(Message::Search);
+        }
+        self.props.set_title.emit(localize!("audit-log"));
+    }
+}
+
+impl AuditLog {
+    fn send_query(&mut self) {
+        self.api.send(AgentMessage::Request(NcogRequest::IAM(
+            IAMRequest::AuditLogQuery(self.query.clone()),
+        )));
+        self.is_loading = true;
+    }
+}
+
+fn render_entry(entry: &AuditLogEntry) -> Html {
+    let account = match entry.account_id {
+        Some(account_id) => html! {
+            <RouterAnchor<AppRoute> route=AppRoute::BackOfficeUserEdit(EditingId::Id(account_id))>
+                { account_id }
+            </RouterAnchor<AppRoute>>
+        },
+        None => html! { <span>{ localize!("not-set") }</span> },
+    };
+
+    html! {
+        <tr>
+            <td>{ entry.id }</td>
+            <td>{ entry.created_at }</td>
+            <td>{ account }</td>
+            <td>{ entry.installation_id.map(|id| id.to_string()).unwrap_or_else(|| localize!("not-set")) }</td>
+            <td>{ &entry.request_kind }</td>
+            <td>{ render_json(entry.before.as_ref()) }</td>
+            <td>{ render_json(entry.after.as_ref()) }</td>
+        </tr>
+    }
+}
+
+fn render_json(json: Option<&String>) -> Html {
+    match json {
+        Some(json) => html! { <pre>{ json }</pre> },
+        None => Html::default(),
+    }
+}
@@ -26,6 +26,11 @@ pub trait Form: Default {
 
     fn title(is_new: bool) -> &'static str;
     fn load_request(&self, props: &Props) -> Option<NcogRequest>;
+    /// Requests for data the form needs besides the entity being edited, such
+    /// as the options for a picker.
+    fn load_related_requests(&self, _props: &Props) -> Vec<NcogRequest> {
+        Vec::new()
+    }
     fn save(&mut self, props: &Props, api: &mut ApiBridge);
     fn handle_webserver_response(&mut self, response: NcogResponse) -> Handled;
     fn render(
@@ -207,5 +212,9 @@ where
         if let Some(request) = self.form.load_request(&self.props) {
             self.api.send(AgentMessage::Request(request))
         }
+
+        for request in self.form.load_related_requests(&self.props) {
+            self.api.send(AgentMessage::Request(request))
+        }
     }
 }
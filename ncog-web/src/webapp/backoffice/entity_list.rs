@@ -2,12 +2,15 @@ use std::{rc::Rc, sync::RwLock};
 use yew::prelude::*;
 
 pub mod body;
+pub mod pager;
 
 pub struct EntityList<T>
 where
     T: Clone + 'static,
 {
     props: Props<T>,
+    link: ComponentLink<Self>,
+    search: String,
 }
 
 #[derive(Clone, Properties)]
@@ -18,20 +21,69 @@ where
     pub header: Html,
     pub entities: Option<Rc<RwLock<Vec<T>>>>,
     pub row: body::EntityRenderer<T>,
+    /// Shows a search field, which emits the search text when submitted.
+    #[prop_or_default]
+    pub on_search: Option<Callback<String>>,
+    /// Shows the total count and previous/next page buttons.
+    #[prop_or_default]
+    pub paging: Option<Paging>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Paging {
+    pub total_count: Option<i64>,
+    pub has_previous: bool,
+    pub has_next: bool,
+    pub on_previous: Callback<()>,
+    pub on_next: Callback<()>,
+}
+
+pub enum Message {
+    SearchChanged(String),
+    Search,
+    PreviousPage,
+    NextPage,
 }
 
 impl<T> Component for EntityList<T>
 where
     T: Clone + 'static,
 {
-    type Message = ();
+    type Message = Message;
     type Properties = Props<T>;
-    fn create(props: Self::Properties, _link: ComponentLink<Self>) -> Self {
-        Self { props }
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Self {
+            props,
+            link,
+            search: String::new(),
+        }
     }
 
-    fn update(&mut self, _msg: Self::Message) -> ShouldRender {
-        false
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::SearchChanged(search) => {
+                self.search = search;
+                false
+            }
+            Message::Search => {
+                if let Some(on_search) = &self.props.on_search {
+                    on_search.emit(self.search.clone());
+                }
+                false
+            }
+            Message::PreviousPage => {
+                if let Some(paging) = &self.props.paging {
+                    paging.on_previous.emit(());
+                }
+                false
+            }
+            Message::NextPage => {
+                if let Some(paging) = &self.props.paging {
+                    paging.on_next.emit(());
+                }
+                false
+            }
+        }
     }
 
     fn change(&mut self, props: Self::Properties) -> ShouldRender {
@@ -40,7 +92,7 @@ where
     }
 
     fn view(&self) -> Html {
-        match &self.props.entities {
+        let table = match &self.props.entities {
             Some(entities) => html!(
 
                 <table class="table is-hoverable is-striped">
@@ -53,6 +105,79 @@ where
                     <progress class="progress is-primary" max="100"/>
                 }
             }
+        };
+
+        html! {
+            <div>
+                { self.render_search() }
+                { table }
+                { self.render_paging() }
+            </div>
         }
     }
 }
+
+impl<T> EntityList<T>
+where
+    T: Clone + 'static,
+{
+    fn render_search(&self) -> Html {
+        if self.props.on_search.is_none() {
+            return Html::default();
+        }
+
+        html! {
+            <form class="field has-addons" onsubmit=self.link.callback(|e: FocusEvent| {e.prevent_default(); Message::Search})>
+                <div class="control is-expanded">
+                    <input
+                        class="input"
+                        type="search"
+                        placeholder=localize!("search")
+                        value=&self.search
+                        oninput=self.link.callback(|e: InputData| Message::SearchChanged(e.value))
+                        />
+                </div>
+                <div class="control">
+                    <button class="button is-info" type="submit">{ localize!("search") }</button>
+                </div>
+            </form>
+        }
+    }
+
+    fn render_paging(&self) -> Html {
+        let paging = match &self.props.paging {
+            Some(paging) => paging,
+            None => return Html::default(),
+        };
+
+        let total_count = match paging.total_count {
+            Some(count) => localize!("list-total-count", "count" => count),
+            None => String::default(),
+        };
+
+        html! {
+            <nav class="level">
+                <div class="level-left">
+                    <div class="level-item">{ total_count }</div>
+                </div>
+                <div class="level-right">
+                    <button class="level-item button" disabled=!paging.has_previous onclick=self.link.callback(|_| Message::PreviousPage)>
+                        { localize!("previous-page") }
+                    </button>
+                    <button class="level-item button" disabled=!paging.has_next onclick=self.link.callback(|_| Message::NextPage)>
+                        { localize!("next-page") }
+                    </button>
+                </div>
+            </nav>
+        }
+    }
+}
+
+/// Renders a column heading that sorts the list by the column when clicked.
+pub fn sortable_head(label: String, indicator: &'static str, on_sort: Callback<()>) -> Html {
+    html! {
+        <td>
+            <a onclick=on_sort.reform(|_| ())>{ label }{ indicator }</a>
+        </td>
+    }
+}
@@ -0,0 +1,104 @@
+use super::Paging;
+use ncog_shared::iam::{ListPage, ListQuery};
+use yew::Callback;
+
+/// Tracks the query for a paged list. Pages are requested with cursors, so
+/// the cursors of earlier pages are remembered to be able to go back.
+pub struct Pager<S> {
+    pub query: ListQuery<S>,
+    previous_cursors: Vec<Option<String>>,
+    next_cursor: Option<String>,
+    total_count: Option<i64>,
+}
+
+impl<S> Pager<S>
+where
+    S: Copy + PartialEq,
+{
+    pub fn new(sort: S, page_size: i64) -> Self {
+        Self {
+            query: ListQuery {
+                search: None,
+                sort,
+                descending: false,
+                cursor: None,
+                limit: Some(page_size),
+            },
+            previous_cursors: Vec::new(),
+            next_cursor: None,
+            total_count: None,
+        }
+    }
+
+    pub fn search(&mut self, search: String) {
+        self.query.search = if search.is_empty() {
+            None
+        } else {
+            Some(search)
+        };
+        self.first_page();
+    }
+
+    /// Sorts by `sort`, or reverses the direction if already sorted by it.
+    pub fn sort_by(&mut self, sort: S) {
+        if self.query.sort == sort {
+            self.query.descending = !self.query.descending;
+        } else {
+            self.query.sort = sort;
+            self.query.descending = false;
+        }
+        self.first_page();
+    }
+
+    pub fn next_page(&mut self) -> bool {
+        match self.next_cursor.take() {
+            Some(cursor) => {
+                let current = std::mem::replace(&mut self.query.cursor, Some(cursor));
+                self.previous_cursors.push(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn previous_page(&mut self) -> bool {
+        match self.previous_cursors.pop() {
+            Some(cursor) => {
+                self.query.cursor = cursor;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn received<T>(&mut self, page: &ListPage<T>) {
+        self.next_cursor = page.next_cursor.clone();
+        self.total_count = Some(page.total_count);
+    }
+
+    pub fn paging(&self, on_previous: Callback<()>, on_next: Callback<()>) -> Paging {
+        Paging {
+            total_count: self.total_count,
+            has_previous: !self.previous_cursors.is_empty(),
+            has_next: self.next_cursor.is_some(),
+            on_previous,
+            on_next,
+        }
+    }
+
+    pub fn sort_indicator(&self, sort: S) -> &'static str {
+        if self.query.sort != sort {
+            ""
+        } else if self.query.descending {
+            " ▼"
+        } else {
+            " ▲"
+        }
+    }
+
+    fn first_page(&mut self) {
+        self.query.cursor = None;
+        self.previous_cursors.clear();
+        self.next_cursor = None;
+    }
+}
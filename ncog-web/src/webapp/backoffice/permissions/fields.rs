@@ -0,0 +1,27 @@
+use crate::webapp::strings::Namable;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum SimulatorFields {
+    AccountId,
+    Service,
+    ResourceType,
+    ResourceId,
+    Action,
+    Result,
+    Explanation,
+}
+
+impl Namable for SimulatorFields {
+    fn name(&self) -> &'static str {
+        use SimulatorFields::*;
+        match self {
+            AccountId => "permission-simulator-account-id",
+            Service => "permission-statements-service",
+            ResourceType => "permission-statements-resource-type",
+            ResourceId => "permission-statements-resource-id",
+            Action => "permission-statements-action",
+            Result => "permission-simulator-result",
+            Explanation => "permission-simulator-explanation",
+        }
+    }
+}
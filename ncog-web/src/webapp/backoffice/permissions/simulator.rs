@@ -0,0 +1,309 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    backoffice::permissions::fields::SimulatorFields,
+    strings::Namable,
+    AppRoute, EditingId, LoggedInUser,
+};
+use khonsuweb::prelude::*;
+use ncog_shared::{
+    iam::{users_read_permissions_claim, IAMRequest, IAMResponse},
+    permissions::{Claim, PermissionDecision, PermissionExplanation},
+    NcogRequest, NcogResponse,
+};
+use std::{sync::Arc, time::Duration};
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+pub struct PermissionSimulator {
+    api: ApiBridge,
+    props: Props,
+    link: ComponentLink<Self>,
+    account_id: FormStorage<Option<i64>>,
+    service: FormStorage<Option<String>>,
+    resource_type: FormStorage<Option<String>>,
+    resource_id: FormStorage<Option<String>>,
+    action: FormStorage<Option<String>>,
+    claims: Vec<Claim>,
+    explanations: Option<Vec<PermissionExplanation>>,
+    flash_message: Option<flash::Message>,
+    is_simulating: bool,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    ValueChanged,
+    AddClaim,
+    RemoveClaim(usize),
+    Simulate,
+}
+
+impl Component for PermissionSimulator {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        Self {
+            api,
+            props,
+            link,
+            account_id: Default::default(),
+            service: Default::default(),
+            resource_type: Default::default(),
+            resource_id: Default::default(),
+            action: Default::default(),
+            claims: Vec::new(),
+            explanations: None,
+            flash_message: None,
+            is_simulating: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::IAM(IAMResponse::PermissionsSimulated {
+                        account_id,
+                        explanations,
+                    }) => {
+                        if self.account_id.unchecked_value() == Some(account_id) {
+                            self.explanations = Some(explanations);
+                        }
+                        self.is_simulating = false;
+                        true
+                    }
+                    NcogResponse::Error { message } => {
+                        if let Some(message) = message {
+                            self.flash_message = Some(flash::Message::new(
+                                flash::Kind::Danger,
+                                message,
+                                Duration::from_secs(3),
+                            ));
+                        }
+                        self.is_simulating = false;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::ValueChanged => true,
+            Message::AddClaim => {
+                if let Some(claim) = self.pending_claim() {
+                    self.claims.push(claim);
+                    self.explanations = None;
+                }
+                true
+            }
+            Message::RemoveClaim(index) => {
+                if index < self.claims.len() {
+                    self.claims.remove(index);
+                    self.explanations = None;
+                }
+                true
+            }
+            Message::Simulate => {
+                if let Ok(Some(account_id)) = self.account_id.value() {
+                    self.api.send(AgentMessage::Request(NcogRequest::IAM(
+                        IAMRequest::PermissionsSimulate {
+                            account_id,
+                            claims: self.claims.clone(),
+                        },
+                    )));
+                    self.is_simulating = true;
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        require_permission!(&self.props.user, users_read_permissions_claim(None));
+
+        let can_add_claim = !self.is_simulating && self.pending_claim().is_some();
+        let can_simulate = !self.is_simulating
+            && !self.claims.is_empty()
+            && matches!(self.account_id.value(), Ok(Some(_)));
+
+        html! {
+            <div>
+                <section class="section content">
+                    <Title>{localize!("permission-simulator")}</Title>
+                    <form>
+                        <flash::Flash message=self.flash_message.clone() />
+                        <Field<SimulatorFields> field=SimulatorFields::AccountId errors=None>
+                            <Label text=SimulatorFields::AccountId.localized_name() />
+                            <TextInput<SimulatorFields, i64> field=SimulatorFields::AccountId storage=self.account_id.clone() readonly=self.is_simulating on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<SimulatorFields>>
+                        <Field<SimulatorFields> field=SimulatorFields::Service errors=None>
+                            <Label text=SimulatorFields::Service.localized_name() />
+                            <TextInput<SimulatorFields, String> field=SimulatorFields::Service storage=self.service.clone() readonly=self.is_simulating on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<SimulatorFields>>
+                        <Field<SimulatorFields> field=SimulatorFields::ResourceType errors=None>
+                            <Label text=SimulatorFields::ResourceType.localized_name() />
+                            <TextInput<SimulatorFields, String> field=SimulatorFields::ResourceType storage=self.resource_type.clone() readonly=self.is_simulating on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<SimulatorFields>>
+                        <Field<SimulatorFields> field=SimulatorFields::ResourceId errors=None>
+                            <Label text=SimulatorFields::ResourceId.localized_name() />
+                            <TextInput<SimulatorFields, String> field=SimulatorFields::ResourceId storage=self.resource_id.clone() readonly=self.is_simulating on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<SimulatorFields>>
+                        <Field<SimulatorFields> field=SimulatorFields::Action errors=None>
+                            <Label text=SimulatorFields::Action.localized_name() />
+                            <TextInput<SimulatorFields, String> field=SimulatorFields::Action storage=self.action.clone() readonly=self.is_simulating on_value_changed=self.link.callback(|_| Message::ValueChanged) errors=None />
+                        </Field<SimulatorFields>>
+                        <div class="field is-grouped">
+                            <Button
+                                label=localize!("add-claim")
+                                disabled=!can_add_claim
+                                css_class="is-info"
+                                action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::AddClaim})
+                            />
+                            <Button
+                                label=localize!("simulate-claims")
+                                disabled=!can_simulate
+                                css_class="is-primary"
+                                action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Simulate})
+                                processing=self.is_simulating
+                            />
+                        </div>
+                    </form>
+                </section>
+
+                <section class="section content">
+                    <table class="table is-hoverable is-striped">
+                        <tr>
+                            <td>{ SimulatorFields::Service.localized_name() }</td>
+                            <td>{ SimulatorFields::ResourceType.localized_name() }</td>
+                            <td>{ SimulatorFields::ResourceId.localized_name() }</td>
+                            <td>{ SimulatorFields::Action.localized_name() }</td>
+                            <td>{ SimulatorFields::Result.localized_name() }</td>
+                            <td>{ SimulatorFields::Explanation.localized_name() }</td>
+                            <td></td>
+                        </tr>
+                        <tbody>
+                            { self.claims.iter().enumerate().map(|(index, claim)| self.render_claim(index, claim)).collect::<Html>() }
+                        </tbody>
+                    </table>
+                </section>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, _first_render: bool) {
+        self.props.set_title.emit(localize!("permission-simulator"));
+    }
+}
+
+impl PermissionSimulator {
+    fn pending_claim(&self) -> Option<Claim> {
+        let service = self.service.value().ok()??;
+        let action = self.action.value().ok()??;
+        let resource_type = self.resource_type.value().ok()?;
+        let resource_id = self.resource_id.value().ok()?;
+
+        Some(Claim::with_resource_id(
+            service,
+            resource_type,
+            resource_id,
+            action,
+        ))
+    }
+
+    fn render_claim(&self, index: usize, claim: &Claim) -> Html {
+        let explanation = self
+            .explanations
+            .as_ref()
+            .and_then(|explanations| explanations.get(index));
+        let (result, details) = match explanation {
+            Some(explanation) => {
+                let result = if explanation.allowed() {
+                    html! { <span class="tag is-success is-medium">{ localize!("action-allowed")}</span> }
+                } else {
+                    html! { <span class="tag is-danger is-medium">{ localize!("action-denied")}</span> }
+                };
+                let details = match &explanation.decision {
+                    Some(decision) => render_decision(decision),
+                    None => {
+                        html! { <span>{ localize!("permission-simulator-default-deny") }</span> }
+                    }
+                };
+                (result, details)
+            }
+            None => (Html::default(), Html::default()),
+        };
+
+        html! {
+            <tr>
+                <td>{ claim.service() }</td>
+                <td>{ claim.resource_type().map(ToString::to_string).unwrap_or_else(|| localize!("not-set")) }</td>
+                <td>{ claim.resource_id().map(ToString::to_string).unwrap_or_else(|| localize!("not-set")) }</td>
+                <td>{ claim.action() }</td>
+                <td>{ result }</td>
+                <td>{ details }</td>
+                <td>
+                    <Button
+                        label=localize!("delete")
+                        css_class="is-danger"
+                        disabled=self.is_simulating
+                        action=self.link.callback(move |_| Message::RemoveClaim(index))
+                    />
+                </td>
+            </tr>
+        }
+    }
+}
+
+fn render_decision(decision: &PermissionDecision) -> Html {
+    let path = vec![
+        decision
+            .service
+            .clone()
+            .unwrap_or_else(|| localize!("any-service")),
+        decision
+            .resource_type
+            .clone()
+            .unwrap_or_else(|| localize!("any-resource-type")),
+        decision
+            .resource_id
+            .clone()
+            .unwrap_or_else(|| localize!("any-resource-id")),
+        decision
+            .action
+            .clone()
+            .unwrap_or_else(|| localize!("any-action")),
+    ]
+    .join(" / ");
+    let statement = decision
+        .statement_id
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+    let source = match decision.role_id {
+        Some(role_id) => html! {
+            <RouterAnchor<AppRoute> route=AppRoute::BackOfficeRoleEdit(EditingId::Id(role_id))>
+                { localize!("permission-simulator-from-role", "statement" => statement, "role" => role_id.to_string()) }
+            </RouterAnchor<AppRoute>>
+        },
+        None => {
+            html! { <span>{ localize!("permission-simulator-from-global", "statement" => statement) }</span> }
+        }
+    };
+
+    html! {
+        <div>
+            <p>{ path }</p>
+            <p>{ source }</p>
+        </div>
+    }
+}
@@ -6,6 +6,7 @@ use crate::webapp::{
         render_heading_with_add_button,
         roles::fields::RoleFields,
         roles::permission_statements::{self},
+        roles::picker::{self, RolePicker},
     },
     strings::Namable,
     AppRoute, EditingId,
@@ -29,6 +30,8 @@ pub struct Role {
     name: FormStorage<Option<String>>,
     permission_statements: Option<Rc<RwLock<Vec<PermissionStatement>>>>,
     pending_permission_deletion: Option<i64>,
+    included_roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
+    available_roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +39,9 @@ pub enum RoleMessage {
     PermissionRequestDelete(i64),
     PermissionDelete,
     PermissionCancelDelete,
+    IncludedRoleAdd(RoleSummary),
+    IncludedRoleRemove(i64),
+    IncludedRolesSave,
 }
 
 impl Form for Role {
@@ -60,6 +66,16 @@ impl Form for Role {
             .map(|role_id| NcogRequest::IAM(IAMRequest::RoleGet(role_id)))
     }
 
+    fn load_related_requests(&self, props: &Props) -> Vec<NcogRequest> {
+        if props.editing_id.is_existing() {
+            vec![NcogRequest::IAM(IAMRequest::RolesList(
+                picker::available_roles_query(),
+            ))]
+        } else {
+            Vec::new()
+        }
+    }
+
     fn save(&mut self, props: &Props, api: &mut ApiBridge) {
         let role = RoleSummary {
             id: props.editing_id.existing_id(),
@@ -80,6 +96,7 @@ impl Form for Role {
                         self.name.update(Some(role.name));
                         self.permission_statements =
                             Some(Rc::new(RwLock::new(role.permission_statements)));
+                        self.included_roles = Some(Rc::new(RwLock::new(role.included_roles)));
                         Handled::ShouldRender(true)
                     } else {
                         Handled::ShouldRender(false)
@@ -89,6 +106,14 @@ impl Form for Role {
                     label: "saved-role",
                     new_id,
                 },
+                IAMResponse::RoleIncludedRolesSaved(role_id) => Handled::Saved {
+                    label: "saved-included-roles",
+                    new_id: role_id,
+                },
+                IAMResponse::RolesList(page) => {
+                    self.available_roles = Some(Rc::new(RwLock::new(page.entities)));
+                    Handled::ShouldRender(true)
+                }
                 IAMResponse::PermissionStatementDeleted(id) => {
                     if let Some(permission_statements) = &self.permission_statements {
                         let mut permission_statements = permission_statements.write().unwrap();
@@ -122,6 +147,31 @@ impl Form for Role {
             EditingId::New => Html::default(),
         };
 
+        let included_roles = match edit_form.props.editing_id {
+            EditingId::Id(role_id) => html! {
+                <section class="section content">
+                    <Title size=3>{RoleFields::IncludedRoles.localized_name()}</Title>
+                    <p>{localize!("included-roles-help")}</p>
+                    <RolePicker
+                        available_roles=self.available_roles.clone()
+                        roles=self.included_roles.clone()
+                        excluded_role_ids=vec![role_id]
+                        readonly=readonly
+                        on_add=edit_form.link.callback(|role| Message::FormMessage(RoleMessage::IncludedRoleAdd(role)))
+                        on_remove=edit_form.link.callback(|id| Message::FormMessage(RoleMessage::IncludedRoleRemove(id)))
+                        />
+                    <Button
+                        label=localize!("save-included-roles")
+                        disabled=readonly
+                        css_class="is-primary"
+                        action=edit_form.link.callback(|e: web_sys::MouseEvent| {e.prevent_default(); Message::FormMessage(RoleMessage::IncludedRolesSave)})
+                        processing=edit_form.is_saving
+                    />
+                </section>
+            },
+            EditingId::New => Html::default(),
+        };
+
         let permission_statements = if is_new {
             Html::default()
         } else {
@@ -195,6 +245,8 @@ impl Form for Role {
                     </form>
                 </section>
 
+                { included_roles }
+
                 { permission_statements }
             </div>
         }
@@ -219,7 +271,7 @@ impl Form for Role {
     fn update(
         &mut self,
         message: Self::Message,
-        _props: &Props,
+        props: &Props,
         api: &mut ApiBridge,
     ) -> ShouldRender {
         match message {
@@ -237,6 +289,37 @@ impl Form for Role {
             RoleMessage::PermissionCancelDelete => {
                 self.pending_permission_deletion = None;
             }
+            RoleMessage::IncludedRoleAdd(role) => {
+                let included_roles = self
+                    .included_roles
+                    .get_or_insert_with(|| Rc::new(RwLock::new(Vec::new())));
+                included_roles.write().unwrap().push(role);
+            }
+            RoleMessage::IncludedRoleRemove(id) => {
+                if let Some(included_roles) = &self.included_roles {
+                    let mut included_roles = included_roles.write().unwrap();
+                    included_roles.retain(|role| role.id != Some(id));
+                }
+            }
+            RoleMessage::IncludedRolesSave => {
+                if let Some(role_id) = props.editing_id.existing_id() {
+                    let included_role_ids = match &self.included_roles {
+                        Some(included_roles) => included_roles
+                            .read()
+                            .unwrap()
+                            .iter()
+                            .filter_map(|role| role.id)
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    api.send(AgentMessage::Request(NcogRequest::IAM(
+                        IAMRequest::RoleIncludedRolesSave {
+                            role_id,
+                            included_role_ids,
+                        },
+                    )));
+                }
+            }
         }
         true
     }
@@ -6,6 +6,7 @@ pub enum RoleFields {
     Name,
     CreatedAt,
     PermissionStatements,
+    IncludedRoles,
 }
 
 impl Namable for RoleFields {
@@ -15,6 +16,7 @@ impl Namable for RoleFields {
             Self::Name => "role-fields-name",
             Self::CreatedAt => "role-fields-created-at",
             Self::PermissionStatements => "role-fields-permission-statements",
+            Self::IncludedRoles => "role-fields-included-roles",
         }
     }
 }
@@ -1,13 +1,19 @@
 use crate::webapp::{
     api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
-    backoffice::{entity_list::EntityList, render_heading_with_add_button, roles::summary_list},
-    has_permission, AppRoute, EditingId, LoggedInUser,
+    backoffice::{
+        entity_list::{pager::Pager, sortable_head, EntityList},
+        render_heading_with_add_button,
+        roles::{fields::RoleFields, summary_list},
+    },
+    has_permission,
+    strings::Namable,
+    AppRoute, EditingId, LoggedInUser,
 };
 use khonsuweb::prelude::*;
 use ncog_shared::{
     iam::{
-        roles_create_claim, roles_delete_claim, roles_list_claim, IAMRequest, IAMResponse,
-        RoleSummary,
+        roles_create_claim, roles_delete_claim, roles_list_claim, roles_read_claim, IAMRequest,
+        IAMResponse, RoleSort, RoleSummary,
     },
     NcogRequest, NcogResponse,
 };
@@ -18,12 +24,15 @@ use std::{
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+const PAGE_SIZE: i64 = 50;
+
 pub struct RolesList {
     api: ApiBridge,
     props: Props,
     roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
     link: ComponentLink<Self>,
     pending_delete_id: Option<i64>,
+    pager: Pager<RoleSort>,
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -37,6 +46,10 @@ pub enum RolesListMessage {
     RoleRequestDelete(i64),
     RoleDelete,
     RoleCancelDelete,
+    Search(String),
+    Sort(RoleSort),
+    PreviousPage,
+    NextPage,
 }
 
 impl Component for RolesList {
@@ -51,6 +64,7 @@ impl Component for RolesList {
             link,
             roles: None,
             pending_delete_id: None,
+            pager: Pager::new(RoleSort::Name, PAGE_SIZE),
         }
     }
 
@@ -59,8 +73,9 @@ impl Component for RolesList {
             RolesListMessage::WsMessage(agent_response) => match agent_response {
                 AgentResponse::Response(ws_response) => match ws_response {
                     NcogResponse::IAM(iam_response) => match iam_response {
-                        IAMResponse::RolesList(users) => {
-                            self.roles = Some(Rc::new(RwLock::new(users)));
+                        IAMResponse::RolesList(page) => {
+                            self.pager.received(&page);
+                            self.roles = Some(Rc::new(RwLock::new(page.entities)));
                             true
                         }
                         IAMResponse::RoleDeleted(id) => {
@@ -95,6 +110,28 @@ impl Component for RolesList {
                 self.pending_delete_id = None;
                 true
             }
+            RolesListMessage::Search(search) => {
+                self.pager.search(search);
+                self.initialize();
+                true
+            }
+            RolesListMessage::Sort(sort) => {
+                self.pager.sort_by(sort);
+                self.initialize();
+                true
+            }
+            RolesListMessage::PreviousPage => {
+                if self.pager.previous_page() {
+                    self.initialize();
+                }
+                true
+            }
+            RolesListMessage::NextPage => {
+                if self.pager.next_page() {
+                    self.initialize();
+                }
+                true
+            }
         }
     }
 
@@ -106,6 +143,7 @@ impl Component for RolesList {
 
     fn view(&self) -> Html {
         require_permission!(&self.props.user, roles_list_claim());
+        require_permission!(&self.props.user, roles_read_claim(None));
         let can_create = has_permission(&self.props.user, roles_create_claim());
         let link = self.link.clone();
         let user = self.props.user.clone();
@@ -124,7 +162,7 @@ impl Component for RolesList {
                     { render_heading_with_add_button("list-roles", AppRoute::BackOfficeRoleEdit(EditingId::New), "add-role", !can_create) }
 
                     <EntityList<RoleSummary>
-                        header=summary_list::standard_head()
+                        header=self.head()
                         row=summary_list::row(move |role| {
                             let id = role.id.unwrap();
                             let can_delete = has_permission(&user, roles_delete_claim(role.id));
@@ -148,6 +186,11 @@ impl Component for RolesList {
                             }
                         })
                         entities=self.roles.clone()
+                        on_search=self.link.callback(RolesListMessage::Search)
+                        paging=self.pager.paging(
+                            self.link.callback(|_| RolesListMessage::PreviousPage),
+                            self.link.callback(|_| RolesListMessage::NextPage),
+                        )
                     />
                 </section>
             </div>
@@ -167,7 +210,24 @@ impl RolesList {
     fn initialize(&mut self) {
         self.api
             .send(AgentMessage::Request(ncog_shared::NcogRequest::IAM(
-                IAMRequest::RolesList,
+                IAMRequest::RolesList(self.pager.query.clone()),
             )))
     }
+
+    fn head(&self) -> Html {
+        let sortable = |field: RoleFields, sort: RoleSort| {
+            sortable_head(
+                field.localized_name(),
+                self.pager.sort_indicator(sort),
+                self.link.callback(move |_| RolesListMessage::Sort(sort)),
+            )
+        };
+        html! {
+            <tr>
+                { sortable(RoleFields::Id, RoleSort::Id) }
+                { sortable(RoleFields::Name, RoleSort::Name) }
+                <td></td>
+            </tr>
+        }
+    }
 }
@@ -2,4 +2,5 @@ pub mod edit;
 pub mod fields;
 pub mod list;
 pub mod permission_statements;
+pub mod picker;
 pub mod summary_list;
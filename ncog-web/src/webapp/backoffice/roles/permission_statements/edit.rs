@@ -24,7 +24,7 @@ pub struct PermissionStatementForm {
     id: FormStorage<Option<i64>>,
     service: FormStorage<Option<String>>,
     resource_type: FormStorage<Option<String>>,
-    resource_id: FormStorage<Option<i64>>,
+    resource_id: FormStorage<Option<String>>,
     action: FormStorage<Option<String>>,
     allow: FormStorage<bool>,
     comment: FormStorage<Option<String>>,
@@ -136,7 +136,7 @@ impl Form for PermissionStatementForm {
 
                     <Field<PermissionStatementFields> field=PermissionStatementFields::ResourceId errors=errors.clone()>
                         <Label text=PermissionStatementFields::ResourceId.localized_name() />
-                        <TextInput<PermissionStatementFields, i64> field=PermissionStatementFields::ResourceId storage=self.resource_id.clone() readonly=readonly on_value_changed=edit_form.link.callback(|_| Message::ValueChanged) placeholder=localize!("any-resource-id") errors=errors.clone() />
+                        <TextInput<PermissionStatementFields, String> field=PermissionStatementFields::ResourceId storage=self.resource_id.clone() readonly=readonly on_value_changed=edit_form.link.callback(|_| Message::ValueChanged) placeholder=localize!("any-resource-id") errors=errors.clone() />
                     </Field<PermissionStatementFields>>
 
                     <Field<PermissionStatementFields> field=PermissionStatementFields::Action errors=errors.clone()>
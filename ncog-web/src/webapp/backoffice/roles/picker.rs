@@ -0,0 +1,175 @@
+use crate::webapp::backoffice::{entity_list::EntityList, roles::summary_list};
+use khonsuweb::prelude::*;
+use ncog_shared::iam::{ListQuery, RoleSort, RoleSummary};
+use std::sync::RwLock;
+use yew::prelude::*;
+
+/// The number of roles offered by the picker.
+const AVAILABLE_ROLES_LIMIT: i64 = 500;
+
+/// The query to request the roles the picker offers with.
+pub fn available_roles_query() -> ListQuery<RoleSort> {
+    ListQuery {
+        sort: RoleSort::Name,
+        limit: Some(AVAILABLE_ROLES_LIMIT),
+        ..Default::default()
+    }
+}
+
+pub struct RolePicker {
+    props: Props,
+    link: ComponentLink<Self>,
+    selected_role_id: Option<i64>,
+}
+
+#[derive(Clone, Properties)]
+pub struct Props {
+    /// All of the roles that can be picked from.
+    pub available_roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
+    /// The roles that are currently picked.
+    pub roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
+    /// Roles that should never be offered, such as the role being edited.
+    #[prop_or_default]
+    pub excluded_role_ids: Vec<i64>,
+    #[prop_or_default]
+    pub readonly: bool,
+    pub on_add: Callback<RoleSummary>,
+    pub on_remove: Callback<i64>,
+}
+
+pub enum Message {
+    RoleSelected(ChangeData),
+    Add,
+    Remove(i64),
+}
+
+impl Component for RolePicker {
+    type Message = Message;
+    type Properties = Props;
+
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        Self {
+            props,
+            link,
+            selected_role_id: None,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::RoleSelected(change) => {
+                if let ChangeData::Select(select) = change {
+                    self.selected_role_id = select.value().parse().ok();
+                }
+                true
+            }
+            Message::Add => {
+                if let Some(role) = self.selected_role() {
+                    self.props.on_add.emit(role);
+                }
+                self.selected_role_id = None;
+                true
+            }
+            Message::Remove(id) => {
+                self.props.on_remove.emit(id);
+                false
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        let link = self.link.clone();
+        let readonly = self.props.readonly;
+        html! {
+            <div>
+                <EntityList<RoleSummary>
+                    header=summary_list::standard_head()
+                    row=summary_list::row(move |role| {
+                        let id = role.id.unwrap();
+                        html! {
+                            <div class="field is-grouped">
+                                <p class="control">
+                                    { summary_list::standard_actions(role) }
+                                </p>
+                                <p class="control">
+                                    <Button
+                                        label=localize!("remove")
+                                        css_class="is-danger"
+                                        disabled=readonly
+                                        action=link.callback(move |_| Message::Remove(id))
+                                    />
+                                </p>
+                            </div>
+                        }
+                    })
+                    entities=self.props.roles.clone()
+                    />
+                <div class="field has-addons">
+                    <div class="control">
+                        <div class="select">
+                            <select disabled=readonly onchange=self.link.callback(Message::RoleSelected)>
+                                <option value="" selected=self.selected_role_id.is_none()>{ localize!("select-role") }</option>
+                                { self.pickable_roles().iter().map(|role| self.render_option(role)).collect::<Html>() }
+                            </select>
+                        </div>
+                    </div>
+                    <Button
+                        label=localize!("add")
+                        css_class="is-success"
+                        disabled=readonly || self.selected_role_id.is_none()
+                        action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Add})
+                    />
+                </div>
+            </div>
+        }
+    }
+}
+
+impl RolePicker {
+    fn pickable_roles(&self) -> Vec<RoleSummary> {
+        let picked = match &self.props.roles {
+            Some(roles) => roles
+                .read()
+                .unwrap()
+                .iter()
+                .filter_map(|role| role.id)
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        };
+
+        match &self.props.available_roles {
+            Some(roles) => roles
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|role| match role.id {
+                    Some(id) => {
+                        !picked.contains(&id) && !self.props.excluded_role_ids.contains(&id)
+                    }
+                    None => false,
+                })
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn selected_role(&self) -> Option<RoleSummary> {
+        let selected_role_id = self.selected_role_id?;
+        self.pickable_roles()
+            .into_iter()
+            .find(|role| role.id == Some(selected_role_id))
+    }
+
+    fn render_option(&self, role: &RoleSummary) -> Html {
+        let id = role.id.unwrap();
+        html! {
+            <option value=id.to_string() selected=self.selected_role_id == Some(id)>{ &role.name }</option>
+        }
+    }
+}
@@ -1,13 +1,9 @@
 use crate::webapp::{
-    backoffice::{
-        entity_list::{body::EntityRenderer, EntityList},
-        roles::fields::RoleFields,
-    },
+    backoffice::{entity_list::body::EntityRenderer, roles::fields::RoleFields},
     strings::Namable,
     AppRoute, EditingId,
 };
 use ncog_shared::iam::RoleSummary;
-use std::{rc::Rc, sync::RwLock};
 use yew::prelude::*;
 use yew_router::prelude::*;
 
@@ -21,20 +17,6 @@ pub fn standard_head() -> Html {
     }
 }
 
-pub fn standard(entities: Option<Rc<RwLock<Vec<RoleSummary>>>>) -> Html {
-    html! {
-        <EntityList<RoleSummary>
-            row=standard_row()
-            header=standard_head()
-            entities=entities
-            />
-    }
-}
-
-pub fn standard_row() -> EntityRenderer<RoleSummary> {
-    row(standard_actions)
-}
-
 pub fn row<F: Fn(&RoleSummary) -> Html + 'static>(actions: F) -> EntityRenderer<RoleSummary> {
     EntityRenderer::new(move |role: &RoleSummary| {
         html! {
@@ -49,7 +31,7 @@ pub fn row<F: Fn(&RoleSummary) -> Html + 'static>(actions: F) -> EntityRenderer<
     })
 }
 
-fn standard_actions(role: &RoleSummary) -> Html {
+pub fn standard_actions(role: &RoleSummary) -> Html {
     html! {
         <RouterButton<AppRoute> route=AppRoute::BackOfficeRoleEdit(EditingId::Id(role.id.unwrap())) classes="button is-primary" >
             <strong>{ localize!("edit") }</strong>
@@ -1,34 +1,54 @@
 use crate::webapp::{
-    api::ApiBridge,
+    api::{AgentMessage, ApiBridge},
     backoffice::{
         edit_form::{EditForm, ErrorMap, Form, Handled, Message, Props},
-        roles::summary_list,
+        roles::picker::{self, RolePicker},
         users::fields::UserFields,
     },
+    has_permission,
     strings::Namable,
     AppRoute, EditingId,
 };
+use chrono::{Duration, Utc};
 use khonsuweb::{flash, forms::prelude::*, validations::prelude::*};
 use ncog_shared::{
     iam::{
-        users_create_claim, users_read_claim, users_update_claim, IAMRequest, IAMResponse,
-        RoleSummary,
+        users_create_claim, users_merge_claim, users_read_claim, users_revoke_tokens_claim,
+        users_suspend_claim, users_update_claim, IAMRequest, IAMResponse, RoleSummary,
     },
     permissions::Claim,
-    NcogRequest, NcogResponse,
+    AccountSuspension, NcogRequest, NcogResponse,
 };
 use std::{rc::Rc, sync::RwLock};
 use yew::prelude::*;
+use yew_router::prelude::*;
 
 #[derive(Debug, Default)]
 pub struct User {
     id: FormStorage<Option<i64>>,
-    screenname: FormStorage<Option<String>>,
+    login: FormStorage<Option<String>>,
+    display_name: FormStorage<Option<String>>,
     roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
+    available_roles: Option<Rc<RwLock<Vec<RoleSummary>>>>,
+    suspension: Option<AccountSuspension>,
+    suspension_reason: FormStorage<Option<String>>,
+    suspension_days: FormStorage<Option<i64>>,
+    merged_into_id: Option<i64>,
+    merge_target_id: FormStorage<Option<i64>>,
+}
+
+#[derive(Debug, Clone)]
+pub enum UserMessage {
+    RoleAdd(RoleSummary),
+    RoleRemove(i64),
+    Suspend,
+    Unsuspend,
+    RevokeIdentityTokens,
+    Merge,
 }
 
 impl Form for User {
-    type Message = ();
+    type Message = UserMessage;
     type Fields = UserFields;
 
     fn title(is_new: bool) -> &'static str {
@@ -49,8 +69,19 @@ impl Form for User {
         })
     }
 
+    fn load_related_requests(&self, props: &Props) -> Vec<NcogRequest> {
+        if props.editing_id.is_existing() {
+            vec![NcogRequest::IAM(IAMRequest::RolesList(
+                picker::available_roles_query(),
+            ))]
+        } else {
+            Vec::new()
+        }
+    }
+
     fn save(&mut self, _props: &Props, _api: &mut ApiBridge) {
-        todo!("need to be able to save users eventually")
+        // The only editable part of a user is their roles, which are sent to
+        // the server as they are picked.
     }
 
     fn handle_webserver_response(&mut self, response: NcogResponse) -> Handled {
@@ -59,13 +90,66 @@ impl Form for User {
                 IAMResponse::UserProfile(profile) => {
                     if let Some(id) = &profile.id {
                         self.id.update(Some(*id));
-                        self.screenname.update(profile.screenname);
+                        self.login.update(profile.login);
+                        self.display_name.update(profile.display_name);
                         self.roles = Some(Rc::new(RwLock::new(profile.roles)));
+                        self.suspension = profile.suspension;
+                        self.merged_into_id = profile.merged_into_id;
                         Handled::ShouldRender(true)
                     } else {
                         Handled::ShouldRender(false)
                     }
                 }
+                IAMResponse::RolesList(page) => {
+                    self.available_roles = Some(Rc::new(RwLock::new(page.entities)));
+                    Handled::ShouldRender(true)
+                }
+                IAMResponse::UserRoleAdded {
+                    account_id,
+                    role_id,
+                } => {
+                    if let Some(role) = self.available_role(role_id) {
+                        let roles = self
+                            .roles
+                            .get_or_insert_with(|| Rc::new(RwLock::new(Vec::new())));
+                        let mut roles = roles.write().unwrap();
+                        if !roles.iter().any(|role| role.id == Some(role_id)) {
+                            roles.push(role);
+                        }
+                    }
+                    Handled::Saved {
+                        label: "saved-user-roles",
+                        new_id: account_id,
+                    }
+                }
+                IAMResponse::UserRoleRemoved {
+                    account_id,
+                    role_id,
+                } => {
+                    if let Some(roles) = &self.roles {
+                        let mut roles = roles.write().unwrap();
+                        roles.retain(|role| role.id != Some(role_id));
+                    }
+                    Handled::Saved {
+                        label: "saved-user-roles",
+                        new_id: account_id,
+                    }
+                }
+                IAMResponse::UserSuspended(account_id)
+                | IAMResponse::UserUnsuspended(account_id) => Handled::Saved {
+                    label: "saved-user-suspension",
+                    new_id: account_id,
+                },
+                IAMResponse::UserIdentityTokensRevoked(account_id) => Handled::Saved {
+                    label: "saved-user-identity-tokens-revoked",
+                    new_id: account_id,
+                },
+                IAMResponse::UsersMerged {
+                    source_account_id, ..
+                } => Handled::Saved {
+                    label: "saved-user-merge",
+                    new_id: source_account_id,
+                },
                 _ => Handled::ShouldRender(false),
             },
             _ => unreachable!("Unexpected message from server"),
@@ -76,7 +160,7 @@ impl Form for User {
         &self,
         edit_form: &EditForm<Self>,
         readonly: bool,
-        can_save: bool,
+        _can_save: bool,
         errors: Option<Rc<ErrorMap<Self::Fields>>>,
     ) -> Html {
         html! {
@@ -89,26 +173,32 @@ impl Form for User {
                             <Label text=UserFields::Id.localized_name() />
                             <TextInput<UserFields,i64> field=UserFields::Id storage=self.id.clone() readonly=true errors=errors.clone() />
                         </Field<UserFields>>
-                        <Field<UserFields> field=UserFields::Screenname errors=errors.clone()>
-                            <Label text=UserFields::Screenname.localized_name() />
-                            <TextInput<UserFields,String> field=UserFields::Screenname storage=self.screenname.clone() readonly=readonly on_value_changed=edit_form.link.callback(|_| Message::ValueChanged) placeholder="Type your message here..." errors=errors.clone() />
+                        <Field<UserFields> field=UserFields::Login errors=errors.clone()>
+                            <Label text=UserFields::Login.localized_name() />
+                            <TextInput<UserFields,String> field=UserFields::Login storage=self.login.clone() readonly=true errors=errors.clone() />
+                        </Field<UserFields>>
+                        <Field<UserFields> field=UserFields::DisplayName errors=errors.clone()>
+                            <Label text=UserFields::DisplayName.localized_name() />
+                            <TextInput<UserFields,String> field=UserFields::DisplayName storage=self.display_name.clone() readonly=true errors=errors.clone() />
                         </Field<UserFields>>
-                        <Button
-                            label=localize!("save-user")
-                            disabled=!can_save
-                            css_class="is-primary"
-                            action=edit_form.link.callback(|e: web_sys::MouseEvent| {e.prevent_default(); Message::Save})
-                            processing=edit_form.is_saving
-                        />
                     </form>
                 </section>
 
                 <section class="Section content">
                     <Title size=3>{UserFields::AssignedRoles.localized_name()}</Title>
-
-                    { summary_list::standard(self.roles.clone()) }
-
+                    <p>{localize!("user-roles-help")}</p>
+                    <RolePicker
+                        available_roles=self.available_roles.clone()
+                        roles=self.roles.clone()
+                        readonly=readonly
+                        on_add=edit_form.link.callback(|role| Message::FormMessage(UserMessage::RoleAdd(role)))
+                        on_remove=edit_form.link.callback(|id| Message::FormMessage(UserMessage::RoleRemove(id)))
+                        />
                 </section>
+
+                { self.render_suspension(edit_form, errors.clone()) }
+                { self.render_identity_tokens(edit_form) }
+                { self.render_merge(edit_form, errors.clone()) }
             </div>
         }
     }
@@ -126,4 +216,189 @@ impl Form for User {
     fn create_claim() -> Claim {
         users_create_claim()
     }
+
+    fn update(
+        &mut self,
+        message: Self::Message,
+        props: &Props,
+        api: &mut ApiBridge,
+    ) -> ShouldRender {
+        let account_id = match props.editing_id.existing_id() {
+            Some(account_id) => account_id,
+            None => return false,
+        };
+
+        let request = match message {
+            UserMessage::RoleAdd(role) => match role.id {
+                Some(role_id) => IAMRequest::UserAddRole {
+                    account_id,
+                    role_id,
+                },
+                None => return false,
+            },
+            UserMessage::RoleRemove(role_id) => IAMRequest::UserRemoveRole {
+                account_id,
+                role_id,
+            },
+            UserMessage::Suspend => IAMRequest::UserSuspend {
+                account_id,
+                reason: self
+                    .suspension_reason
+                    .value()
+                    .unwrap_or(None)
+                    .filter(|reason| !reason.is_empty()),
+                until: self
+                    .suspension_days
+                    .value()
+                    .unwrap_or(None)
+                    .map(|days| Utc::now() + Duration::days(days)),
+            },
+            UserMessage::Unsuspend => IAMRequest::UserUnsuspend(account_id),
+            UserMessage::RevokeIdentityTokens => IAMRequest::UserRevokeIdentityTokens(account_id),
+            UserMessage::Merge => match self.merge_target_id.value().unwrap_or(None) {
+                Some(target_account_id) => IAMRequest::UserMerge {
+                    source_account_id: account_id,
+                    target_account_id,
+                },
+                None => return false,
+            },
+        };
+        api.send(AgentMessage::Request(NcogRequest::IAM(request)));
+        false
+    }
+}
+
+impl User {
+    fn render_suspension(
+        &self,
+        edit_form: &EditForm<Self>,
+        errors: Option<Rc<ErrorMap<UserFields>>>,
+    ) -> Html {
+        let can_suspend = has_permission(
+            &edit_form.props.user,
+            users_suspend_claim(edit_form.props.editing_id.existing_id()),
+        );
+        let status = match &self.suspension {
+            Some(suspension) => {
+                let until = match &suspension.until {
+                    Some(until) => until.to_rfc2822(),
+                    None => localize!("user-suspended-indefinitely"),
+                };
+                html! {
+                    <p>
+                        { localize!("user-suspended-until", "until" => until) }
+                        <br />
+                        { suspension.reason.clone().unwrap_or_else(|| localize!("not-set")) }
+                    </p>
+                }
+            }
+            None => html! { <p>{ localize!("user-not-suspended") }</p> },
+        };
+
+        html! {
+            <section class="section content">
+                <Title size=3>{UserFields::Suspension.localized_name()}</Title>
+                { status }
+                <Field<UserFields> field=UserFields::SuspensionReason errors=errors.clone()>
+                    <Label text=UserFields::SuspensionReason.localized_name() />
+                    <TextInput<UserFields,String> field=UserFields::SuspensionReason storage=self.suspension_reason.clone() readonly=!can_suspend errors=errors.clone() />
+                </Field<UserFields>>
+                <Field<UserFields> field=UserFields::SuspensionDays errors=errors.clone()>
+                    <Label text=UserFields::SuspensionDays.localized_name() />
+                    <TextInput<UserFields,i64> field=UserFields::SuspensionDays storage=self.suspension_days.clone() readonly=!can_suspend placeholder=localize!("user-suspended-indefinitely") errors=errors.clone() />
+                </Field<UserFields>>
+                <div class="field is-grouped">
+                    <p class="control">
+                        <Button
+                            label=localize!("suspend-user")
+                            css_class="is-danger"
+                            disabled=!can_suspend || edit_form.is_saving
+                            action=edit_form.link.callback(|e: web_sys::MouseEvent| {e.prevent_default(); Message::FormMessage(UserMessage::Suspend)})
+                        />
+                    </p>
+                    <p class="control">
+                        <Button
+                            label=localize!("unsuspend-user")
+                            disabled=!can_suspend || self.suspension.is_none() || edit_form.is_saving
+                            action=edit_form.link.callback(|e: web_sys::MouseEvent| {e.prevent_default(); Message::FormMessage(UserMessage::Unsuspend)})
+                        />
+                    </p>
+                </div>
+            </section>
+        }
+    }
+
+    fn render_identity_tokens(&self, edit_form: &EditForm<Self>) -> Html {
+        let can_revoke = has_permission(
+            &edit_form.props.user,
+            users_revoke_tokens_claim(edit_form.props.editing_id.existing_id()),
+        );
+        html! {
+            <section class="section content">
+                <Title size=3>{localize!("user-identity-tokens")}</Title>
+                <p>{ localize!("user-identity-tokens-help") }</p>
+                <Button
+                    label=localize!("revoke-user-identity-tokens")
+                    css_class="is-danger"
+                    disabled=!can_revoke || edit_form.is_saving
+                    action=edit_form.link.callback(|e: web_sys::MouseEvent| {e.prevent_default(); Message::FormMessage(UserMessage::RevokeIdentityTokens)})
+                />
+            </section>
+        }
+    }
+
+    fn render_merge(
+        &self,
+        edit_form: &EditForm<Self>,
+        errors: Option<Rc<ErrorMap<UserFields>>>,
+    ) -> Html {
+        let merge_form = match self.merged_into_id {
+            Some(merged_into_id) => html! {
+                <p>
+                    <RouterAnchor<AppRoute> route=AppRoute::BackOfficeUserEdit(EditingId::Id(merged_into_id))>
+                        { localize!("user-merged-into", "id" => merged_into_id) }
+                    </RouterAnchor<AppRoute>>
+                </p>
+            },
+            None => {
+                let can_merge = has_permission(
+                    &edit_form.props.user,
+                    users_merge_claim(edit_form.props.editing_id.existing_id()),
+                );
+                html! {
+                    <>
+                        <p>{ localize!("merge-user-help") }</p>
+                        <Field<UserFields> field=UserFields::MergeTargetId errors=errors.clone()>
+                            <Label text=UserFields::MergeTargetId.localized_name() />
+                            <TextInput<UserFields,i64> field=UserFields::MergeTargetId storage=self.merge_target_id.clone() readonly=!can_merge errors=errors.clone() />
+                        </Field<UserFields>>
+                        <Button
+                            label=localize!("merge-user")
+                            css_class="is-danger"
+                            disabled=!can_merge || edit_form.is_saving
+                            action=edit_form.link.callback(|e: web_sys::MouseEvent| {e.prevent_default(); Message::FormMessage(UserMessage::Merge)})
+                        />
+                    </>
+                }
+            }
+        };
+
+        html! {
+            <section class="section content">
+                <Title size=3>{localize!("merge-user")}</Title>
+                { merge_form }
+            </section>
+        }
+    }
+
+    fn available_role(&self, role_id: i64) -> Option<RoleSummary> {
+        self.available_roles.as_ref().and_then(|roles| {
+            roles
+                .read()
+                .unwrap()
+                .iter()
+                .find(|role| role.id == Some(role_id))
+                .cloned()
+        })
+    }
 }
@@ -3,18 +3,28 @@ use crate::webapp::strings::Namable;
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum UserFields {
     Id,
-    Screenname,
+    Login,
+    DisplayName,
     CreatedAt,
     AssignedRoles,
+    Suspension,
+    SuspensionReason,
+    SuspensionDays,
+    MergeTargetId,
 }
 
 impl Namable for UserFields {
     fn name(&self) -> &'static str {
         match self {
             Self::Id => "user-fields-id",
-            Self::Screenname => "user-fields-screenname",
+            Self::Login => "user-fields-login",
+            Self::DisplayName => "user-fields-display-name",
             Self::CreatedAt => "user-fields-created-at",
             Self::AssignedRoles => "user-fields-assigned-roles",
+            Self::Suspension => "user-fields-suspension",
+            Self::SuspensionReason => "user-fields-suspension-reason",
+            Self::SuspensionDays => "user-fields-suspension-days",
+            Self::MergeTargetId => "user-fields-merge-target-id",
         }
     }
 }
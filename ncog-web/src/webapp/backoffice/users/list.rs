@@ -1,7 +1,7 @@
 use crate::webapp::{
     api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
     backoffice::{
-        entity_list::{body::EntityRenderer, EntityList},
+        entity_list::{body::EntityRenderer, pager::Pager, sortable_head, EntityList},
         users::fields::UserFields,
     },
     strings::{localize, localize_raw, Namable},
@@ -9,7 +9,7 @@ use crate::webapp::{
 };
 use khonsuweb::title::Title;
 use ncog_shared::{
-    iam::{users_list_claim, IAMRequest, IAMResponse, User},
+    iam::{users_list_claim, users_read_claim, IAMRequest, IAMResponse, User, UserSort},
     NcogResponse,
 };
 use std::{
@@ -19,10 +19,14 @@ use std::{
 use yew::prelude::*;
 use yew_router::prelude::*;
 
+const PAGE_SIZE: i64 = 50;
+
 pub struct UsersList {
     api: ApiBridge,
     props: Props,
+    link: ComponentLink<Self>,
     users: Option<Rc<RwLock<Vec<User>>>>,
+    pager: Pager<UserSort>,
 }
 
 #[derive(Clone, PartialEq, Properties)]
@@ -33,6 +37,10 @@ pub struct Props {
 
 pub enum Message {
     WsMessage(AgentResponse),
+    Search(String),
+    Sort(UserSort),
+    PreviousPage,
+    NextPage,
 }
 
 impl Component for UsersList {
@@ -44,7 +52,9 @@ impl Component for UsersList {
         Self {
             props,
             api,
+            link,
             users: None,
+            pager: Pager::new(UserSort::Id, PAGE_SIZE),
         }
     }
 
@@ -53,8 +63,9 @@ impl Component for UsersList {
             Message::WsMessage(agent_response) => match agent_response {
                 AgentResponse::Response(ws_response) => match ws_response {
                     NcogResponse::IAM(iam_response) => match iam_response {
-                        IAMResponse::UsersList(users) => {
-                            self.users = Some(Rc::new(RwLock::new(users)));
+                        IAMResponse::UsersList(page) => {
+                            self.pager.received(&page);
+                            self.users = Some(Rc::new(RwLock::new(page.entities)));
                             true
                         }
                         _ => false,
@@ -63,6 +74,28 @@ impl Component for UsersList {
                 },
                 _ => false,
             },
+            Message::Search(search) => {
+                self.pager.search(search);
+                self.initialize();
+                true
+            }
+            Message::Sort(sort) => {
+                self.pager.sort_by(sort);
+                self.initialize();
+                true
+            }
+            Message::PreviousPage => {
+                if self.pager.previous_page() {
+                    self.initialize();
+                }
+                true
+            }
+            Message::NextPage => {
+                if self.pager.next_page() {
+                    self.initialize();
+                }
+                true
+            }
         }
     }
 
@@ -74,11 +107,21 @@ impl Component for UsersList {
 
     fn view(&self) -> Html {
         require_permission!(&self.props.user, users_list_claim());
+        require_permission!(&self.props.user, users_read_claim(None));
         html!(
             <div class="container">
                 <Title>{localize("list-users")}</Title>
 
-                { standard(self.users.clone()) }
+                <EntityList<User>
+                    header=self.head()
+                    row=standard_row()
+                    entities=self.users.clone()
+                    on_search=self.link.callback(Message::Search)
+                    paging=self.pager.paging(
+                        self.link.callback(|_| Message::PreviousPage),
+                        self.link.callback(|_| Message::NextPage),
+                    )
+                    />
             </div>
         )
     }
@@ -96,19 +139,27 @@ impl UsersList {
     fn initialize(&mut self) {
         self.api
             .send(AgentMessage::Request(ncog_shared::NcogRequest::IAM(
-                IAMRequest::UsersList,
+                IAMRequest::UsersList(self.pager.query.clone()),
             )))
     }
-}
 
-pub fn standard_head() -> Html {
-    html! {
-        <tr>
-            <td>{ UserFields::Id.localized_name() }</td>
-            <td>{ UserFields::Screenname.localized_name() }</td>
-            <td>{ UserFields::CreatedAt.localized_name() }</td>
-            <td></td>
-        </tr>
+    fn head(&self) -> Html {
+        let sortable = |field: UserFields, sort: UserSort| {
+            sortable_head(
+                field.localized_name(),
+                self.pager.sort_indicator(sort),
+                self.link.callback(move |_| Message::Sort(sort)),
+            )
+        };
+        html! {
+            <tr>
+                { sortable(UserFields::Id, UserSort::Id) }
+                { sortable(UserFields::Login, UserSort::Login) }
+                { sortable(UserFields::DisplayName, UserSort::DisplayName) }
+                { sortable(UserFields::CreatedAt, UserSort::CreatedAt) }
+                <td></td>
+            </tr>
+        }
     }
 }
 
@@ -117,7 +168,8 @@ pub fn standard_row() -> EntityRenderer<User> {
         html! {
             <tr>
                 <td>{ user.id.unwrap() }</td>
-                <td>{ user.screenname.as_ref().unwrap_or(&localize_raw("not-set"))}</td>
+                <td>{ user.login.as_ref().unwrap_or(&localize_raw("not-set"))}</td>
+                <td>{ user.display_name.as_ref().unwrap_or(&localize_raw("not-set"))}</td>
                 <td>{ user.created_at }</td>
                 <td>
                     <RouterButton<AppRoute> route=AppRoute::BackOfficeUserEdit(EditingId::Id(user.id.unwrap())) classes="button is-primary" >
@@ -128,13 +180,3 @@ pub fn standard_row() -> EntityRenderer<User> {
         }
     })
 }
-
-pub fn standard(entities: Option<Rc<RwLock<Vec<User>>>>) -> Html {
-    html! {
-        <EntityList<User>
-            header=standard_head()
-            row=standard_row()
-            entities=entities
-            />
-    }
-}
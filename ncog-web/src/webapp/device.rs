@@ -0,0 +1,162 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    strings::{localize, localize_raw},
+    AppRoute, LoggedInUser,
+};
+use khonsuweb::prelude::*;
+use ncog_shared::{NcogRequest, NcogResponse};
+use std::sync::Arc;
+use yew::prelude::*;
+use yew_router::prelude::*;
+
+/// Where users enter the code shown by a game client that can't open a
+/// browser, logging it into their account.
+pub struct Device {
+    api: ApiBridge,
+    props: Props,
+    link: ComponentLink<Self>,
+    user_code: FormStorage<Option<String>>,
+    error: Option<String>,
+    is_authorizing: bool,
+    authorized: bool,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    /// The code from the link the client showed, if the user followed it.
+    #[prop_or_default]
+    pub user_code: Option<String>,
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Fields {
+    UserCode,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    ValueChanged,
+    Authorize,
+}
+
+impl Component for Device {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        let user_code = FormStorage::new(props.user_code.clone());
+        Self {
+            api,
+            props,
+            link,
+            user_code,
+            error: None,
+            is_authorizing: false,
+            authorized: false,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::DeviceAuthorized => {
+                        self.authorized = true;
+                        self.is_authorizing = false;
+                        true
+                    }
+                    NcogResponse::Error { message } => {
+                        if self.is_authorizing {
+                            self.error = message;
+                            self.is_authorizing = false;
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::ValueChanged => true,
+            Message::Authorize => {
+                if let Some(user_code) = self.user_code.unchecked_value() {
+                    self.api
+                        .send(AgentMessage::Request(NcogRequest::AuthorizeDevice {
+                            user_code,
+                        }));
+                    self.error = None;
+                    self.is_authorizing = true;
+                }
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        if self.props.user_code != props.user_code {
+            self.user_code.update(props.user_code.clone());
+            self.authorized = false;
+            self.error = None;
+        }
+        self.props = props;
+        true
+    }
+
+    fn view(&self) -> Html {
+        let content = if self.authorized {
+            html! {
+                <p class="notification is-success is-light">{ localize("device-authorized") }</p>
+            }
+        } else if self.props.user.is_none() {
+            html! {
+                <>
+                    <p>{ localize("device-requires-login") }</p>
+                    <RouterButton<AppRoute> route=AppRoute::LogIn classes="button is-primary">
+                        <strong>{ localize_raw("log-in") }</strong>
+                    </RouterButton<AppRoute>>
+                </>
+            }
+        } else {
+            let error = match &self.error {
+                Some(error) => html! {
+                    <p class="notification is-danger is-light">{ error }</p>
+                },
+                None => Html::default(),
+            };
+            html! {
+                <form>
+                    { error }
+                    <p>{ localize("device-intro") }</p>
+                    <Field<Fields> field=Fields::UserCode errors=None>
+                        <Label text=localize_raw("device-user-code") />
+                        <TextInput<Fields, String> field=Fields::UserCode storage=self.user_code.clone() readonly=self.is_authorizing on_value_changed=self.link.callback(|_| Message::ValueChanged) placeholder="XXXX-XXXX" errors=None />
+                    </Field<Fields>>
+                    <Button
+                        label=localize_raw("device-authorize")
+                        css_class="is-primary"
+                        disabled=self.is_authorizing || self.user_code.unchecked_value().is_none()
+                        action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::Authorize})
+                        processing=self.is_authorizing
+                    />
+                </form>
+            }
+        };
+
+        html! {
+            <div class="columns is-centered">
+                <div class="column is-half">
+                    <Title>{ localize_raw("device") }</Title>
+                    { content }
+                </div>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, _first_render: bool) {
+        self.props.set_title.emit(localize_raw("device"));
+    }
+}
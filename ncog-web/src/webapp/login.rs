@@ -1,9 +1,11 @@
 use crate::webapp::{
     api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
     strings::{localize, localize_raw},
+    AppRoute,
 };
-use ncog_shared::{NcogRequest, OAuthProvider};
+use ncog_shared::{LoginFailure, NcogRequest};
 use yew::prelude::*;
+use yew_router::prelude::*;
 
 pub struct Login {
     link: ComponentLink<Self>,
@@ -11,7 +13,7 @@ pub struct Login {
     current_storage_status: bool,
 }
 pub enum Message {
-    LogInWith(OAuthProvider),
+    LogInWith(&'static str),
     ApiMessage(AgentResponse),
     ToggleStatus,
 }
@@ -34,7 +36,7 @@ impl Component for Login {
             Message::LogInWith(provider) => {
                 self.api
                     .send(AgentMessage::Request(NcogRequest::AuthenticationUrl(
-                        provider,
+                        provider.to_owned(),
                     )));
                 false
             }
@@ -75,7 +77,7 @@ impl Component for Login {
                             {localize_raw("i-agree")}
                         </label>
                     </div>
-                    <button class="button twitch-button" disabled=!self.current_storage_status onclick=self.link.callback(|_| Message::LogInWith(OAuthProvider::Twitch))>
+                    <button class="button twitch-button" disabled=!self.current_storage_status onclick=self.link.callback(|_| Message::LogInWith("twitch"))>
                         {localize("log-in-with-twitch")}
                     </button>
                 </div>
@@ -83,3 +85,27 @@ impl Component for Login {
         )
     }
 }
+
+/// Explains why logging in with a provider failed, based on the
+/// `LoginFailure` code the server redirected to.
+pub fn login_failed(code: &str) -> Html {
+    let message = match LoginFailure::from_code(code) {
+        Some(LoginFailure::Denied) => "login-failed-denied",
+        Some(LoginFailure::InvalidState) => "login-failed-invalid-state",
+        Some(LoginFailure::Provider) => "login-failed-provider",
+        Some(LoginFailure::IdentityInUse) => "login-failed-identity-in-use",
+        Some(LoginFailure::UnknownProvider) => "login-failed-unknown-provider",
+        Some(LoginFailure::ServerError) | None => "login-failed-server-error",
+    };
+    html! {
+        <div class="login columns is-centered">
+            <div class="column is-half">
+                <h1>{localize("login-failed")}</h1>
+                <p class="notification is-danger is-light">{localize(message)}</p>
+                <RouterButton<AppRoute> route=AppRoute::LogIn classes="button is-primary">
+                    {localize_raw("try-again")}
+                </RouterButton<AppRoute>>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,204 @@
+use crate::webapp::{
+    api::{AgentMessage, AgentResponse, ApiAgent, ApiBridge},
+    strings::localize,
+    LoggedInUser,
+};
+use khonsuweb::prelude::*;
+use ncog_shared::{LinkedIdentity, NcogRequest, NcogResponse};
+use std::{sync::Arc, time::Duration};
+use yew::prelude::*;
+
+pub struct Profile {
+    api: ApiBridge,
+    props: Props,
+    link: ComponentLink<Self>,
+    identities: Option<Vec<LinkedIdentity>>,
+    is_loading: bool,
+    flash_message: Option<flash::Message>,
+}
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct Props {
+    pub user: Option<Arc<LoggedInUser>>,
+    pub set_title: Callback<String>,
+}
+
+pub enum Message {
+    WsMessage(AgentResponse),
+    Link(&'static str),
+    Unlink(LinkedIdentity),
+    RevokeIdentityTokens,
+}
+
+impl Component for Profile {
+    type Message = Message;
+    type Properties = Props;
+    fn create(props: Self::Properties, link: ComponentLink<Self>) -> Self {
+        let callback = link.callback(Message::WsMessage);
+        let api = ApiAgent::bridge(callback);
+        Self {
+            api,
+            props,
+            link,
+            identities: None,
+            is_loading: false,
+            flash_message: None,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        match msg {
+            Message::WsMessage(agent_response) => match agent_response {
+                AgentResponse::Response(ws_response) => match ws_response {
+                    NcogResponse::LinkedIdentities(identities) => {
+                        self.identities = Some(identities);
+                        self.is_loading = false;
+                        true
+                    }
+                    NcogResponse::IdentityTokensRevoked => {
+                        self.flash_message = Some(flash::Message::new(
+                            flash::Kind::Success,
+                            localize!("identity-tokens-revoked"),
+                            Duration::from_secs(3),
+                        ));
+                        self.is_loading = false;
+                        true
+                    }
+                    NcogResponse::Error { message } => {
+                        if let Some(message) = message {
+                            self.flash_message = Some(flash::Message::new(
+                                flash::Kind::Danger,
+                                message,
+                                Duration::from_secs(3),
+                            ));
+                        }
+                        self.is_loading = false;
+                        true
+                    }
+                    _ => false,
+                },
+                _ => false,
+            },
+            Message::Link(provider) => {
+                self.api
+                    .send(AgentMessage::Request(NcogRequest::LinkIdentityUrl(
+                        provider.to_owned(),
+                    )));
+                self.is_loading = true;
+                true
+            }
+            Message::RevokeIdentityTokens => {
+                self.api
+                    .send(AgentMessage::Request(NcogRequest::RevokeIdentityTokens));
+                self.is_loading = true;
+                true
+            }
+            Message::Unlink(identity) => {
+                self.api
+                    .send(AgentMessage::Request(NcogRequest::UnlinkIdentity {
+                        provider: identity.provider,
+                        external_id: identity.external_id,
+                    }));
+                self.is_loading = true;
+                true
+            }
+        }
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        let logged_in = self.props.user.is_none() && props.user.is_some();
+        self.props = props;
+        // The page can load before the connection is authenticated, such as
+        // when returning from linking an identity.
+        if logged_in {
+            self.load_identities();
+        }
+        true
+    }
+
+    fn view(&self) -> Html {
+        let user = match &self.props.user {
+            Some(user) => user,
+            None => return localize("profile-requires-login"),
+        };
+
+        let identities = match &self.identities {
+            Some(identities) => {
+                let can_unlink = identities.len() > 1 && !self.is_loading;
+                html! {
+                    <table class="table is-hoverable is-striped">
+                        <tr>
+                            <td>{ localize!("identity-provider") }</td>
+                            <td>{ localize!("identity-login") }</td>
+                            <td>{ localize!("identity-linked-at") }</td>
+                            <td></td>
+                        </tr>
+                        <tbody>
+                            { identities.iter().map(|identity| self.render_identity(identity, can_unlink)).collect::<Html>() }
+                        </tbody>
+                    </table>
+                }
+            }
+            None => html! {
+                <progress class="progress is-primary" max="100"/>
+            },
+        };
+
+        html! {
+            <div class="columns is-centered">
+                <div class="column is-half">
+                    <Title>{ user.profile.display_name.clone().unwrap_or_default() }</Title>
+                    <flash::Flash message=self.flash_message.clone() />
+                    <Title size=4>{ localize!("linked-identities") }</Title>
+                    <p>{ localize("linked-identities-intro") }</p>
+                    { identities }
+                    <button class="button twitch-button" disabled=self.is_loading onclick=self.link.callback(|_| Message::Link("twitch"))>
+                        { localize("link-twitch") }
+                    </button>
+                    <Title size=4>{ localize!("identity-tokens") }</Title>
+                    <p>{ localize("identity-tokens-intro") }</p>
+                    <Button
+                        label=localize!("revoke-identity-tokens")
+                        css_class="is-danger"
+                        disabled=self.is_loading
+                        action=self.link.callback(|e: MouseEvent| {e.prevent_default(); Message::RevokeIdentityTokens})
+                    />
+                </div>
+            </div>
+        }
+    }
+
+    fn rendered(&mut self, first_render: bool) {
+        if first_render && self.props.user.is_some() {
+            self.load_identities();
+        }
+        self.props.set_title.emit(localize!("profile"));
+    }
+}
+
+impl Profile {
+    fn load_identities(&mut self) {
+        self.api
+            .send(AgentMessage::Request(NcogRequest::ListLinkedIdentities));
+        self.is_loading = true;
+    }
+
+    fn render_identity(&self, identity: &LinkedIdentity, can_unlink: bool) -> Html {
+        let unlinked = identity.clone();
+        html! {
+            <tr>
+                <td>{ &identity.provider }</td>
+                <td>{ identity.display_name.as_ref().or_else(|| identity.login.as_ref()).cloned().unwrap_or_else(|| identity.external_id.clone()) }</td>
+                <td>{ identity.linked_at.to_rfc2822() }</td>
+                <td>
+                    <Button
+                        label=localize!("unlink")
+                        css_class="is-danger is-small"
+                        disabled=!can_unlink
+                        action=self.link.callback(move |e: MouseEvent| {e.prevent_default(); Message::Unlink(unlinked.clone())})
+                    />
+                </td>
+            </tr>
+        }
+    }
+}